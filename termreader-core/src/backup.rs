@@ -0,0 +1,355 @@
+// This module contains backups of the data directory, as single archives that can be restored later.
+
+use crate::{
+    lock,
+    profile::PROFILE_DIR,
+    storage::{self, StorageBackend},
+    Context, TRError,
+};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+/// The directory within the data directory that backups are stored in
+const BACKUP_DIR: &str = "backups";
+/// The directory within the backup directory that automatic backups are stored in
+const AUTO_BACKUP_DIR: &str = "auto";
+/// The directory within the backup directory that archives are extracted to while being restored
+const RESTORE_DIR: &str = ".restore";
+const ARCHIVE_EXTENSION: &str = "zip";
+
+/// The amount of automatic backups kept by default
+pub const DEFAULT_AUTO_BACKUPS: usize = 5;
+/// How long after an automatic backup the next one is taken. Saves happen every few minutes while reading,
+/// so backing up on each one would mean the kept backups only reach back a few minutes
+const AUTO_BACKUP_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// How the data in a backup should be combined with the current data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Discard all current data, replacing it with the backup
+    Replace,
+    /// Add anything in the backup that's missing, combining reading progress for books that exist in both
+    Merge,
+}
+
+impl Context {
+    /// Returns the directory that backups are stored in by default
+    pub fn get_backup_dir(&self) -> PathBuf {
+        self.data_path.join(BACKUP_DIR)
+    }
+
+    /// Set how many automatic backups are kept. Automatic backups are disabled when this is 0.
+    /// At most one automatic backup is taken every 6 hours
+    pub fn set_auto_backup_count(&mut self, count: usize) {
+        self.auto_backups = count;
+    }
+
+    /// Saves, then writes a backup of everything in the data directory to a timestamped archive in the given directory.
+    ///
+    /// This includes all books, the library, history, updates, and any other files such as the config.
+    /// Returns the path of the archive
+    pub fn create_backup(&mut self, dir: &Path) -> Result<PathBuf, TRError> {
+        self.save()?;
+        write_archive(&self.data_path, dir)
+    }
+
+    /// Returns the paths of all backups in the backup directory, including automatic backups, newest first
+    pub fn list_backups(&self) -> Result<Vec<PathBuf>, TRError> {
+        let dir = self.get_backup_dir();
+        let mut backups = list_archives(&dir)?;
+        backups.append(&mut list_archives(&dir.join(AUTO_BACKUP_DIR))?);
+        // Archives are named by the time they were created
+        backups.sort_by_key(|p| std::cmp::Reverse(p.file_name().map(|n| n.to_os_string())));
+        Ok(backups)
+    }
+
+    /// Restores a backup created by `Context::create_backup`, then saves.
+    ///
+    /// When replacing, files other than the save data (such as the config) are restored too,
+    /// and should be reloaded by the caller
+    pub fn restore_backup(&mut self, archive: &Path, mode: ImportMode) -> Result<(), TRError> {
+        let dir = self.get_backup_dir().join(RESTORE_DIR);
+        // This may be left over from a restore that was interrupted
+        let _ = fs::remove_dir_all(&dir);
+
+        let res = self.restore_from(archive, &dir, mode);
+        let _ = fs::remove_dir_all(&dir);
+        res?;
+        self.save()
+    }
+
+    fn restore_from(
+        &mut self,
+        archive: &Path,
+        dir: &Path,
+        mode: ImportMode,
+    ) -> Result<(), TRError> {
+        let mut zip = ZipArchive::new(File::open(archive)?)?;
+        if !zip
+            .file_names()
+            .any(|name| storage::is_storage_file(Path::new(name)))
+        {
+            return Err(TRError::InvalidArgument(String::from(
+                "the archive is not a termreader backup",
+            )));
+        }
+        zip.extract(dir)?;
+
+        let data = {
+            let mut storage = StorageBackend::detect(dir).open(dir)?;
+            let data = storage.load()?;
+            // Nothing can be loaded on demand once the extracted files are removed
+            for book in data.books.books.values() {
+                book.write().load_chapter_data()?;
+            }
+            data
+        };
+
+        match mode {
+            ImportMode::Replace => {
+                self.books = data.books;
+                self.library = data.library;
+                self.history = data.history;
+                self.updates = data.updates;
+                self.journal.clear();
+                for file in list_files(dir)? {
+                    if !storage::is_storage_file(&file) {
+                        let dest = self.data_path.join(&file);
+                        if let Some(parent) = dest.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        fs::copy(dir.join(&file), dest)?;
+                    }
+                }
+            }
+            ImportMode::Merge => self.merge_data(data),
+        }
+        Ok(())
+    }
+
+    /// Takes an automatic backup of the data currently on disk, unless the newest was taken recently, removing the
+    /// oldest automatic backups so that no more than the configured amount are kept
+    pub(crate) fn auto_backup(&self) -> Result<(), TRError> {
+        if self.auto_backups == 0 {
+            return Ok(());
+        }
+        // There's nothing to back up before the first save
+        if !list_files(&self.data_path)?
+            .iter()
+            .any(|f| storage::is_storage_file(f))
+        {
+            return Ok(());
+        }
+
+        let dir = self.get_backup_dir().join(AUTO_BACKUP_DIR);
+        if let Some(newest) = list_archives(&dir)?.last() {
+            // A backup from the future means the clock has gone back, so its age can't be trusted
+            let modified = fs::metadata(newest)?.modified()?;
+            if modified
+                .elapsed()
+                .is_ok_and(|age| age < AUTO_BACKUP_INTERVAL)
+            {
+                return Ok(());
+            }
+        }
+        write_archive(&self.data_path, &dir)?;
+
+        let archives = list_archives(&dir)?;
+        let excess = archives.len().saturating_sub(self.auto_backups);
+        // Archives are sorted oldest first
+        for archive in archives.into_iter().take(excess) {
+            fs::remove_file(archive)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes every file in the data directory to a new archive in the given directory, returning its path
+fn write_archive(data_path: &Path, dir: &Path) -> Result<PathBuf, TRError> {
+    fs::create_dir_all(dir)?;
+    let name = format!(
+        "termreader-{}.{}",
+        chrono::Local::now().format("%Y-%m-%d_%H-%M-%S%.3f"),
+        ARCHIVE_EXTENSION
+    );
+    let path = dir.join(name);
+    // Write to a temporary file first, so that an incomplete archive is never mistaken for a backup
+    let tmp = path.with_extension("tmp");
+
+    let mut zip = ZipWriter::new(File::create(&tmp)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for file in list_files(data_path)? {
+        // Archives always use forward slashes
+        let name = file
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        zip.start_file(name, options)?;
+        io::copy(&mut File::open(data_path.join(&file))?, &mut zip)?;
+    }
+    let mut file = zip.finish()?;
+    file.flush()?;
+    file.sync_all()?;
+    fs::rename(&tmp, &path)?;
+    Ok(path)
+}
+
+/// Lists all files that should be backed up in the data directory, relative to it
+fn list_files(data_path: &Path) -> Result<Vec<PathBuf>, TRError> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(rel) = dirs.pop() {
+        let entries = match fs::read_dir(data_path.join(&rel)) {
+            Ok(e) => e,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let path = rel.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                // Backups shouldn't contain other backups, or other profiles
+                if path != Path::new(BACKUP_DIR) && path != Path::new(PROFILE_DIR) {
+                    dirs.push(path);
+                }
+            } else if !path
+                .extension()
+                .is_some_and(|ext| ext == "tmp" || ext == "log")
+                && !lock::is_lock_file(&path)
+            {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Lists the archives in a directory, oldest first
+fn list_archives(dir: &Path) -> Result<Vec<PathBuf>, TRError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut archives = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == ARCHIVE_EXTENSION) {
+            archives.push(path);
+        }
+    }
+    // Archives are named by the time they were created
+    archives.sort();
+    Ok(archives)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::ChapterProgress;
+    use crate::testing::{add_library_book, TestDir};
+
+    #[test]
+    fn backup_then_restore() {
+        let dir = TestDir::new("backup-restore");
+        let mut ctx = dir.open();
+        ctx.set_auto_backup_count(0);
+        let id = add_library_book(&mut ctx, "Book", 2);
+        fs::create_dir_all(dir.path()).unwrap();
+        fs::write(dir.path().join("config.json"), "{}").unwrap();
+
+        let archive = ctx.create_backup(&ctx.get_backup_dir()).unwrap();
+        assert_eq!(ctx.list_backups().unwrap(), vec![archive.clone()]);
+
+        // Merging a backup of the same data changes nothing
+        ctx.restore_backup(&archive, ImportMode::Merge).unwrap();
+        assert_eq!(ctx.get_library_books()["Default"].len(), 1);
+
+        ctx.remove_from_lib(id);
+        fs::remove_file(dir.path().join("config.json")).unwrap();
+        ctx.restore_backup(&archive, ImportMode::Replace).unwrap();
+        assert!(ctx.get_book(id).unwrap().in_library());
+        assert!(dir.path().join("config.json").exists());
+    }
+
+    #[test]
+    fn merge_combines_progress() {
+        let dir = TestDir::new("backup-merge");
+        let mut ctx = dir.open();
+        ctx.set_auto_backup_count(0);
+        let id = add_library_book(&mut ctx, "Book", 2);
+        ctx.get_book(id)
+            .unwrap()
+            .global_set_progress(ChapterProgress::Finished, 1)
+            .unwrap();
+        let archive = ctx.create_backup(&ctx.get_backup_dir()).unwrap();
+
+        let mut book = ctx.get_book(id).unwrap();
+        book.reset_progress();
+        book.global_set_progress(ChapterProgress::Finished, 2)
+            .unwrap();
+        ctx.restore_backup(&archive, ImportMode::Merge).unwrap();
+
+        let progress = ctx.get_book(id).unwrap().get_all_chapter_progress();
+        assert_eq!(progress.get(&1), Some(&ChapterProgress::Finished));
+        assert_eq!(progress.get(&2), Some(&ChapterProgress::Finished));
+    }
+
+    /// Makes the newest automatic backup look old enough for the next save to take another
+    fn age_newest_backup(dir: &Path) {
+        let dir = dir.join(BACKUP_DIR).join(AUTO_BACKUP_DIR);
+        let Some(newest) = list_archives(&dir).unwrap().pop() else {
+            return;
+        };
+        let time = std::time::SystemTime::now() - AUTO_BACKUP_INTERVAL;
+        File::options()
+            .write(true)
+            .open(newest)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn auto_backups_are_rotated() {
+        let dir = TestDir::new("backup-rotate");
+        let mut ctx = dir.open();
+        ctx.set_auto_backup_count(2);
+        for _ in 0..4 {
+            ctx.save().unwrap();
+            age_newest_backup(dir.path());
+            // Archives are named to the millisecond
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        // The first save had nothing to back up
+        assert_eq!(
+            list_archives(&dir.path().join(BACKUP_DIR).join(AUTO_BACKUP_DIR))
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn auto_backups_are_taken_every_few_hours() {
+        let dir = TestDir::new("backup-throttle");
+        let mut ctx = dir.open();
+        ctx.set_auto_backup_count(5);
+        for _ in 0..4 {
+            ctx.save().unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        assert_eq!(
+            list_archives(&dir.path().join(BACKUP_DIR).join(AUTO_BACKUP_DIR))
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -1,104 +1,211 @@
+use crate::bookmarks::{renumber_bookmarks, Bookmark};
+use crate::highlights::{renumber_highlights, Highlight};
+use crate::storage::ChapterSource;
 use crate::TRError;
-use crate::{id::ID, updates::UpdatedChapters, Context};
+use crate::{
+    id::ID,
+    updates::{diff_chapters, ChapterDiff},
+    Context,
+};
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
 use std::path::Path;
-use std::{collections::HashMap, rc::Rc};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use termreader_sources::{
     chapter::ChapterPreview,
-    novel::Novel,
+    novel::{Novel, NovelStatus},
     sources::{Scrape, Source, SourceID},
 };
 
-/// A reference to a `Book`
+/// A reference to a `Book`.
+///
+/// `BookRef`s can be sent to other threads, e.g. to update a book in the background. Changes made through any
+/// `BookRef` are seen by every other reference to the same book
 #[derive(Serialize, Deserialize, Debug)]
-pub struct BookRef(pub(crate) Rc<RefCell<Book>>);
+pub struct BookRef(pub(crate) Arc<RwLock<Book>>);
 
 impl Clone for BookRef {
     /// Clones the `BookRef`. This is a shallow clone
     fn clone(&self) -> Self {
-        BookRef(Rc::clone(&self.0))
+        BookRef(Arc::clone(&self.0))
     }
 }
 
 impl BookRef {
+    pub(crate) fn new(book: Book) -> Self {
+        BookRef(Arc::new(RwLock::new(book)))
+    }
+
+    /// Locks the `Book` for reading.
+    ///
+    /// The lock must not be held while also locking the same book for writing, or the thread deadlocks.
+    /// A lock poisoned by a panic in another thread is still used, as a half-finished change to a book is
+    /// preferable to losing access to it
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, Book> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the `Book` for writing. See `BookRef::read`
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, Book> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
     /// Returns the ID of the `Book`
     pub fn get_id(&self) -> ID {
-        self.0.borrow().get_id()
+        self.read().get_id()
     }
 
     /// Returns the name of the `Book`
     pub fn get_name(&self) -> String {
-        self.0.borrow().get_name().to_string()
+        self.read().get_name().to_string()
     }
 
     /// Returns true if the `Book` is in the library
     pub fn in_library(&self) -> bool {
-        self.0.borrow().in_library
+        self.read().in_library
     }
 
     /// Get the url (just the book-specific part) of the referenced `Book`
     ///
     /// Returns `None` if the book is locally sourced
     pub fn get_url(&self) -> Option<String> {
-        Some(self.0.borrow().get_url()?.to_string())
+        Some(self.read().get_url()?.to_string())
     }
 
     /// Get the url for a specific chapter of the referenced `Book`
     ///
     /// Returns `None` if the book is locally sourced
     pub fn get_chapter_url(&self, chapter: usize) -> Option<String> {
-        Some(self.0.borrow().get_chapter_url(chapter)?.to_string())
+        self.ensure_chapters_loaded();
+        Some(self.read().get_chapter_url(chapter)?.to_string())
+    }
+
+    /// Loads the chapter list and chapter progress of the `Book` if they aren't yet in memory.
+    ///
+    /// These are loaded on demand, as they make up the bulk of the save data.
+    /// If loading fails, the book is left unloaded and the error is logged.
+    fn ensure_chapters_loaded(&self) {
+        if self.read().chapters_loaded() {
+            return;
+        }
+        // The result is taken first so that the book isn't still locked when its ID is read
+        let res = self.write().load_chapter_data();
+        if let Err(e) = res {
+            tracing::error!(
+                "failed to load chapters for book {:?}: {}",
+                self.get_id(),
+                e
+            );
+        }
+    }
+
+    /// Fetches the latest details and chapter list of the referenced `Book` from its source, then updates it.
+    ///
+    /// The book isn't locked while fetching, so this can be run in a background thread while the book is in use.
+    /// Locally sourced books are never updated.
+    pub fn update(&self, source: &Source) -> Result<ChapterDiff, TRError> {
+        let Some(url) = self.get_url() else {
+            return Ok(ChapterDiff::unchanged());
+        };
+        let novel = source
+            .parse_novel_and_chapters(url)
+            .map_err(|e| TRError::SourceFailure(e.to_string()))?;
+        self.ensure_chapters_loaded();
+        Ok(self.write().apply_update(novel))
+    }
+
+    /// Returns the chapters past the last chapter of the referenced `Book` that have progress recorded.
+    /// Locally sourced books never have any
+    pub(crate) fn get_progress_past_end(&self) -> Vec<usize> {
+        self.ensure_chapters_loaded();
+        match &self.read().data {
+            BookData::Global(d) => d.progress_past_end(),
+            BookData::Local(_) => Vec::new(),
+        }
+    }
+
+    /// Removes any progress for chapters past the last chapter of the referenced `Book`
+    pub(crate) fn remove_progress_past_end(&self) {
+        self.ensure_chapters_loaded();
+        if let BookData::Global(d) = &mut self.write().data {
+            let total = d.total_chapters;
+            d.chapter_progress.retain(|&ch, _| ch <= total);
+            d.chapters_read_ordered = d.chapters_read_ordered.min(total);
+        }
+    }
+
+    /// Combines the reading progress of another copy of this book into the referenced `Book`.
+    ///
+    /// See `Book::merge_progress`
+    pub(crate) fn merge_progress(&self, other: &Book) {
+        self.ensure_chapters_loaded();
+        self.write().merge_progress(other)
+    }
+
+    /// See `Book::get_progress_snapshot`
+    pub(crate) fn get_progress_snapshot(&self) -> ProgressSnapshot {
+        self.ensure_chapters_loaded();
+        self.read().get_progress_snapshot()
+    }
+
+    /// See `Book::merge_progress_snapshot`
+    pub(crate) fn merge_progress_snapshot(&self, progress: &ProgressSnapshot) {
+        self.ensure_chapters_loaded();
+        self.write().merge_progress_snapshot(progress)
     }
 
     /// Get the full url (website and book parts) of the referenced `Book`
     ///
     /// Returns `None` if the book is locally sourced
     pub fn get_full_url(&self) -> Option<String> {
-        Some(self.0.borrow().get_full_url()?.to_string())
+        Some(self.read().get_full_url()?.to_string())
     }
 
     /// Renames the referenced `Book`
     pub fn rename(&mut self, new_name: String) {
-        self.0.borrow_mut().rename(new_name.clone());
+        self.write().rename(new_name.clone());
     }
 
     /// Resets all progress related to the `Book`
     pub fn reset_progress(&mut self) {
-        self.0.borrow_mut().reset_progress()
+        self.ensure_chapters_loaded();
+        self.write().reset_progress()
     }
 
     /// Returns true if a book is locally sourced
     pub fn is_local(&self) -> bool {
-        self.0.borrow().is_local()
+        self.read().is_local()
     }
 
     /// Returns true if a book is not locally sourced
     pub fn is_global(&self) -> bool {
-        self.0.borrow().is_global()
+        self.read().is_global()
     }
 
     /// Returns the progress for the chapter currently being read
     pub fn get_current_ch_progress(&self) -> ChapterProgress {
-        self.0.borrow().get_current_ch_progress()
+        self.ensure_chapters_loaded();
+        self.read().get_current_ch_progress()
     }
 
     pub fn get_all_chapter_progress(&self) -> HashMap<usize, ChapterProgress> {
-        self.0.borrow().get_all_ch_progress()
+        self.ensure_chapters_loaded();
+        self.read().get_all_ch_progress()
     }
 
     /// Returns the current chapter
     ///
     /// Returns `None` when called on a locally sourced book
     pub fn get_current_ch(&self) -> Option<usize> {
-        self.0.borrow().get_current_ch()
+        self.read().get_current_ch()
     }
 
     /// Returns the total chapter count
     ///
     /// Returns `None` when called on a locally sourced book
     pub fn get_total_ch_count(&self) -> Option<usize> {
-        self.0.borrow().get_total_chs()
+        self.read().get_total_chs()
     }
 
     /// Sets the progress for a chapter of a global book
@@ -109,14 +216,16 @@ impl BookRef {
         progress: ChapterProgress,
         chapter: usize,
     ) -> Result<(), TRError> {
-        self.0.borrow_mut().global_set_progress(progress, chapter)
+        self.ensure_chapters_loaded();
+        self.write().global_set_progress(progress, chapter)
     }
 
     /// Marks a chapter as read for a global book
     ///
     /// Errors when called on a locally sourced book
     pub fn global_mark_ch_read(&mut self, chapter: usize) -> Result<(), TRError> {
-        self.0.borrow_mut().global_mark_ch_read(chapter)
+        self.ensure_chapters_loaded();
+        self.write().global_mark_ch_read(chapter)
     }
 
     /// Sets the current chapter for a global book
@@ -124,8 +233,7 @@ impl BookRef {
     /// Errors when called on a locally sourced book,
     /// or when the set chapter is outside of the chapter range
     pub fn global_set_chapter(&mut self, chapter: usize) -> Result<(), TRError> {
-        let mut b = self.0.borrow_mut();
-
+        let mut b = self.write();
         let total_chapters = b.get_total_chs().ok_or_else(|| {
             TRError::BadUse(String::from(
                 "supplied a local book where a global book should have been supplied",
@@ -135,6 +243,10 @@ impl BookRef {
         if chapter <= total_chapters {
             b.global_set_ch(chapter)
                 .expect("the invariant was checked earlier");
+            // Setting the chapter is opening it, so the book is being read
+            if b.get_reading_status() == ReadingStatus::PlanToRead {
+                b.reading_status = Some(ReadingStatus::Reading);
+            }
             Ok(())
         } else {
             Err(TRError::InvalidArgument(String::from(
@@ -147,7 +259,7 @@ impl BookRef {
     ///
     /// Errors when called on a locally sourced book
     pub fn global_get_ordered_chapters(&self) -> Result<usize, TRError> {
-        self.0.borrow().global_get_ordered_chapters()
+        self.read().global_get_ordered_chapters()
     }
 
     /// Get the chapter that should be read for the book to be read in order
@@ -156,13 +268,14 @@ impl BookRef {
     ///
     /// Returns `None` if a book has no chapters, or is sourced locally
     pub fn global_get_next_ordered_chap(&mut self) -> Option<usize> {
-        self.0.borrow_mut().global_get_next_ordered_chap()
+        self.ensure_chapters_loaded();
+        self.write().global_get_next_ordered_chap()
     }
 
     /// Returns a global novels original name
     pub fn global_get_original_name(&self) -> Result<String, TRError> {
         if self.is_global() {
-            Ok(self.0.borrow().global_get_novel().get_name().to_string())
+            Ok(self.read().global_get_novel().get_name().to_string())
         } else {
             Err(TRError::BadUse(String::from(
                 "supplied a local book where a global book should have been supplied",
@@ -175,7 +288,7 @@ impl BookRef {
         if self.is_local() {
             unimplemented!()
         } else {
-            self.0.borrow().global_get_novel().get_synopsis()
+            self.read().global_get_novel().get_synopsis()
         }
     }
 
@@ -183,7 +296,8 @@ impl BookRef {
     ///
     /// Errors when called on a locally sourced book
     pub fn get_chapters(&self) -> Result<Vec<ChapterPreview>, TRError> {
-        match self.0.borrow().get_chapters() {
+        self.ensure_chapters_loaded();
+        match self.read().get_chapters() {
             Some(chs) => Ok(chs.clone()),
             None => Err(TRError::BadUse(String::from(
                 "supplied a local book where a global book should have been supplied",
@@ -193,12 +307,44 @@ impl BookRef {
 
     /// Get a copy of the `Book` that is referenced
     pub fn get_book(self) -> Book {
-        self.0.borrow().clone()
+        self.ensure_chapters_loaded();
+        self.read().clone()
+    }
+
+    /// Returns the tags of the referenced `Book`, in alphabetical order
+    pub fn get_tags(&self) -> Vec<String> {
+        self.read().tags.iter().cloned().collect()
+    }
+
+    /// Returns true if the referenced `Book` has every one of the tags
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        let book = self.read();
+        tags.iter().all(|t| book.tags.contains(t))
+    }
+
+    /// Returns the bookmarks in the referenced `Book`, ordered by their position in it
+    pub fn get_bookmarks(&self) -> Vec<Bookmark> {
+        self.read().bookmarks.clone()
+    }
+
+    /// Returns the highlights in the referenced `Book`, ordered by their position in it
+    pub fn get_highlights(&self) -> Vec<Highlight> {
+        self.read().highlights.clone()
+    }
+
+    /// Returns the reading status of the referenced `Book`. See `Book::get_reading_status`
+    pub fn get_reading_status(&self) -> ReadingStatus {
+        self.read().get_reading_status()
+    }
+
+    /// Sets the reading status of the referenced `Book`
+    pub fn set_reading_status(&self, status: ReadingStatus) {
+        self.write().reading_status = Some(status);
     }
 
     // TODO: Remove this function as it shouldn't be implemented in core
     pub fn get_display_info(&self) -> String {
-        self.0.borrow().display_info()
+        self.read().display_info()
     }
 }
 
@@ -211,6 +357,22 @@ pub struct Book {
     pub(crate) in_history: bool,
     pub(crate) in_updates: bool,
     pub(crate) category: Option<String>,
+    /// Free-form tags, in addition to the book's category
+    #[serde(default)]
+    pub(crate) tags: BTreeSet<String>,
+    /// When the book was added to the library, in seconds since the UNIX epoch.
+    /// 0 for books added before this was recorded
+    #[serde(default)]
+    pub(crate) added_to_library: u64,
+    /// Set by the user, or by reading the book. Worked out from the progress if it's never been set
+    #[serde(default)]
+    pub(crate) reading_status: Option<ReadingStatus>,
+    /// Ordered by position in the book
+    #[serde(default)]
+    pub(crate) bookmarks: Vec<Bookmark>,
+    /// Ordered by position in the book
+    #[serde(default)]
+    pub(crate) highlights: Vec<Highlight>,
 }
 
 impl PartialEq for Book {
@@ -246,10 +408,15 @@ impl Book {
 
     pub fn from_novel(novel: Novel) -> Self {
         Self {
-            id: ID::generate(),
+            id: global_id(&novel),
             name: novel.get_name().to_string(),
             data: BookData::Global(GlobalData::from_novel(novel)),
             category: None,
+            tags: BTreeSet::new(),
+            added_to_library: 0,
+            reading_status: None,
+            bookmarks: Vec::new(),
+            highlights: Vec::new(),
             in_library: false,
             in_history: false,
             in_updates: false,
@@ -258,23 +425,43 @@ impl Book {
 
     pub fn from_local_source(path: String) -> Result<Self, TRError> {
         let p = Path::new(&path);
-        let id = ID::generate();
         let name = p.file_stem().unwrap().to_str().unwrap().to_string();
         let category = None;
 
-        let data = BookData::Local(LocalData::from_path(path)?);
+        let local = LocalData::from_path(path)?;
+        let id = local_id(&local.hash);
+        let data = BookData::Local(local);
 
         Ok(Self {
             id,
             name,
             data,
             category,
+            tags: BTreeSet::new(),
+            added_to_library: 0,
+            reading_status: None,
+            bookmarks: Vec::new(),
+            highlights: Vec::new(),
             in_library: false,
             in_history: false,
             in_updates: false,
         })
     }
 
+    /// Returns the ID the book should have. Global books are identified by their source and URL, and local books by
+    /// the hash of their file. Books added before IDs were derived this way may have a different ID until
+    /// `Context::merge_duplicates` is run
+    pub(crate) fn stable_id(&self) -> ID {
+        match &self.data {
+            BookData::Local(d) => local_id(&d.hash),
+            BookData::Global(d) => global_id(&d.source_novel),
+        }
+    }
+
+    pub(crate) fn set_id(&mut self, id: ID) {
+        self.id = id;
+    }
+
     pub fn is_local(&self) -> bool {
         matches!(self.data, BookData::Local(_))
     }
@@ -320,6 +507,34 @@ impl Book {
         }
     }
 
+    /// Returns the reading status of the book. If it's never been set, it's worked out from the progress:
+    /// books that haven't been read are planned to be read, completed novels that have been read to the end are
+    /// completed, and anything else is being read
+    pub fn get_reading_status(&self) -> ReadingStatus {
+        if let Some(status) = self.reading_status {
+            return status;
+        }
+        match &self.data {
+            BookData::Local(d) if d.progress == ChapterProgress::Location((0, 0)) => {
+                ReadingStatus::PlanToRead
+            }
+            BookData::Local(_) => ReadingStatus::Reading,
+            BookData::Global(d) if d.chapters_read_ordered == 0 => ReadingStatus::PlanToRead,
+            BookData::Global(d) if d.is_finished() => ReadingStatus::Completed,
+            BookData::Global(_) => ReadingStatus::Reading,
+        }
+    }
+
+    /// Marks the book as completed if the last chapter of a completed novel has been finished
+    fn check_completed(&mut self, chapter: usize) {
+        if let BookData::Global(d) = &self.data {
+            let finished = d.get_chapter_prog(chapter) == ChapterProgress::Finished;
+            if finished && chapter == d.total_chapters && d.is_complete_novel() {
+                self.reading_status = Some(ReadingStatus::Completed);
+            }
+        }
+    }
+
     pub fn global_get_source_id(&self) -> SourceID {
         match &self.data {
             BookData::Local(_) => panic!("Function called on a book that is not sourced globally"),
@@ -361,6 +576,7 @@ impl Book {
             ))),
             BookData::Global(d) => {
                 d.set_chapter_prog(progress, chapter);
+                self.check_completed(chapter);
                 Ok(())
             }
         }
@@ -373,6 +589,7 @@ impl Book {
             ))),
             BookData::Global(d) => {
                 d.mark_chapter_complete(chapter);
+                self.check_completed(chapter);
                 Ok(())
             }
         }
@@ -456,6 +673,15 @@ impl Book {
     }
 
     pub fn display_info(&self) -> String {
+        let info = self.progress_info();
+        if self.tags.is_empty() {
+            return info;
+        }
+        let tags: Vec<&str> = self.tags.iter().map(|t| t.as_str()).collect();
+        format!("{} | Tags: {}", info, tags.join(", "))
+    }
+
+    fn progress_info(&self) -> String {
         if let BookData::Global(data) = &self.data {
             let pct =
                 if (data.chapters_read_ordered as f64 / data.total_chapters as f64).is_finite() {
@@ -464,14 +690,15 @@ impl Book {
                     100.0
                 };
             format!(
-                "{} | Chapters read: {}/{} ({:.2}%)",
+                "{} | {} | Chapters read: {}/{} ({:.2}%)",
                 self.name.clone(),
+                self.get_reading_status(),
                 data.chapters_read_ordered,
                 data.total_chapters,
                 pct,
             )
         } else {
-            return self.name.clone();
+            format!("{} | {}", self.name, self.get_reading_status())
         }
     }
 
@@ -491,13 +718,160 @@ impl Book {
         ctx.sources.get_source_by_id(s_id).unwrap().clone()
     }
 
-    pub fn update(&mut self, source: &Source) -> UpdatedChapters {
-        if let BookData::Global(ref mut data) = self.data {
-            data.update(source)
-        } else {
-            UpdatedChapters::None
+    /// Returns the hash of the file of a locally sourced book, or `None` if the book is global
+    pub(crate) fn get_local_hash(&self) -> Option<&str> {
+        match &self.data {
+            BookData::Local(d) => Some(&d.hash),
+            BookData::Global(_) => None,
         }
     }
+
+    /// Combines the reading progress of another copy of this book into this one.
+    ///
+    /// Finished chapters stay finished, the furthest position through each chapter and the furthest current chapter
+    /// are kept, and the longer chapter list is kept. Both books should have their chapter data loaded
+    pub(crate) fn merge_progress(&mut self, other: &Book) {
+        match (&mut self.data, &other.data) {
+            (BookData::Local(d), BookData::Local(o)) => {
+                d.progress = d.progress.furthest(o.progress)
+            }
+            (BookData::Global(d), BookData::Global(o)) => d.merge_progress(o),
+            // These can't be the same book
+            _ => (),
+        }
+    }
+
+    /// Returns a copy of the book's reading progress, e.g. to find what has changed since it was taken.
+    /// The book should have its chapter data loaded
+    pub(crate) fn get_progress_snapshot(&self) -> ProgressSnapshot {
+        match &self.data {
+            BookData::Local(d) => ProgressSnapshot::Local(d.progress),
+            BookData::Global(d) => ProgressSnapshot::Global {
+                current_chapter: d.current_chapter,
+                chapters: d.chapter_progress.clone(),
+            },
+        }
+    }
+
+    /// Combines reading progress from elsewhere (such as another device) into this book,
+    /// in the same way as `Book::merge_progress`. The book should have its chapter data loaded
+    pub(crate) fn merge_progress_snapshot(&mut self, progress: &ProgressSnapshot) {
+        match (&mut self.data, progress) {
+            (BookData::Local(d), ProgressSnapshot::Local(p)) => {
+                d.progress = d.progress.furthest(*p);
+            }
+            (
+                BookData::Global(d),
+                ProgressSnapshot::Global {
+                    current_chapter,
+                    chapters,
+                },
+            ) => d.merge_chapter_progress(chapters, *current_chapter),
+            // These can't be the same book
+            _ => (),
+        }
+    }
+
+    /// Returns the chapter list and all chapter progress of a global book.
+    ///
+    /// This is used by storage backends that store chapters separately to other book data.
+    /// Returns `None` if called on a locally sourced book, or if the chapter data hasn't been loaded
+    pub(crate) fn get_chapter_data(
+        &self,
+    ) -> Option<(&Vec<ChapterPreview>, &HashMap<usize, ChapterProgress>)> {
+        match &self.data {
+            BookData::Global(d) if d.chapter_source.is_none() => {
+                Some((d.source_novel.get_chapters(), &d.chapter_progress))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns a copy of the book without its chapter list or chapter progress,
+    /// for storing separately to the data that's loaded on demand
+    pub(crate) fn without_chapter_data(&self) -> Book {
+        let mut book = self.clone();
+        if let BookData::Global(d) = &mut book.data {
+            d.source_novel.take_chapters();
+            d.chapter_progress = HashMap::new();
+            d.chapter_source = None;
+        }
+        book
+    }
+
+    /// Marks the chapter data of a global book as not loaded, to be loaded on demand from the given source.
+    /// Any chapter data currently in memory is discarded
+    pub(crate) fn set_chapter_source(&mut self, source: ChapterSource) {
+        if let BookData::Global(d) = &mut self.data {
+            d.source_novel.take_chapters();
+            d.chapter_progress = HashMap::new();
+            d.chapter_source = Some(source);
+        }
+    }
+
+    /// Returns true if the chapter list and chapter progress are in memory.
+    /// This is always the case for locally sourced books
+    pub(crate) fn chapters_loaded(&self) -> bool {
+        match &self.data {
+            BookData::Local(_) => true,
+            BookData::Global(d) => d.chapter_source.is_none(),
+        }
+    }
+
+    /// Loads the chapter list and chapter progress if they aren't already in memory
+    pub(crate) fn load_chapter_data(&mut self) -> Result<(), TRError> {
+        let id = self.id;
+        if let BookData::Global(d) = &mut self.data {
+            if let Some(source) = &d.chapter_source {
+                let (chapters, progress) = source.load(id)?;
+                d.source_novel.set_chapters(chapters);
+                d.chapter_progress = progress;
+                d.chapter_source = None;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn update(&mut self, source: &Source) -> ChapterDiff {
+        let Some(url) = self.get_url().map(str::to_string) else {
+            return ChapterDiff::unchanged();
+        };
+        match source.parse_novel_and_chapters(url) {
+            Ok(novel) => self.apply_update(novel),
+            Err(_) => ChapterDiff::unchanged(),
+        }
+    }
+
+    /// Replaces the details and chapter list of a global book with ones newly fetched from its source, moving
+    /// progress, bookmarks and highlights onto the new numbering of the chapters.
+    ///
+    /// The history, timeline and reading sessions are kept by the `Context`, so they're moved by passing the returned
+    /// diff to `Context::record_update`
+    pub(crate) fn apply_update(&mut self, novel: Novel) -> ChapterDiff {
+        // Progress can't be kept if the existing chapters can't be loaded
+        if self.load_chapter_data().is_err() {
+            return ChapterDiff::unchanged();
+        }
+        let BookData::Global(ref mut data) = self.data else {
+            return ChapterDiff::unchanged();
+        };
+        let diff = data.apply_update(novel);
+        renumber_bookmarks(&mut self.bookmarks, &diff);
+        renumber_highlights(&mut self.highlights, &diff);
+        diff
+    }
+}
+
+fn global_id(novel: &Novel) -> ID {
+    ID::from_key(&format!(
+        "global:{}:{}",
+        usize::from(novel.get_source()),
+        novel.get_url()
+    ))
+}
+
+fn local_id(hash: &str) -> ID {
+    ID::from_key(&format!("local:{hash}"))
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -553,6 +927,10 @@ struct GlobalData {
     total_chapters: usize,
     chapter_progress: HashMap<usize, ChapterProgress>,
     source_novel: Novel,
+    /// Where the chapter list and chapter progress can be loaded from, if they haven't been loaded yet.
+    /// While this is set, both are empty in memory
+    #[serde(skip)]
+    chapter_source: Option<ChapterSource>,
 }
 
 impl PartialEq for GlobalData {
@@ -569,6 +947,7 @@ impl GlobalData {
             total_chapters: novel.get_length(),
             chapter_progress: HashMap::new(),
             source_novel: novel,
+            chapter_source: None,
         }
     }
 
@@ -604,12 +983,33 @@ impl GlobalData {
         }
     }
 
+    /// Returns true if the novel is completed on its source, so won't have any more chapters
+    fn is_complete_novel(&self) -> bool {
+        self.source_novel.get_status() == NovelStatus::Completed
+    }
+
+    /// Returns true if the novel is completed, and every chapter has been read
+    fn is_finished(&self) -> bool {
+        self.is_complete_novel() && self.chapters_read_ordered >= self.total_chapters
+    }
+
     fn mark_chapter_complete(&mut self, chapter: usize) {
         self.chapter_progress
             .insert(chapter, ChapterProgress::Finished);
         self.update_ordered_chapters();
     }
 
+    fn progress_past_end(&self) -> Vec<usize> {
+        let mut chapters: Vec<usize> = self
+            .chapter_progress
+            .keys()
+            .copied()
+            .filter(|&ch| ch > self.total_chapters)
+            .collect();
+        chapters.sort();
+        chapters
+    }
+
     fn update_ordered_chapters(&mut self) {
         loop {
             let next_ch = self.chapter_progress.get(&(self.chapters_read_ordered + 1));
@@ -623,30 +1023,113 @@ impl GlobalData {
         }
     }
 
-    fn get_ordered_chapters(&self) -> usize {
-        self.chapters_read_ordered
+    fn merge_progress(&mut self, other: &GlobalData) {
+        if other.total_chapters > self.total_chapters {
+            self.total_chapters = other.total_chapters;
+            self.source_novel = other.source_novel.clone();
+        }
+        self.merge_chapter_progress(&other.chapter_progress, other.current_chapter);
     }
 
-    fn update(&mut self, source: &Source) -> UpdatedChapters {
-        let u = source.parse_novel_and_chapters(self.source_novel.get_url().to_string());
-        if let Ok(updated) = u {
-            let current_length = self.total_chapters;
-            let updated_length = updated.get_length();
+    fn merge_chapter_progress(
+        &mut self,
+        progress: &HashMap<usize, ChapterProgress>,
+        current_chapter: usize,
+    ) {
+        for (&chapter, &progress) in progress.iter() {
+            let merged = match self.chapter_progress.get(&chapter) {
+                Some(&existing) => existing.furthest(progress),
+                None => progress,
+            };
+            self.chapter_progress.insert(chapter, merged);
+        }
+        self.update_ordered_chapters();
+        self.set_chapter(self.current_chapter.max(current_chapter));
+    }
 
-            // Update the details
-            self.total_chapters = updated_length;
-            self.source_novel = updated;
+    fn get_ordered_chapters(&self) -> usize {
+        self.chapters_read_ordered
+    }
 
-            if updated_length - current_length == 0 {
-                return UpdatedChapters::None;
-            } else if updated_length - current_length == 1 {
-                return UpdatedChapters::Single(updated_length);
-            } else {
-                return UpdatedChapters::Range((current_length + 1, updated_length));
+    /// Replaces the details and chapter list, comparing the chapters by URL to move progress onto the new numbering.
+    /// Progress in removed chapters is dropped
+    fn apply_update(&mut self, mut updated: Novel) -> ChapterDiff {
+        // A source listing no chapters has almost certainly failed to, rather than every chapter being taken down
+        if updated.get_length() == 0 {
+            updated.set_chapters(self.source_novel.get_chapters().clone());
+        }
+        let diff = diff_chapters(self.source_novel.get_chapters(), updated.get_chapters());
+
+        // Chapters that were kept take precedence over progress past the end that happens to share their new number
+        let mut progress = HashMap::new();
+        let mut past_end = Vec::new();
+        for (chapter, p) in self.chapter_progress.drain() {
+            match diff.numbers.get(&chapter) {
+                Some(&new) => {
+                    progress.insert(new, p);
+                }
+                None if !diff.removed.contains(&chapter) => past_end.push((chapter, p)),
+                None => (),
             }
-        } else {
-            UpdatedChapters::None
         }
+        for (chapter, p) in past_end {
+            progress.entry(chapter).or_insert(p);
+        }
+        self.chapter_progress = progress;
+
+        // If the current chapter was removed, carry on from the next chapter that's still there
+        let total = updated.get_length();
+        self.current_chapter = match diff.renumber(self.current_chapter) {
+            Some(chapter) => chapter,
+            None => diff
+                .numbers
+                .iter()
+                .filter(|(&old, _)| old > self.current_chapter)
+                .min_by_key(|(&old, _)| old)
+                .map(|(_, &new)| new)
+                .unwrap_or(total),
+        }
+        .max(1);
+
+        self.total_chapters = total;
+        self.source_novel = updated;
+        self.chapters_read_ordered = 0;
+        self.update_ordered_chapters();
+        diff
+    }
+}
+
+/// Where the user is with reading a book, as a whole
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ReadingStatus {
+    Reading,
+    PlanToRead,
+    OnHold,
+    Dropped,
+    Completed,
+}
+
+impl ReadingStatus {
+    /// Every reading status, in the order they should be shown
+    pub const ALL: [ReadingStatus; 5] = [
+        ReadingStatus::Reading,
+        ReadingStatus::PlanToRead,
+        ReadingStatus::OnHold,
+        ReadingStatus::Dropped,
+        ReadingStatus::Completed,
+    ];
+}
+
+impl Display for ReadingStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ReadingStatus::Reading => "Reading",
+            ReadingStatus::PlanToRead => "Plan to read",
+            ReadingStatus::OnHold => "On hold",
+            ReadingStatus::Dropped => "Dropped",
+            ReadingStatus::Completed => "Completed",
+        };
+        write!(f, "{name}")
     }
 }
 
@@ -658,3 +1141,56 @@ pub enum ChapterProgress {
     Word((usize, usize)),
     Finished,
 }
+
+impl ChapterProgress {
+    /// Returns whichever of two progresses through the same chapter is further through it.
+    /// Finished is always the furthest
+    fn furthest(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Finished, _) | (_, Self::Finished) => Self::Finished,
+            (Self::Location(a), Self::Location(b)) => Self::Location(a.max(b)),
+            (Self::Word(a), Self::Word(b)) => Self::Word(a.max(b)),
+            // Positions of different kinds can't be compared, so keep the existing one
+            (existing, _) => existing,
+        }
+    }
+}
+
+/// The reading progress of a book at some point in time
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) enum ProgressSnapshot {
+    Local(ChapterProgress),
+    Global {
+        current_chapter: usize,
+        chapters: HashMap<usize, ChapterProgress>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_status_follows_reading() {
+        // A completed novel with two chapters
+        let mut novel = serde_json::to_value(Novel::default()).unwrap();
+        novel["status"] = serde_json::json!("Completed");
+        let mut novel: Novel = serde_json::from_value(novel).unwrap();
+        novel.set_chapters(vec![ChapterPreview::default(); 2]);
+        let mut book = BookRef::new(Book::from_novel(novel));
+        assert_eq!(book.get_reading_status(), ReadingStatus::PlanToRead);
+
+        // A chapter that doesn't exist isn't opened
+        assert!(book.global_set_chapter(3).is_err());
+        assert_eq!(book.get_reading_status(), ReadingStatus::PlanToRead);
+        book.global_set_chapter(1).unwrap();
+        assert_eq!(book.get_reading_status(), ReadingStatus::Reading);
+        book.set_reading_status(ReadingStatus::OnHold);
+        book.global_mark_ch_read(1).unwrap();
+        assert_eq!(book.get_reading_status(), ReadingStatus::OnHold);
+        // Finishing the last chapter completes the book
+        book.global_set_progress(ChapterProgress::Finished, 2)
+            .unwrap();
+        assert_eq!(book.get_reading_status(), ReadingStatus::Completed);
+    }
+}
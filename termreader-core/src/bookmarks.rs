@@ -0,0 +1,206 @@
+// This module contains bookmarks, which are named places in a book that can be returned to.
+//
+// A chapter's progress only records how far through it the user is, so bookmarks are kept separately, with each
+// book. A bookmark is anchored to the index of a word in its chapter, as the reader splits chapters into words.
+
+use crate::{id::ID, updates::ChapterDiff, Context, TRError};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A named place in a book, with an optional note
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Bookmark {
+    chapter: usize,
+    word: usize,
+    name: String,
+    note: Option<String>,
+    /// When the bookmark was added, in seconds since the UNIX epoch
+    timestamp: u64,
+}
+
+impl Bookmark {
+    pub fn get_chapter(&self) -> usize {
+        self.chapter
+    }
+
+    /// Returns the index of the word in the chapter that the bookmark is anchored to
+    pub fn get_word(&self) -> usize {
+        self.word
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn position(&self) -> (usize, usize) {
+        (self.chapter, self.word)
+    }
+}
+
+/// Adds bookmarks to a list ordered by position, skipping any that are already in it
+pub(crate) fn merge_bookmarks(list: &mut Vec<Bookmark>, other: &[Bookmark]) {
+    for bookmark in other {
+        let same = |b: &Bookmark| b.position() == bookmark.position() && b.name == bookmark.name;
+        if !list.iter().any(same) {
+            list.push(bookmark.clone());
+        }
+    }
+    list.sort_by_key(|b| b.position());
+}
+
+/// Moves bookmarks onto the new numbering of a book's chapters after an update, dropping those in removed chapters
+pub(crate) fn renumber_bookmarks(list: &mut Vec<Bookmark>, diff: &ChapterDiff) {
+    list.retain_mut(|b| match diff.renumber(b.chapter) {
+        Some(chapter) => {
+            b.chapter = chapter;
+            true
+        }
+        None => false,
+    });
+    list.sort_by_key(|b| b.position());
+}
+
+impl Context {
+    /// Adds a bookmark to a book, anchored to a word in a chapter. Surrounding whitespace is removed from the name and
+    /// note, and an empty note is left out.
+    ///
+    /// Errors if:
+    /// - The book is missing from memory
+    /// - The book is local, as only books from sources have chapters
+    /// - The chapter doesn't exist
+    /// - The name is empty
+    pub fn add_bookmark(
+        &mut self,
+        id: ID,
+        chapter: usize,
+        word: usize,
+        name: &str,
+        note: Option<&str>,
+    ) -> Result<(), TRError> {
+        let Some(book) = self.books.get(id) else {
+            return Err(TRError::BookMissing);
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(TRError::InvalidChoice(String::from(
+                "a bookmark needs a name",
+            )));
+        }
+
+        let mut book = book.write();
+        let Some(total) = book.get_total_chs() else {
+            return Err(TRError::BadUse(String::from(
+                "supplied a local book where a global book should have been supplied",
+            )));
+        };
+        if chapter == 0 || chapter > total {
+            return Err(TRError::InvalidArgument(String::from(
+                "chapter outside of range",
+            )));
+        }
+
+        let bookmark = Bookmark {
+            chapter,
+            word,
+            name: name.to_string(),
+            note: note
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .map(String::from),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("time has gone VERY backwards")
+                .as_secs(),
+        };
+        // Bookmarks at the same place are kept in the order they were added
+        let pos = book
+            .bookmarks
+            .partition_point(|b| b.position() <= bookmark.position());
+        book.bookmarks.insert(pos, bookmark);
+        Ok(())
+    }
+
+    /// Removes a book's bookmark, given its index in `BookRef::get_bookmarks`
+    ///
+    /// Errors if the book is missing from memory, or the bookmark doesn't exist
+    pub fn remove_bookmark(&mut self, id: ID, index: usize) -> Result<Bookmark, TRError> {
+        let Some(book) = self.books.get(id) else {
+            return Err(TRError::BookMissing);
+        };
+        let mut book = book.write();
+        if index >= book.bookmarks.len() {
+            return Err(TRError::InvalidArgument(String::from(
+                "bookmark doesn't exist",
+            )));
+        }
+        Ok(book.bookmarks.remove(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        testing::{add_library_book, TestDir},
+        TRError,
+    };
+
+    #[test]
+    fn bookmarks_are_kept_in_order() {
+        let dir = TestDir::new("bookmarks-order");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        ctx.add_bookmark(id, 3, 10, "Ending", None).unwrap();
+        ctx.add_bookmark(id, 1, 50, " Start ", Some("  ")).unwrap();
+        ctx.add_bookmark(id, 1, 5, "Earlier", Some("a note"))
+            .unwrap();
+
+        let ctx = dir.reopen(ctx);
+        let bookmarks = ctx.get_book(id).unwrap().get_bookmarks();
+        let names: Vec<&str> = bookmarks.iter().map(|b| b.get_name()).collect();
+        assert_eq!(names, vec!["Earlier", "Start", "Ending"]);
+        assert_eq!(bookmarks[0].get_note(), Some("a note"));
+        assert_eq!(bookmarks[1].get_note(), None);
+    }
+
+    #[test]
+    fn bookmarks_need_a_name_and_an_existing_chapter() {
+        let dir = TestDir::new("bookmarks-invalid");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        assert!(matches!(
+            ctx.add_bookmark(id, 4, 0, "Missing", None),
+            Err(TRError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            ctx.add_bookmark(id, 0, 0, "Missing", None),
+            Err(TRError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            ctx.add_bookmark(id, 1, 0, " ", None),
+            Err(TRError::InvalidChoice(_))
+        ));
+        assert!(ctx.get_book(id).unwrap().get_bookmarks().is_empty());
+    }
+
+    #[test]
+    fn bookmarks_are_removed_by_index() {
+        let dir = TestDir::new("bookmarks-remove");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        ctx.add_bookmark(id, 1, 5, "First", None).unwrap();
+        ctx.add_bookmark(id, 1, 50, "Second", None).unwrap();
+
+        let removed = ctx.remove_bookmark(id, 1).unwrap();
+        assert_eq!(removed.get_word(), 50);
+        assert!(ctx.remove_bookmark(id, 1).is_err());
+        assert_eq!(ctx.get_book(id).unwrap().get_bookmarks().len(), 1);
+    }
+}
@@ -1,5 +1,5 @@
-use std::rc::Rc;
-use std::{cell::RefCell, collections::HashMap};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
@@ -25,8 +25,7 @@ impl BooksContext {
 
     pub(super) fn add_book(&mut self, book: Book) {
         if self.books.get(&book.get_id()).is_none() {
-            self.books
-                .insert(book.get_id(), BookRef(Rc::new(RefCell::new(book))));
+            self.books.insert(book.get_id(), BookRef::new(book));
         }
     }
 
@@ -46,21 +45,38 @@ impl BooksContext {
         self.books
             .values()
             .find(|x| {
-                x.0.borrow()
+                x.read()
                     .get_full_url()
                     .is_some_and(|link| link.to_string() == url)
             })
             .cloned()
     }
 
+    /// Finds the stored copy of a book from elsewhere, such as a backup.
+    ///
+    /// Books are matched by their ID, then by their URL, or for local books, the hash of their file
+    pub(super) fn find_matching(&self, book: &Book) -> Option<BookRef> {
+        if let Some(b) = self.get(book.get_id()) {
+            return Some(b);
+        }
+        if let Some(url) = book.get_full_url() {
+            return self.find_book_by_url(url.to_string());
+        }
+        let hash = book.get_local_hash()?;
+        self.books
+            .values()
+            .find(|b| b.read().get_local_hash() == Some(hash))
+            .cloned()
+    }
+
     /// Removes any books that are no longer referenced, returning the number of books removed
     pub(super) fn remove_unneeded(&mut self) -> usize {
         let mut to_remove = Vec::new();
         for book in self.books.values() {
             // Strong count of 1 implies that the books are only stored in this map
             // Strong count of the inner type should always be the same as the amount of `BookRef`s
-            if Rc::strong_count(&book.0) == 1 {
-                to_remove.push(book.0.borrow().get_id());
+            if Arc::strong_count(&book.0) == 1 {
+                to_remove.push(book.read().get_id());
             }
         }
 
@@ -0,0 +1,440 @@
+// This module contains exporting the library to files that can be shared or kept elsewhere, and importing them again.
+//
+// Three formats are supported, chosen by the file's extension:
+//
+// - JSON (`.json`), in the following schema. New fields may be added in later versions,
+//   but existing fields will keep their meaning unless `version` is changed.
+//
+//   ```json
+//   {
+//     "version": 1,
+//     "books": [
+//       {
+//         "category": "Default",        // the library category the book is in
+//         "title": "Some Novel",        // the name of the novel on its source
+//         "alias": "My Name",           // the name the book was renamed to, or null
+//         "source": "FreeWebNovel",     // the name of the source the novel is from
+//         "url": "https://...",         // the full URL of the novel's page
+//         "chapters_read": 12,          // the amount of chapters read in order from the first
+//         "total_chapters": 100,        // the amount of chapters the novel had when exported
+//         "status": "Reading"           // one of "Unread", "Reading", or "Finished"
+//       }
+//     ]
+//   }
+//   ```
+//
+// - CSV (`.csv`), with a header row and one row per book, using the same fields (in the same order) as the JSON
+//   format. An empty `alias` means the book wasn't renamed.
+//
+// - OPML (`.opml`), with an outline per category containing an outline per book. Each book's `text` is its alias or
+//   title, `title` is its title, `url` is its URL, and the rest of the fields are stored as the attributes `source`,
+//   `chaptersRead`, `totalChapters` and `status`.
+//
+// Locally sourced books are never exported, as they can't be found again from a URL.
+
+use crate::{
+    book::{Book, ChapterProgress},
+    books_context::BooksContext,
+    history::HistoryContext,
+    library::LibraryContext,
+    storage::LoadedData,
+    updates::UpdatesContext,
+    Context, TRError,
+};
+use quick_xml::{
+    events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event},
+    Reader, Writer,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+use termreader_sources::sources::{Scrape, Source};
+
+/// The version of the JSON export schema
+const EXPORT_VERSION: u32 = 1;
+
+/// The formats the library can be exported to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Opml,
+}
+
+impl ExportFormat {
+    /// Returns the format of a file from its extension
+    pub fn from_path(path: &Path) -> Result<Self, TRError> {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("csv") => Ok(Self::Csv),
+            Some("json") => Ok(Self::Json),
+            Some("opml") | Some("xml") => Ok(Self::Opml),
+            _ => Err(TRError::InvalidArgument(String::from(
+                "the file should end in .csv, .json, or .opml",
+            ))),
+        }
+    }
+}
+
+/// How far through a book the user is
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressStatus {
+    Unread,
+    Reading,
+    Finished,
+}
+
+impl ProgressStatus {
+    fn from_chapters(read: usize, total: usize) -> Self {
+        if read == 0 {
+            Self::Unread
+        } else if read >= total {
+            Self::Finished
+        } else {
+            Self::Reading
+        }
+    }
+}
+
+/// A book in an exported library
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LibraryEntry {
+    pub category: String,
+    pub title: String,
+    pub alias: Option<String>,
+    pub source: String,
+    pub url: String,
+    pub chapters_read: usize,
+    pub total_chapters: usize,
+    pub status: ProgressStatus,
+}
+
+/// A book that couldn't be imported
+#[derive(Clone, Debug)]
+pub struct FailedEntry {
+    pub title: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LibraryExport {
+    version: u32,
+    books: Vec<LibraryEntry>,
+}
+
+impl LibraryEntry {
+    /// Fetches the book from its source, restoring its alias and reading progress.
+    ///
+    /// This makes a (blocking) web request
+    pub fn resolve(&self, source: &Source) -> Result<Book, TRError> {
+        let Some((novel_path, _)) = source.split_url(&self.url) else {
+            return Err(TRError::InvalidArgument(format!(
+                "{} is not a novel on {}",
+                self.url,
+                source.get_name()
+            )));
+        };
+        let novel = source
+            .parse_novel_and_chapters(novel_path)
+            .map_err(|e| TRError::SourceFailure(e.to_string()))?;
+
+        let mut book = Book::from_novel(novel);
+        if let Some(alias) = &self.alias {
+            book.rename(alias.clone());
+        }
+        let total = book.get_total_chs().unwrap_or_default();
+        let read = self.chapters_read.min(total);
+        for chapter in 1..=read {
+            book.global_set_progress(ChapterProgress::Finished, chapter)?;
+        }
+        if read > 0 {
+            // Continue from the next chapter, if there is one
+            book.global_set_ch((read + 1).min(total))?;
+        }
+        Ok(book)
+    }
+
+    /// Returns a failure for this entry
+    pub fn fail(&self, reason: String) -> FailedEntry {
+        FailedEntry {
+            title: self.alias.clone().unwrap_or_else(|| self.title.clone()),
+            reason,
+        }
+    }
+}
+
+impl Context {
+    /// Exports every globally sourced book in the library to a file, in the format given by its extension.
+    ///
+    /// Returns the amount of books exported
+    pub fn export_library(&self, path: &Path) -> Result<usize, TRError> {
+        let format = ExportFormat::from_path(path)?;
+        let entries = self.library_entries();
+
+        // Write to a temporary file first, so that an existing export is never left incomplete
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        match format {
+            ExportFormat::Csv => write_csv(&mut file, &entries)?,
+            ExportFormat::Json => serde_json::to_writer_pretty(
+                &mut file,
+                &LibraryExport {
+                    version: EXPORT_VERSION,
+                    books: entries.clone(),
+                },
+            )?,
+            ExportFormat::Opml => write_opml(&mut file, &entries)?,
+        }
+        file.flush()?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(entries.len())
+    }
+
+    /// Returns an entry for every globally sourced book in the library, in category order
+    pub fn library_entries(&self) -> Vec<LibraryEntry> {
+        let mut entries = Vec::new();
+        for category in self.library.category_order.iter() {
+            for book in self.library.books[category].iter() {
+                if book.is_local() {
+                    continue;
+                }
+                let title = book.global_get_original_name().expect("the book is global");
+                let name = book.get_name();
+                let chapters_read = book
+                    .global_get_ordered_chapters()
+                    .expect("the book is global");
+                let total_chapters = book.get_total_ch_count().unwrap_or_default();
+                entries.push(LibraryEntry {
+                    category: category.clone(),
+                    alias: (name != title).then_some(name),
+                    title,
+                    source: self
+                        .get_book_source(book.get_id())
+                        .map(|s| s.get_name())
+                        .unwrap_or_default(),
+                    url: book.get_full_url().unwrap_or_default(),
+                    chapters_read,
+                    total_chapters,
+                    status: ProgressStatus::from_chapters(chapters_read, total_chapters),
+                });
+            }
+        }
+        entries
+    }
+
+    /// Finds a source by its name, ignoring case
+    pub fn find_source_by_name(&self, name: &str) -> Option<&Source> {
+        self.sources
+            .iter()
+            .find(|s| s.get_name().eq_ignore_ascii_case(name))
+    }
+
+    /// Adds books resolved from library entries (see `LibraryEntry::resolve`) to the library, along with their categories.
+    ///
+    /// Books that are already in termreader keep their data, with reading progress combined
+    pub fn import_library_books(&mut self, books: Vec<(Book, String)>) {
+        let mut data = LoadedData {
+            books: BooksContext::new(),
+            library: LibraryContext::new(),
+            history: HistoryContext::new(),
+            updates: UpdatesContext::new(),
+            problems: Vec::new(),
+        };
+        for (book, category) in books {
+            let id = book.get_id();
+            data.books.add_book(book);
+            let book = data.books.get(id).expect("we just added the book");
+            let category = (category != self.library.default_category_name).then_some(category);
+            data.library.add_to_category(book, category);
+        }
+        self.merge_data(data);
+    }
+}
+
+/// Reads the entries of an exported library, in the format given by its extension
+pub fn read_library_export(path: &Path) -> Result<Vec<LibraryEntry>, TRError> {
+    let data = fs::read_to_string(path)?;
+    match ExportFormat::from_path(path)? {
+        ExportFormat::Csv => {
+            let mut reader = csv::Reader::from_reader(data.as_bytes());
+            let mut entries = Vec::new();
+            for entry in reader.deserialize() {
+                let entry: LibraryEntry = entry?;
+                entries.push(entry);
+            }
+            Ok(entries)
+        }
+        ExportFormat::Json => {
+            let export: LibraryExport = serde_json::from_str(&data)?;
+            if export.version > EXPORT_VERSION {
+                return Err(TRError::UnsupportedVersion(export.version));
+            }
+            Ok(export.books)
+        }
+        ExportFormat::Opml => read_opml(&data),
+    }
+}
+
+fn write_csv(file: &mut File, entries: &[LibraryEntry]) -> Result<(), TRError> {
+    let mut writer = csv::Writer::from_writer(file);
+    for entry in entries {
+        writer.serialize(entry)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_opml(file: &mut File, entries: &[LibraryEntry]) -> Result<(), TRError> {
+    let mut writer = Writer::new_with_indent(file, b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    writer.write_event(Event::Start(
+        BytesStart::new("opml").with_attributes([("version", "2.0")]),
+    ))?;
+    writer.write_event(Event::Start(BytesStart::new("head")))?;
+    writer.write_event(Event::Start(BytesStart::new("title")))?;
+    writer.write_event(Event::Text(BytesText::new("termreader library")))?;
+    writer.write_event(Event::End(BytesEnd::new("title")))?;
+    writer.write_event(Event::End(BytesEnd::new("head")))?;
+    writer.write_event(Event::Start(BytesStart::new("body")))?;
+
+    let mut category: Option<&str> = None;
+    for entry in entries {
+        // Entries are grouped by category, so a new category means the previous one is done
+        if category != Some(entry.category.as_str()) {
+            if category.is_some() {
+                writer.write_event(Event::End(BytesEnd::new("outline")))?;
+            }
+            category = Some(&entry.category);
+            writer.write_event(Event::Start(
+                BytesStart::new("outline").with_attributes([("text", entry.category.as_str())]),
+            ))?;
+        }
+
+        let chapters_read = entry.chapters_read.to_string();
+        let total_chapters = entry.total_chapters.to_string();
+        let status = format!("{:?}", entry.status);
+        writer.write_event(Event::Empty(BytesStart::new("outline").with_attributes([
+            ("type", "link"),
+            ("text", entry.alias.as_deref().unwrap_or(&entry.title)),
+            ("title", entry.title.as_str()),
+            ("url", entry.url.as_str()),
+            ("source", entry.source.as_str()),
+            ("chaptersRead", chapters_read.as_str()),
+            ("totalChapters", total_chapters.as_str()),
+            ("status", status.as_str()),
+        ])))?;
+    }
+    if category.is_some() {
+        writer.write_event(Event::End(BytesEnd::new("outline")))?;
+    }
+
+    writer.write_event(Event::End(BytesEnd::new("body")))?;
+    writer.write_event(Event::End(BytesEnd::new("opml")))?;
+    Ok(())
+}
+
+fn read_opml(data: &str) -> Result<Vec<LibraryEntry>, TRError> {
+    let mut reader = Reader::from_str(data);
+    reader.trim_text(true);
+
+    let mut entries = Vec::new();
+    // The categories of the outlines currently open, or `None` for outlines that are books
+    let mut open: Vec<Option<String>> = Vec::new();
+    loop {
+        let (outline, is_empty) = match reader.read_event()? {
+            Event::Start(e) if e.name().as_ref() == b"outline" => (e, false),
+            Event::Empty(e) if e.name().as_ref() == b"outline" => (e, true),
+            Event::End(e) if e.name().as_ref() == b"outline" => {
+                open.pop();
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        let mut attributes = std::collections::HashMap::new();
+        for attribute in outline.attributes() {
+            let attribute = attribute.map_err(quick_xml::Error::from)?;
+            attributes.insert(
+                String::from_utf8_lossy(attribute.key.as_ref()).to_string(),
+                attribute.unescape_value()?.to_string(),
+            );
+        }
+
+        let entry = attributes.get("url").map(|url| {
+            let text = attributes.get("text").cloned().unwrap_or_default();
+            let title = attributes.get("title").cloned().unwrap_or(text.clone());
+            let number = |name: &str| {
+                attributes
+                    .get(name)
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or_default()
+            };
+            let chapters_read = number("chaptersRead");
+            let total_chapters = number("totalChapters");
+            LibraryEntry {
+                // Books outside of any category go in the default category
+                category: open
+                    .iter()
+                    .rev()
+                    .flatten()
+                    .next()
+                    .cloned()
+                    .unwrap_or_else(|| String::from("Default")),
+                alias: (text != title && !text.is_empty()).then_some(text),
+                title,
+                source: attributes.get("source").cloned().unwrap_or_default(),
+                url: url.clone(),
+                chapters_read,
+                total_chapters,
+                status: ProgressStatus::from_chapters(chapters_read, total_chapters),
+            }
+        });
+
+        if !is_empty {
+            open.push(match entry {
+                Some(_) => None,
+                None => attributes.get("text").cloned(),
+            });
+        }
+        entries.extend(entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestDir;
+    use termreader_sources::novel::Novel;
+
+    #[test]
+    fn export_then_read() {
+        let dir = TestDir::new("export-roundtrip");
+        fs::create_dir_all(dir.path()).unwrap();
+
+        let mut ctx = dir.open();
+        let mut book = Book::from_novel(Novel::default());
+        book.rename(String::from("Renamed"));
+        let id = book.get_id();
+        ctx.add_book(book);
+        ctx.create_library_category(String::from("Reading & more"))
+            .unwrap();
+        ctx.add_to_lib(id, Some("Reading & more")).unwrap();
+
+        let entries = ctx.library_entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].alias.as_deref(), Some("Renamed"));
+        for name in ["library.csv", "library.json", "library.opml"] {
+            let path = dir.path().join(name);
+            assert_eq!(ctx.export_library(&path).unwrap(), 1);
+            assert_eq!(read_library_export(&path).unwrap(), entries, "{name}");
+        }
+    }
+}
@@ -0,0 +1,326 @@
+// This module contains highlights, which are spans of words in a chapter marked by the user, and the export of a
+// book's highlights and bookmarks to Markdown.
+//
+// Like bookmarks, highlights are kept with each book and anchored to word indexes in a chapter. Chapter text isn't
+// saved, so the highlighted words are kept in the highlight, so that they can be exported without fetching them.
+
+use crate::{book::BookRef, id::ID, updates::ChapterDiff, Context, TRError};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A span of words in a chapter, with an optional comment
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Highlight {
+    chapter: usize,
+    /// The index of the first word highlighted
+    start: usize,
+    /// The index of the last word highlighted
+    end: usize,
+    /// The words highlighted
+    text: String,
+    comment: Option<String>,
+    /// When the highlight was added, in seconds since the UNIX epoch
+    timestamp: u64,
+}
+
+impl Highlight {
+    pub fn get_chapter(&self) -> usize {
+        self.chapter
+    }
+
+    /// Returns the indexes of the first and last words highlighted in the chapter
+    pub fn get_span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    /// Returns true if the word at the index in the chapter is highlighted
+    pub fn contains(&self, word: usize) -> bool {
+        (self.start..=self.end).contains(&word)
+    }
+
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn get_comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn position(&self) -> (usize, usize, usize) {
+        (self.chapter, self.start, self.end)
+    }
+}
+
+/// Adds highlights to a list ordered by position, skipping any that are already in it
+pub(crate) fn merge_highlights(list: &mut Vec<Highlight>, other: &[Highlight]) {
+    for highlight in other {
+        if !list.iter().any(|h| h.position() == highlight.position()) {
+            list.push(highlight.clone());
+        }
+    }
+    list.sort_by_key(|h| h.position());
+}
+
+/// Moves highlights onto the new numbering of a book's chapters after an update, dropping those in removed chapters
+pub(crate) fn renumber_highlights(list: &mut Vec<Highlight>, diff: &ChapterDiff) {
+    list.retain_mut(|h| match diff.renumber(h.chapter) {
+        Some(chapter) => {
+            h.chapter = chapter;
+            true
+        }
+        None => false,
+    });
+    list.sort_by_key(|h| h.position());
+}
+
+/// Writes the highlights and bookmarks of a book as Markdown, grouped under a heading for each chapter
+fn write_annotations(book: &BookRef, chapter_url: impl Fn(&str) -> String) -> String {
+    let highlights = book.get_highlights();
+    let bookmarks = book.get_bookmarks();
+    let chapters = book.get_chapters().unwrap_or_default();
+
+    let mut md = format!("# {}\n", book.get_name());
+    if let Some(url) = book.get_full_url() {
+        let _ = writeln!(md, "\nSource: <{url}>");
+    }
+
+    let mut numbers: Vec<usize> = highlights
+        .iter()
+        .map(|h| h.chapter)
+        .chain(bookmarks.iter().map(|b| b.get_chapter()))
+        .collect();
+    numbers.sort();
+    numbers.dedup();
+
+    for number in numbers {
+        let name = chapters
+            .iter()
+            .find(|c| c.get_chapter_no() == number)
+            .map(|c| c.get_name())
+            .filter(|n| !n.is_empty());
+        match name {
+            Some(name) => {
+                let _ = writeln!(md, "\n## Chapter {number}: {name}");
+            }
+            None => {
+                let _ = writeln!(md, "\n## Chapter {number}");
+            }
+        }
+        if let Some(path) = book.get_chapter_url(number) {
+            let _ = writeln!(md, "\n<{}>", chapter_url(&path));
+        }
+
+        for bookmark in bookmarks.iter().filter(|b| b.get_chapter() == number) {
+            let _ = match bookmark.get_note() {
+                Some(note) => writeln!(md, "\n- Bookmark **{}**: {}", bookmark.get_name(), note),
+                None => writeln!(md, "\n- Bookmark **{}**", bookmark.get_name()),
+            };
+        }
+        for highlight in highlights.iter().filter(|h| h.chapter == number) {
+            let _ = writeln!(md, "\n> {}", highlight.text);
+            if let Some(comment) = &highlight.comment {
+                let _ = writeln!(md, "\n{comment}");
+            }
+        }
+    }
+    md
+}
+
+impl Context {
+    /// Adds a highlight to a book, spanning from one word to another in a chapter, and holding the words
+    /// highlighted. Surrounding whitespace is removed from the comment, and an empty comment is left out.
+    ///
+    /// Errors if:
+    /// - The book is missing from memory
+    /// - The book is local, as only books from sources have chapters
+    /// - The chapter doesn't exist
+    /// - The span ends before it starts
+    /// - The text is empty
+    pub fn add_highlight(
+        &mut self,
+        id: ID,
+        chapter: usize,
+        span: (usize, usize),
+        text: &str,
+        comment: Option<&str>,
+    ) -> Result<(), TRError> {
+        let Some(book) = self.books.get(id) else {
+            return Err(TRError::BookMissing);
+        };
+        let (start, end) = span;
+        if end < start {
+            return Err(TRError::InvalidArgument(String::from(
+                "the highlight ends before it starts",
+            )));
+        }
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(TRError::InvalidArgument(String::from(
+                "nothing was highlighted",
+            )));
+        }
+
+        let mut book = book.write();
+        let Some(total) = book.get_total_chs() else {
+            return Err(TRError::BadUse(String::from(
+                "supplied a local book where a global book should have been supplied",
+            )));
+        };
+        if chapter == 0 || chapter > total {
+            return Err(TRError::InvalidArgument(String::from(
+                "chapter outside of range",
+            )));
+        }
+
+        let highlight = Highlight {
+            chapter,
+            start,
+            end,
+            text: text.to_string(),
+            comment: comment
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .map(String::from),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("time has gone VERY backwards")
+                .as_secs(),
+        };
+        let pos = book
+            .highlights
+            .partition_point(|h| h.position() <= highlight.position());
+        book.highlights.insert(pos, highlight);
+        Ok(())
+    }
+
+    /// Removes a book's highlight, given its index in `BookRef::get_highlights`
+    ///
+    /// Errors if the book is missing from memory, or the highlight doesn't exist
+    pub fn remove_highlight(&mut self, id: ID, index: usize) -> Result<Highlight, TRError> {
+        let Some(book) = self.books.get(id) else {
+            return Err(TRError::BookMissing);
+        };
+        let mut book = book.write();
+        if index >= book.highlights.len() {
+            return Err(TRError::InvalidArgument(String::from(
+                "highlight doesn't exist",
+            )));
+        }
+        Ok(book.highlights.remove(index))
+    }
+
+    /// Returns the highlights and bookmarks of a book as Markdown, under a heading and link for each chapter
+    ///
+    /// Errors if the book is missing from memory
+    pub fn get_annotations_markdown(&self, id: ID) -> Result<String, TRError> {
+        let Some(book) = self.books.get(id) else {
+            return Err(TRError::BookMissing);
+        };
+        let source = self.get_book_source(id);
+        let novel_path = book.get_url().unwrap_or_default();
+        let chapter_url = |path: &str| match source {
+            Some(source) if !path.contains("://") => source.get_chapter_url(&novel_path, path),
+            _ => path.to_string(),
+        };
+        Ok(write_annotations(&book, chapter_url))
+    }
+
+    /// Exports the highlights and bookmarks of a book to a Markdown file. See `Context::get_annotations_markdown`
+    ///
+    /// Returns the amount of highlights and bookmarks exported
+    pub fn export_annotations(&self, id: ID, path: &Path) -> Result<usize, TRError> {
+        let markdown = self.get_annotations_markdown(id)?;
+        let book = self.books.get(id).ok_or(TRError::BookMissing)?;
+        let count = book.read().highlights.len() + book.read().bookmarks.len();
+
+        // Write to a temporary file first, so that an existing export is never left incomplete
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(markdown.as_bytes())?;
+        file.flush()?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        testing::{add_library_book, TestDir},
+        TRError,
+    };
+
+    #[test]
+    fn highlights_are_kept_in_order() {
+        let dir = TestDir::new("highlights-order");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 2);
+        ctx.add_highlight(id, 2, (4, 6), "a dark night", None)
+            .unwrap();
+        ctx.add_highlight(id, 1, (0, 1), " Lord Aster ", Some(" "))
+            .unwrap();
+
+        let ctx = dir.reopen(ctx);
+        let highlights = ctx.get_book(id).unwrap().get_highlights();
+        assert_eq!(highlights[0].get_text(), "Lord Aster");
+        assert_eq!(highlights[0].get_comment(), None);
+        assert!(highlights[1].contains(5));
+        assert!(!highlights[1].contains(7));
+    }
+
+    #[test]
+    fn highlights_need_words_in_an_existing_chapter() {
+        let dir = TestDir::new("highlights-invalid");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 2);
+        for (chapter, span, text) in [
+            (1, (3, 2), "backwards"),
+            (1, (0, 1), " "),
+            (3, (0, 1), "missing"),
+        ] {
+            assert!(matches!(
+                ctx.add_highlight(id, chapter, span, text, None),
+                Err(TRError::InvalidArgument(_))
+            ));
+        }
+        assert!(ctx.get_book(id).unwrap().get_highlights().is_empty());
+    }
+
+    #[test]
+    fn annotations_are_exported_by_chapter() {
+        let dir = TestDir::new("highlights-export");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 2);
+        ctx.add_highlight(id, 2, (4, 6), "a dark night", None)
+            .unwrap();
+        ctx.add_highlight(
+            id,
+            1,
+            (0, 1),
+            "Lord Aster",
+            Some("Also translated as Astor"),
+        )
+        .unwrap();
+        ctx.add_bookmark(id, 2, 4, "Storm", None).unwrap();
+
+        let path = dir.path().join("annotations.md");
+        assert_eq!(ctx.export_annotations(id, &path).unwrap(), 3);
+        let markdown = std::fs::read_to_string(&path).unwrap();
+        let expected =
+            "## Chapter 1: Part 1\n\n<ch-1>\n\n> Lord Aster\n\nAlso translated as Astor\n\n\
+                        ## Chapter 2: Part 2\n\n<ch-2>\n\n- Bookmark **Storm**\n\n> a dark night\n";
+        assert!(markdown.starts_with("# Book\n"), "{markdown}");
+        assert!(markdown.ends_with(expected), "{markdown}");
+    }
+}
@@ -1,5 +1,9 @@
 use crate::book::BookRef;
 use crate::books_context::BooksContext;
+use crate::stats::ReadingSession;
+use crate::timeline::HistoryEvent;
+use crate::updates::ChapterDiff;
+use crate::verify::Problem;
 use crate::ID;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
@@ -9,26 +13,39 @@ use std::time::UNIX_EPOCH;
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub(super) struct HistCtxSerialize {
     pub(super) history: VecDeque<HistEntrySerialize>,
+    #[serde(default)]
+    pub(super) sessions: Vec<ReadingSession>,
+    #[serde(default)]
+    pub(super) events: Vec<HistoryEvent>,
 }
 
 impl HistCtxSerialize {
-    pub(super) fn from_hist_ctx(hist_ctx: HistoryContext) -> Self {
+    pub(super) fn from_hist_ctx(hist_ctx: &HistoryContext) -> Self {
         Self {
             history: hist_ctx
                 .history
-                .into_iter()
+                .iter()
                 .map(|x| HistEntrySerialize::from_hist_entry(x))
                 .collect(),
+            sessions: hist_ctx.sessions.clone(),
+            events: hist_ctx.events.clone(),
         }
     }
 
-    pub(super) fn to_hist_ctx(self, books: &BooksContext) -> HistoryContext {
+    /// Entries for books that don't exist are left out, and added to the problems found
+    pub(super) fn to_hist_ctx(
+        self,
+        books: &BooksContext,
+        problems: &mut Vec<Problem>,
+    ) -> HistoryContext {
         HistoryContext {
             history: self
                 .history
                 .into_iter()
-                .map(|x| HistEntrySerialize::to_hist_entry(x, books))
+                .filter_map(|x| HistEntrySerialize::to_hist_entry(x, books, problems))
                 .collect(),
+            sessions: self.sessions,
+            events: self.events,
         }
     }
 }
@@ -36,17 +53,25 @@ impl HistCtxSerialize {
 #[derive(Clone, Debug)]
 pub(super) struct HistoryContext {
     pub(super) history: VecDeque<HistoryEntry>,
+    /// Every reading session, oldest first. These are kept when the history is cleared, and refer to books by ID
+    /// so that they outlive the books
+    pub(super) sessions: Vec<ReadingSession>,
+    /// The timeline of chapters opened and finished, oldest first
+    pub(super) events: Vec<HistoryEvent>,
 }
 
 impl HistoryContext {
     pub(super) fn new() -> Self {
         Self {
             history: VecDeque::new(),
+            sessions: Vec::new(),
+            events: Vec::new(),
         }
     }
 
     pub(super) fn clear(&mut self) {
         self.history = VecDeque::new();
+        self.events = Vec::new();
     }
 
     pub(super) fn get_history(&self) -> &VecDeque<HistoryEntry> {
@@ -54,7 +79,48 @@ impl HistoryContext {
     }
 
     pub(super) fn remove_entry(&mut self, id: ID) {
-        self.history.retain(|h| h.book.0.borrow().get_id() != id)
+        self.history.retain(|h| h.book.read().get_id() != id)
+    }
+
+    /// Moves a book's entry onto the new numbering of its chapters after an update. If its chapter was removed, the
+    /// entry follows the book to the chapter it carries on from
+    pub(super) fn renumber_entry(&mut self, id: ID, diff: &ChapterDiff) {
+        for entry in self.history.iter_mut() {
+            if entry.book.get_id() != id || entry.timestamp > diff.timestamp {
+                continue;
+            }
+            entry.chapter = diff
+                .renumber(entry.chapter)
+                .or_else(|| entry.book.get_current_ch())
+                .unwrap_or(entry.chapter);
+        }
+    }
+
+    /// Adds an entry from elsewhere, such as a backup, keeping only the latest entry for each book
+    pub(super) fn merge_entry(&mut self, book: BookRef, timestamp: u64, chapter: usize) {
+        let id = book.get_id();
+        if let Some(pos) = self.history.iter().position(|h| h.book.get_id() == id) {
+            if self.history[pos].timestamp >= timestamp {
+                return;
+            }
+            self.history.remove(pos);
+        }
+        book.write().in_history = true;
+
+        // History is ordered from newest to oldest
+        let pos = self
+            .history
+            .iter()
+            .position(|h| h.timestamp < timestamp)
+            .unwrap_or(self.history.len());
+        self.history.insert(
+            pos,
+            HistoryEntry {
+                book,
+                timestamp,
+                chapter,
+            },
+        );
     }
 
     pub(super) fn add_entry(&mut self, book: BookRef) {
@@ -62,7 +128,7 @@ impl HistoryContext {
             .duration_since(UNIX_EPOCH)
             .expect("time has gone VERY backwards")
             .as_secs();
-        if book.0.borrow().is_local() {
+        if book.read().is_local() {
             self.history.push_front(HistoryEntry {
                 book,
                 timestamp,
@@ -70,8 +136,7 @@ impl HistoryContext {
             })
         } else {
             let ch = book
-                .0
-                .borrow()
+                .read()
                 .get_current_ch()
                 .expect("we've checked that the book isn't local");
             self.history.push_front(HistoryEntry {
@@ -91,7 +156,7 @@ pub(super) struct HistEntrySerialize {
 }
 
 impl HistEntrySerialize {
-    fn from_hist_entry(entry: HistoryEntry) -> Self {
+    fn from_hist_entry(entry: &HistoryEntry) -> Self {
         Self {
             book: entry.book.get_id(),
             timestamp: entry.timestamp,
@@ -99,12 +164,23 @@ impl HistEntrySerialize {
         }
     }
 
-    fn to_hist_entry(self, books: &BooksContext) -> HistoryEntry {
-        HistoryEntry {
-            book: books.get(self.book).unwrap(),
+    fn to_hist_entry(
+        self,
+        books: &BooksContext,
+        problems: &mut Vec<Problem>,
+    ) -> Option<HistoryEntry> {
+        let Some(book) = books.get(self.book) else {
+            problems.push(Problem::DanglingReference {
+                place: String::from("history"),
+                id: self.book,
+            });
+            return None;
+        };
+        Some(HistoryEntry {
+            book,
             timestamp: self.timestamp,
             chapter: self.chapter,
-        }
+        })
     }
 }
 
@@ -121,7 +197,7 @@ impl HistoryEntry {
     }
 
     pub fn get_book_name(&self) -> String {
-        self.book.0.borrow().get_name().to_string()
+        self.book.read().get_name().to_string()
     }
 
     pub fn get_chapter(&self) -> usize {
@@ -129,7 +205,7 @@ impl HistoryEntry {
     }
 
     pub fn get_book_id(&self) -> ID {
-        self.book.0.borrow().get_id()
+        self.book.read().get_id()
     }
 
     pub fn get_book_ref(&self) -> BookRef {
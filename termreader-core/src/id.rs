@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 
 /// An ID used to uniquely identify a book.
-/// Determined using the current timestamp, resulting in very little risk of collisions.
+/// Derived from what the book is (see `Book::stable_id`), so every copy of a book has the same ID.
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Serialize, Deserialize, Eq, Hash)]
 pub struct ID {
     id: u128,
@@ -10,6 +10,7 @@ pub struct ID {
 
 impl ID {
     /// Generates an ID using system time.
+    /// Only used for things that can't be identified by their contents, such as devices
     pub(crate) fn generate() -> Self {
         let now = SystemTime::now();
 
@@ -21,4 +22,23 @@ impl ID {
             id: unix_timestamp.as_nanos(),
         }
     }
+
+    /// Derives an ID from a key, so that the same key always gives the same ID.
+    /// The key is hashed, so different keys are very unlikely to collide.
+    /// Only 64 bits of the hash are used, as larger numbers can't be read back from JSON
+    pub(crate) fn from_key(key: &str) -> Self {
+        let digest = sha256::digest(key);
+        let id = u64::from_str_radix(&digest[..16], 16).expect("a SHA-256 digest is hexadecimal");
+        Self { id: id.into() }
+    }
+
+    /// Returns the underlying value, e.g. to be used as a database key
+    pub(crate) fn as_u128(&self) -> u128 {
+        self.id
+    }
+
+    /// Creates an ID from its underlying value
+    pub(crate) fn from_u128(id: u128) -> Self {
+        Self { id }
+    }
 }
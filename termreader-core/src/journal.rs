@@ -0,0 +1,388 @@
+// This module contains the journal of changes that can be undone, and redone.
+//
+// Only actions that lose something the user may want back go through the journal (see `Action`). Each change keeps
+// what's needed to undo it, holding on to the books involved so that they aren't dropped while it can be undone.
+// The journal isn't saved, so changes can only be undone until the data is closed or replaced.
+
+use crate::{
+    book::{BookRef, ProgressSnapshot},
+    id::ID,
+    sort::BookSort,
+    Context, TRError,
+};
+use std::{collections::VecDeque, fmt::Display};
+
+/// How many changes are kept to be undone
+const MAX_UNDO: usize = 100;
+
+/// An action that can be undone. See `Context::perform`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Reset all progress of a book
+    ResetProgress(ID),
+    /// Remove a book from the library (see `Context::remove_from_lib`)
+    RemoveFromLibrary(ID),
+    /// Delete a library category, moving its books to the default category
+    DeleteCategory(String),
+    /// Remove a book's history entry
+    RemoveHistoryEntry(ID),
+}
+
+/// An action that has been performed, along with what's needed to undo it
+#[derive(Clone, Debug)]
+pub struct Change(Undo);
+
+#[derive(Clone, Debug)]
+enum Undo {
+    ResetProgress {
+        book: BookRef,
+        progress: ProgressSnapshot,
+    },
+    RemoveFromLibrary {
+        book: BookRef,
+        category: Option<String>,
+        position: usize,
+        /// When the book was added to the library, which adding it back would otherwise reset
+        added: u64,
+    },
+    DeleteCategory {
+        name: String,
+        position: usize,
+        books: Vec<BookRef>,
+        sort: Option<BookSort>,
+    },
+    RemoveHistoryEntry {
+        book: BookRef,
+        timestamp: u64,
+        chapter: usize,
+    },
+}
+
+impl Change {
+    /// Returns the action that was performed, to perform it again
+    fn action(&self) -> Action {
+        match &self.0 {
+            Undo::ResetProgress { book, .. } => Action::ResetProgress(book.get_id()),
+            Undo::RemoveFromLibrary { book, .. } => Action::RemoveFromLibrary(book.get_id()),
+            Undo::DeleteCategory { name, .. } => Action::DeleteCategory(name.clone()),
+            Undo::RemoveHistoryEntry { book, .. } => Action::RemoveHistoryEntry(book.get_id()),
+        }
+    }
+
+    /// Returns true if the change involves the book
+    fn involves(&self, id: ID) -> bool {
+        match &self.0 {
+            Undo::ResetProgress { book, .. }
+            | Undo::RemoveFromLibrary { book, .. }
+            | Undo::RemoveHistoryEntry { book, .. } => book.get_id() == id,
+            Undo::DeleteCategory { books, .. } => books.iter().any(|b| b.get_id() == id),
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Undo::ResetProgress { book, .. } => {
+                write!(f, "resetting the progress of \"{}\"", book.get_name())
+            }
+            Undo::RemoveFromLibrary { book, .. } => {
+                write!(f, "removing \"{}\" from the library", book.get_name())
+            }
+            Undo::DeleteCategory { name, .. } => write!(f, "deleting category \"{name}\""),
+            Undo::RemoveHistoryEntry { book, .. } => {
+                write!(f, "removing \"{}\" from the history", book.get_name())
+            }
+        }
+    }
+}
+
+/// The changes that can be undone, and those that have been undone and can be redone
+#[derive(Debug, Default)]
+pub(crate) struct Journal {
+    /// Oldest first
+    undo: VecDeque<Change>,
+    redo: Vec<Change>,
+}
+
+impl Journal {
+    fn record(&mut self, change: Change) {
+        self.redo.clear();
+        self.undo.push_back(change);
+        if self.undo.len() > MAX_UNDO {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Forgets every change, e.g. when the data they refer to has been replaced
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Forgets every change involving the book, so that it can be dropped
+    pub(crate) fn forget_book(&mut self, id: ID) {
+        self.undo.retain(|c| !c.involves(id));
+        self.redo.retain(|c| !c.involves(id));
+    }
+}
+
+impl Context {
+    /// Performs an action, recording it so that it can be undone with `Context::undo`.
+    /// Performing an action forgets any changes that could be redone
+    pub fn perform(&mut self, action: Action) -> Result<(), TRError> {
+        let change = self.apply(action)?;
+        self.journal.record(change);
+        Ok(())
+    }
+
+    /// Undoes the latest change, returning it, or `None` if there's nothing to undo.
+    ///
+    /// Progress made since a reset is kept when it's undone, and books restored to the library or
+    /// to a deleted category are put back where they were. Errors if the change can no longer be undone,
+    /// e.g. when the book has since been added back to the library, in which case it's forgotten
+    pub fn undo(&mut self) -> Result<Option<Change>, TRError> {
+        let Some(change) = self.journal.undo.pop_back() else {
+            return Ok(None);
+        };
+        self.revert(&change)?;
+        self.journal.redo.push(change.clone());
+        Ok(Some(change))
+    }
+
+    /// Performs the latest undone change again, returning it, or `None` if there's nothing to redo
+    pub fn redo(&mut self) -> Result<Option<Change>, TRError> {
+        let Some(change) = self.journal.redo.pop() else {
+            return Ok(None);
+        };
+        let change = self.apply(change.action())?;
+        self.journal.undo.push_back(change.clone());
+        Ok(Some(change))
+    }
+
+    fn apply(&mut self, action: Action) -> Result<Change, TRError> {
+        let undo = match action {
+            Action::ResetProgress(id) => {
+                let Some(mut book) = self.get_book(id) else {
+                    return Err(TRError::BookMissing);
+                };
+                let progress = book.get_progress_snapshot();
+                book.reset_progress();
+                Undo::ResetProgress { book, progress }
+            }
+            Action::RemoveFromLibrary(id) => {
+                let Some(book) = self.get_book(id).filter(|b| b.in_library()) else {
+                    return Err(TRError::BookMissing);
+                };
+                let (category, added) = {
+                    let b = book.read();
+                    (b.category.clone(), b.added_to_library)
+                };
+                let list = category
+                    .as_ref()
+                    .unwrap_or(&self.library.default_category_name);
+                let position = self
+                    .library
+                    .books
+                    .get(list)
+                    .and_then(|l| l.iter().position(|b| b.get_id() == id))
+                    .unwrap_or(0);
+                self.remove_from_lib(id);
+                Undo::RemoveFromLibrary {
+                    book,
+                    category,
+                    position,
+                    added,
+                }
+            }
+            Action::DeleteCategory(name) => {
+                let Some(position) = self.library.category_order.iter().position(|c| c == &name)
+                else {
+                    return Err(TRError::InvalidChoice(format!(
+                        "category \"{name}\" doesn't exist"
+                    )));
+                };
+                let books = self.library.books.get(&name).cloned().unwrap_or_default();
+                let sort = self.library.sort_orders.get(&name).copied();
+                self.library.delete_category(name.clone())?;
+                Undo::DeleteCategory {
+                    name,
+                    position,
+                    books,
+                    sort,
+                }
+            }
+            Action::RemoveHistoryEntry(id) => {
+                let Some(entry) = self.history.history.iter().find(|e| e.get_book_id() == id)
+                else {
+                    return Err(TRError::BookMissing);
+                };
+                let undo = Undo::RemoveHistoryEntry {
+                    book: entry.get_book_ref(),
+                    timestamp: entry.get_timestamp(),
+                    chapter: entry.get_chapter(),
+                };
+                self.remove_history_entry(id);
+                undo
+            }
+        };
+        Ok(Change(undo))
+    }
+
+    fn revert(&mut self, change: &Change) -> Result<(), TRError> {
+        match &change.0 {
+            Undo::ResetProgress { book, progress } => book.merge_progress_snapshot(progress),
+            Undo::RemoveFromLibrary {
+                book,
+                category,
+                position,
+                added,
+            } => {
+                let category = category
+                    .as_ref()
+                    .filter(|c| self.library.books.contains_key(*c));
+                self.add_to_lib(book.get_id(), category.map(|c| c.as_str()))?;
+                book.write().added_to_library = *added;
+                let list = category.unwrap_or(&self.library.default_category_name);
+                let list = self
+                    .library
+                    .books
+                    .get_mut(list)
+                    .expect("the book was added");
+                let book = list.pop().expect("the book was added");
+                list.insert((*position).min(list.len()), book);
+            }
+            Undo::DeleteCategory {
+                name,
+                position,
+                books,
+                sort,
+            } => {
+                self.library.create_category(name.clone())?;
+                if let Some(sort) = sort {
+                    self.library.sort_orders.insert(name.clone(), *sort);
+                }
+                let order = &mut self.library.category_order;
+                let name = order.pop().expect("the category was added");
+                order.insert((*position).min(order.len()), name.clone());
+
+                // Books that have since been moved elsewhere are left where they are
+                let default = self.library.default_category_name.clone();
+                for book in books.iter().filter(|b| b.read().category.is_none()) {
+                    let id = book.get_id();
+                    let default_list = self.library.books.get_mut(&default).unwrap();
+                    let Some(pos) = default_list.iter().position(|b| b.get_id() == id) else {
+                        continue;
+                    };
+                    let book = default_list.remove(pos);
+                    book.write().category = Some(name.clone());
+                    self.library.books.get_mut(&name).unwrap().push(book);
+                }
+            }
+            Undo::RemoveHistoryEntry {
+                book,
+                timestamp,
+                chapter,
+            } => self.history.merge_entry(book.clone(), *timestamp, *chapter),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        book::ChapterProgress,
+        testing::{add_library_book, TestDir},
+    };
+
+    #[test]
+    fn reset_progress_can_be_undone_and_redone() {
+        let dir = TestDir::new("journal-reset");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+        ctx.get_book(id)
+            .unwrap()
+            .global_set_progress(ChapterProgress::Finished, 1)
+            .unwrap();
+        let progress = |ctx: &Context| {
+            ctx.get_book(id)
+                .unwrap()
+                .get_all_chapter_progress()
+                .get(&1)
+                .copied()
+        };
+
+        ctx.perform(Action::ResetProgress(id)).unwrap();
+        assert_eq!(progress(&ctx), None);
+        assert!(ctx.undo().unwrap().is_some());
+        assert!(ctx.undo().unwrap().is_none());
+        assert_eq!(progress(&ctx), Some(ChapterProgress::Finished));
+
+        let change = ctx.redo().unwrap().unwrap();
+        assert!(change.to_string().starts_with("resetting the progress of"));
+        assert_eq!(progress(&ctx), None);
+    }
+
+    #[test]
+    fn performing_an_action_forgets_redo() {
+        let dir = TestDir::new("journal-redo");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+        ctx.perform(Action::ResetProgress(id)).unwrap();
+        ctx.undo().unwrap();
+        ctx.perform(Action::RemoveFromLibrary(id)).unwrap();
+        assert!(ctx.redo().unwrap().is_none());
+    }
+
+    #[test]
+    fn removed_book_is_restored_where_it_was() {
+        let dir = TestDir::new("journal-remove");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+        let added = 1000;
+        ctx.get_book(id).unwrap().write().added_to_library = added;
+        add_library_book(&mut ctx, "Other", 1);
+
+        ctx.perform(Action::RemoveFromLibrary(id)).unwrap();
+        assert!(!ctx.get_book(id).unwrap().in_library());
+        ctx.undo().unwrap();
+        let book = ctx.get_library_books()["Default"][0].clone();
+        assert_eq!(book.get_id(), id);
+        // Otherwise it would be sorted as the book added most recently
+        assert_eq!(book.read().added_to_library, added);
+    }
+
+    #[test]
+    fn deleted_category_is_restored_with_its_books_and_sort() {
+        let dir = TestDir::new("journal-category");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+        ctx.create_library_category(String::from("Reading"))
+            .unwrap();
+        ctx.move_book_category(id, Some("Reading")).unwrap();
+        ctx.set_category_sort("Reading", BookSort::Title).unwrap();
+
+        ctx.perform(Action::DeleteCategory(String::from("Reading")))
+            .unwrap();
+        assert_eq!(ctx.get_category_sort("Reading"), BookSort::Unsorted);
+        ctx.undo().unwrap();
+        assert_eq!(ctx.get_library_books()["Reading"][0].get_id(), id);
+        assert_eq!(ctx.get_category_sort("Reading"), BookSort::Title);
+    }
+
+    #[test]
+    fn removed_history_entry_is_restored() {
+        let dir = TestDir::new("journal-history");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+        ctx.add_history_entry(id);
+
+        ctx.perform(Action::RemoveHistoryEntry(id)).unwrap();
+        assert!(ctx.get_history().is_empty());
+        let change = ctx.undo().unwrap().unwrap();
+        assert!(change.to_string().ends_with("from the history"));
+        assert_eq!(ctx.get_history()[0].get_book_id(), id);
+    }
+}
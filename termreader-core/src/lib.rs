@@ -1,15 +1,33 @@
 #![allow(dead_code, unused_variables)]
+pub mod backup;
 pub mod book;
+pub mod bookmarks;
 mod books_context;
+pub mod export;
+pub mod highlights;
 pub mod history;
 pub mod id;
+pub mod journal;
 mod library;
-mod save;
+pub mod lnreader;
+mod lock;
+mod merge;
+pub mod profile;
+pub mod smart;
+pub mod sort;
 mod sources;
-mod updates;
+pub mod stats;
+pub mod storage;
+mod sync;
+mod tags;
+pub mod timeline;
+pub mod trash;
+pub mod updates;
+pub mod verify;
 
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
 use std::time::SystemTime;
 use std::{collections::VecDeque, time::UNIX_EPOCH};
 
@@ -17,13 +35,17 @@ use crate::books_context::BooksContext;
 use crate::history::HistoryContext;
 use crate::id::ID;
 use crate::library::LibraryContext;
+use crate::lock::DataLock;
 use crate::sources::SourceContext;
+use crate::storage::{SaveData, Storage, StorageBackend};
 use crate::updates::UpdatesContext;
 use book::{Book, BookRef};
 use history::HistoryEntry;
+use stats::renumber_sessions;
 use termreader_sources::sources::{Source, SourceID};
 use thiserror::Error;
-use updates::{UpdatedChapters, UpdatesEntry};
+use timeline::renumber_events;
+use updates::{ChapterDiff, UpdatedChapters, UpdatesEntry};
 
 #[derive(Error, Debug)]
 pub enum TRError {
@@ -31,6 +53,8 @@ pub enum TRError {
     SerializationFailure(#[from] serde_json::Error),
     #[error("IO error: {0}")]
     IOFailure(#[from] std::io::Error),
+    #[error("database error: {0}")]
+    DatabaseFailure(#[from] rusqlite::Error),
     #[error("the same identifier was used more than once")]
     Duplicate,
     #[error("an invalid choice was given: {0}")]
@@ -43,9 +67,25 @@ pub enum TRError {
     BadUse(String),
     #[error("the argument was invalid: {0}")]
     InvalidArgument(String),
+    #[error(
+        "the save data is version {0}, which is newer than this version of termreader supports"
+    )]
+    UnsupportedVersion(u32),
+    #[error("archive error: {0}")]
+    ArchiveFailure(#[from] zip::result::ZipError),
+    #[error("CSV error: {0}")]
+    CsvFailure(#[from] csv::Error),
+    #[error("XML error: {0}")]
+    XmlFailure(#[from] quick_xml::Error),
+    #[error("source error: {0}")]
+    SourceFailure(String),
+    #[error("the data is in use by another instance of termreader ({0})")]
+    Locked(String),
+    #[error("the data was opened read-only, so can't be saved")]
+    ReadOnly,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct Context {
     books: BooksContext,
     library: LibraryContext,
@@ -53,35 +93,166 @@ pub struct Context {
     sources: SourceContext,
     updates: UpdatesContext,
     data_path: PathBuf,
+    /// Only used through `&mut self`, the mutex just lets the `Context` be shared between threads
+    storage: Mutex<Box<dyn Storage>>,
+    backend: StorageBackend,
+    /// The lock on the data directory, or `None` if the data was opened read-only
+    lock: Option<DataLock>,
+    /// The amount of automatic backups to keep
+    auto_backups: usize,
+    /// The state of syncing with other devices, if enabled
+    sync: Option<sync::SyncState>,
+    /// Problems found while loading the data, which are reported by `Context::verify`
+    load_problems: Vec<verify::Problem>,
+    /// How many days books are kept in the trash, or 0 to keep them forever
+    trash_retention_days: u64,
+    /// Changes that can be undone
+    journal: journal::Journal,
+    /// How much of the timeline is kept
+    history_retention: timeline::HistoryRetention,
 }
 
 impl Context {
     /// Build a `Context` from the save files
+    ///
+    /// The storage backend is detected from the existing data, defaulting to JSON.
+    /// Older save data is migrated to the current format as it is loaded
     pub fn build(data_path: PathBuf) -> Result<Self, TRError> {
-        let books = save::load_books(&data_path)?;
-        Ok(Self {
-            library: save::load_library(&data_path, &books)?,
-            history: save::load_history(&data_path, &books)?,
+        let backend = StorageBackend::detect(&data_path);
+        Self::build_with_storage(data_path, backend)
+    }
+
+    /// Build a `Context` from the save files, using the given storage backend
+    ///
+    /// When switching to SQLite from existing JSON data, the JSON data is loaded
+    /// and written to the database on the next save.
+    /// Errors with `TRError::Locked` if another instance is using the data (see `Context::build_read_only`)
+    pub fn build_with_storage(
+        data_path: PathBuf,
+        backend: StorageBackend,
+    ) -> Result<Self, TRError> {
+        let lock = DataLock::acquire(&data_path)?;
+        Self::open(data_path, backend, Some(lock))
+    }
+
+    /// Build a `Context` from the save files without taking the lock on them, e.g. while another instance is using them.
+    ///
+    /// The data can't be saved, but can be reloaded (see `Context::reload`).
+    /// If no storage backend is given, it's detected from the existing data
+    pub fn build_read_only(
+        data_path: PathBuf,
+        backend: Option<StorageBackend>,
+    ) -> Result<Self, TRError> {
+        let backend = backend.unwrap_or_else(|| StorageBackend::detect(&data_path));
+        Self::open(data_path, backend, None)
+    }
+
+    fn open(
+        data_path: PathBuf,
+        backend: StorageBackend,
+        lock: Option<DataLock>,
+    ) -> Result<Self, TRError> {
+        let mut storage = backend.open(&data_path)?;
+        let data = storage.load()?;
+        let mut ctx = Self {
+            books: data.books,
+            library: data.library,
+            history: data.history,
             sources: SourceContext::build(),
-            updates: save::load_updates(&data_path, &books)?,
+            updates: data.updates,
             data_path,
-            books,
-        })
+            storage: Mutex::new(storage),
+            backend,
+            lock,
+            auto_backups: backup::DEFAULT_AUTO_BACKUPS,
+            sync: None,
+            load_problems: data.problems,
+            trash_retention_days: trash::DEFAULT_TRASH_RETENTION_DAYS,
+            journal: journal::Journal::default(),
+            history_retention: timeline::HistoryRetention::default(),
+        };
+        // The trash isn't purged until the retention is set (see `Context::set_trash_retention_days`) or the data
+        // is saved, as books kept for longer than the default would otherwise be lost on every start
+        ctx.log_load_problems();
+        ctx.merge_duplicates();
+        Ok(ctx)
     }
 
-    /// Save a `Context` to files to be loaded later
+    /// Save a `Context` to be loaded later
     ///
-    /// The location where the files are saved is the location you call `Context::build` with
-    pub fn save(self) -> Result<(), TRError> {
-        save::store_history(self.history, &self.data_path)?;
-        save::store_library(self.library, &self.data_path)?;
-        save::store_updates(self.updates, &self.data_path)?;
-        save::store_books(&self.books, &self.data_path)?;
+    /// The location where the data is saved is the location you call `Context::build` with.
+    /// Data is replaced atomically, so existing data is left intact if saving fails part way through.
+    /// This may be called as often as required, e.g. to autosave.
+    /// An automatic backup of the existing data is taken first (see `Context::set_auto_backup_count`),
+    /// and changes are recorded for other devices if syncing is enabled (see `Context::enable_sync`)
+    pub fn save(&mut self) -> Result<(), TRError> {
+        let Some(lock) = self.lock.as_mut() else {
+            return Err(TRError::ReadOnly);
+        };
+        if let Err(e) = lock.refresh() {
+            tracing::error!("failed to refresh the lock on the data: {e}");
+        }
+        self.purge_trash();
+        self.trim_timeline();
+        // A failed backup shouldn't stop the data from being saved
+        if let Err(e) = self.auto_backup() {
+            tracing::error!("failed to take an automatic backup: {e}");
+        }
+        let storage = self
+            .storage
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        storage.store(SaveData {
+            books: &self.books,
+            library: &self.library,
+            history: &self.history,
+            updates: &self.updates,
+        })?;
+        // The data is already saved, so other devices just won't see these changes until the next save
+        if let Err(e) = self.record_sync_changes() {
+            tracing::error!("failed to record changes for syncing: {e}");
+        }
+        Ok(())
+    }
+
+    /// Returns true if the data was opened read-only, as another instance was using it
+    pub fn is_read_only(&self) -> bool {
+        self.lock.is_none()
+    }
 
+    /// Discards the data in memory, and loads it from disk again, e.g. after another instance has changed it.
+    ///
+    /// If the data was opened read-only, the lock is taken if it's now free, so that the data can be saved.
+    /// Errors if the data can't be loaded, in which case the data in memory is kept
+    pub fn reload(&mut self) -> Result<(), TRError> {
+        if self.lock.is_none() {
+            match DataLock::acquire(&self.data_path) {
+                Ok(lock) => self.lock = Some(lock),
+                Err(TRError::Locked(_)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        let mut storage = self.backend.open(&self.data_path)?;
+        let data = storage.load()?;
+        self.books = data.books;
+        self.library = data.library;
+        self.history = data.history;
+        self.updates = data.updates;
+        self.load_problems = data.problems;
+        self.log_load_problems();
+        self.journal.clear();
+        self.storage = Mutex::new(storage);
+        self.merge_duplicates();
         Ok(())
     }
 
-    /// Clear all history data
+    fn log_load_problems(&self) {
+        for problem in self.load_problems.iter() {
+            tracing::warn!("problem found while loading data: {problem}");
+        }
+    }
+
+    /// Clear all history data, including the timeline. Reading sessions are kept for statistics
     pub fn clear_history(&mut self) {
         self.history.clear();
     }
@@ -97,7 +268,7 @@ impl Context {
     pub fn remove_history_entry(&mut self, id: ID) {
         let b = self.books.get(id);
         if let Some(book) = b {
-            book.0.borrow_mut().in_history = false;
+            book.write().in_history = false;
         }
         self.history.remove_entry(id)
     }
@@ -107,7 +278,7 @@ impl Context {
         let b = self.books.get(book);
         match b {
             Some(book) => {
-                book.0.borrow_mut().in_history = true;
+                book.write().in_history = true;
                 self.history.add_entry(book);
             }
             None => (),
@@ -119,26 +290,32 @@ impl Context {
         self.history.history.len()
     }
 
-    /// Remove a book from the user's library
+    /// Remove a book from the user's library, moving it to the trash along with its progress.
+    ///
+    /// It can be restored to the same category with `Context::restore_from_trash`
     pub fn remove_from_lib(&mut self, id: ID) {
         let Some(book) = self.books.get(id) else {
             return;
         };
+        if !book.in_library() {
+            return;
+        }
         self.library.remove_book(id);
 
-        {
-            let mut b = book.0.borrow_mut();
+        let category = {
+            let mut b = book.write();
             b.in_library = false;
-            b.category = None;
-        }
-
-        self.books.remove_unneeded();
+            b.category.take()
+        };
+        self.library
+            .trash
+            .push_front(trash::TrashEntry::new(book, category));
     }
 
     /// Add a book to the user's library
     ///
     /// If a category is provided, the book will be added to that category,
-    /// otherwise, it will be added to the default category.
+    /// otherwise, or if the category doesn't exist, it will be added to the default category.
     ///
     /// Errors if:
     /// - The book is missing from memory
@@ -152,33 +329,29 @@ impl Context {
         if book.in_library() {
             return Err(TRError::Redundant);
         }
+        self.library.trash.retain(|e| e.book.get_id() != id);
+        let category = category.filter(|c| self.library.books.contains_key(*c));
 
         // Set book data
         {
-            let mut b = book.0.borrow_mut();
+            let mut b = book.write();
             b.in_library = true;
             b.category = category.map(|x| x.to_string());
+            b.added_to_library = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("time has gone VERY backwards")
+                .as_secs();
         }
 
         // Set library data
-        match category {
-            None => {
-                let list = self
-                    .library
-                    .books
-                    .get_mut(&self.library.default_category_name)
-                    .expect("the default category should always exist");
-                list.push(book);
-                Ok(())
-            }
-            Some(c) => match self.library.books.get_mut(c) {
-                Some(list) => {
-                    list.push(book);
-                    Ok(())
-                }
-                None => self.add_to_lib(id, None),
-            },
-        }
+        let category = category.unwrap_or(&self.library.default_category_name);
+        let list = self
+            .library
+            .books
+            .get_mut(category)
+            .expect("the category exists, and the default category should always exist");
+        list.push(book);
+        Ok(())
     }
 
     /// Move a book from one category to another
@@ -190,7 +363,7 @@ impl Context {
         };
 
         {
-            let b = book.0.borrow();
+            let b = book.read();
             // Fail if the book isn't in the library
             if !b.in_library {
                 return Err(TRError::BadUse(String::from(
@@ -206,12 +379,16 @@ impl Context {
         // Use the function within `self.library` to prevent the book being cleared from
         // the map before being re-added
         self.library.remove_book(id);
-        {
-            let mut b = book.0.borrow_mut();
+        let added = {
+            let mut b = book.write();
             b.category = None;
             b.in_library = false;
-        }
-        self.add_to_lib(id, category)
+            b.added_to_library
+        };
+        // Moving a book doesn't change when it was added
+        let res = self.add_to_lib(id, category);
+        book.write().added_to_library = added;
+        res
     }
 
     /// Move a category forwards by one in order
@@ -228,9 +405,30 @@ impl Context {
         self.library.reorder_category_backwards(category_idx)
     }
 
+    /// Move a book forwards by one in the order of its category
+    ///
+    /// Returns the new position of the book, or None if the book isn't in the library.
+    pub fn reorder_book_forwards(&mut self, id: ID) -> Option<usize> {
+        self.library.reorder_book_forwards(id)
+    }
+
+    /// Move a book backwards by one in the order of its category
+    ///
+    /// Returns the new position of the book, or None if the book isn't in the library.
+    pub fn reorder_book_backwards(&mut self, id: ID) -> Option<usize> {
+        self.library.reorder_book_backwards(id)
+    }
+
+    /// Move a book to the front of its category
+    ///
+    /// Returns the new position of the book, or None if the book isn't in the library.
+    pub fn reorder_book_to_front(&mut self, id: ID) -> Option<usize> {
+        self.library.reorder_book_to_front(id)
+    }
+
     /// Create a new category in the library
     ///
-    /// Errors if the category name is already in use
+    /// Errors if the category name is already in use, including by a smart category
     pub fn create_library_category(&mut self, name: String) -> Result<(), TRError> {
         self.library.create_category(name)
     }
@@ -244,7 +442,7 @@ impl Context {
 
     /// Rename a library category
     ///
-    /// Errors if the new category name is already in use
+    /// Errors if the new category name is already in use, including by a smart category
     pub fn rename_library_category(
         &mut self,
         old_name: String,
@@ -260,6 +458,11 @@ impl Context {
         &self.library.get_categories()
     }
 
+    /// Returns the name of the category that books are added to when no category is given
+    pub fn get_default_category_name(&self) -> &str {
+        &self.library.default_category_name
+    }
+
     /// Returns a source from it's ID, returning `None` if the source ID is not valid
     pub fn get_source_by_id(&self, source_id: SourceID) -> Option<&Source> {
         self.sources.get_source_by_id(source_id)
@@ -276,12 +479,26 @@ impl Context {
         match b {
             None => None,
             Some(book) => {
-                let source_id = book.0.borrow().global_get_source_id();
+                let source_id = book.read().global_get_source_id();
                 self.get_source_by_id(source_id)
             }
         }
     }
 
+    /// Returns every global book in the library along with its source, e.g. to update them all in a background thread.
+    ///
+    /// Updating a book through its `BookRef` changes it in the `Context` too, but the records kept outside of the book
+    /// are left to the caller to update through `Context::record_update`
+    pub fn get_library_books_with_sources(&self) -> Vec<(BookRef, Source)> {
+        self.library
+            .books
+            .values()
+            .flatten()
+            .filter(|b| b.is_global())
+            .filter_map(|b| Some((b.clone(), self.get_book_source(b.get_id())?.clone())))
+            .collect()
+    }
+
     /// Returns the current save directory
     pub fn get_save_dir(&self) -> PathBuf {
         self.data_path.clone()
@@ -314,6 +531,17 @@ impl Context {
         })
     }
 
+    /// Records a book having been updated through its `BookRef`, moving its history, timeline and reading sessions
+    /// onto the new numbering of its chapters, then adding an updates entry if any chapters were added or changed
+    pub fn record_update(&mut self, book: ID, diff: ChapterDiff) {
+        self.history.renumber_entry(book, &diff);
+        renumber_events(&mut self.history.events, book, &diff);
+        renumber_sessions(&mut self.history.sessions, book, &diff);
+        if !matches!(diff.updated, UpdatedChapters::None) {
+            self.add_updates_entry(book, diff.updated);
+        }
+    }
+
     /// Get the amount of update entries
     pub fn get_updates_entry_count(&self) -> usize {
         self.updates.get_len()
@@ -352,7 +580,7 @@ impl Context {
     /// Replaces the `Book` with the new one
     pub fn replace_book(&mut self, book_id: ID, new_book: Book) {
         if let Some(b) = self.get_book(book_id) {
-            b.0.replace(new_book);
+            *b.write() = new_book;
         }
     }
 
@@ -360,3 +588,127 @@ impl Context {
         &self.library.books
     }
 }
+
+/// Fixtures shared by the tests of each module
+#[cfg(test)]
+pub(crate) mod testing {
+    use crate::{book::Book, id::ID, Context};
+    use std::path::{Path, PathBuf};
+    use termreader_sources::{chapter::ChapterPreview, novel::Novel};
+
+    /// A data directory for a test, which is removed when dropped, even if the test fails
+    pub(crate) struct TestDir(PathBuf);
+
+    impl TestDir {
+        /// Makes a directory unique to the test, removing anything left in it
+        pub(crate) fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("termreader-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+
+        pub(crate) fn path(&self) -> &Path {
+            &self.0
+        }
+
+        /// Opens the data in the directory
+        pub(crate) fn open(&self) -> Context {
+            Context::build(self.0.clone()).unwrap()
+        }
+
+        /// Saves the data, then opens it again, so that tests can check what was kept
+        pub(crate) fn reopen(&self, mut ctx: Context) -> Context {
+            ctx.save().unwrap();
+            drop(ctx);
+            self.open()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Adds a book from a source to the default category of the library, returning its ID. The book's name is also
+    /// used for its URL, so each name is a different book, with chapters named and linked by their number
+    pub(crate) fn add_library_book(ctx: &mut Context, name: &str, chapters: usize) -> ID {
+        // Novels can only be made by the sources crate, so the details are filled in through their serialised form
+        let mut novel = serde_json::to_value(Novel::default()).unwrap();
+        novel["name"] = name.into();
+        novel["novel_url"] = name.into();
+        novel["full_url"] = name.into();
+        let mut novel: Novel = serde_json::from_value(novel).unwrap();
+        novel.set_chapters(
+            (1..=chapters)
+                .map(|ch| {
+                    ChapterPreview::new(ch, format!("Part {ch}"), format!("ch-{ch}"), String::new())
+                })
+                .collect(),
+        );
+        let book = Book::from_novel(novel);
+        let id = book.get_id();
+        ctx.add_book(book);
+        ctx.add_to_lib(id, None).unwrap();
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::ChapterProgress;
+    use crate::testing::{add_library_book, TestDir};
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn books_change_from_other_threads() {
+        assert_send_sync::<Context>();
+        assert_send_sync::<BookRef>();
+
+        let dir = TestDir::new("threads");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+
+        let mut book = ctx.get_book(id).unwrap();
+        thread::spawn(move || {
+            book.global_set_progress(ChapterProgress::Finished, 1)
+                .unwrap()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(
+            ctx.get_book(id).unwrap().get_all_chapter_progress().get(&1),
+            Some(&ChapterProgress::Finished)
+        );
+    }
+
+    #[test]
+    fn books_keep_their_order_in_a_category() {
+        let dir = TestDir::new("order");
+        let mut ctx = dir.open();
+        let mut ids = Vec::new();
+        for name in ["a", "b", "c"] {
+            let path = dir.path().join(format!("{name}.txt"));
+            std::fs::write(&path, name).unwrap();
+            let book = Book::from_local_source(path.to_string_lossy().to_string()).unwrap();
+            ids.push(book.get_id());
+            ctx.add_book(book);
+            ctx.add_to_lib(*ids.last().unwrap(), None).unwrap();
+        }
+
+        assert_eq!(ctx.reorder_book_forwards(ids[0]), Some(0));
+        assert_eq!(ctx.reorder_book_backwards(ids[0]), Some(1));
+        assert_eq!(ctx.reorder_book_to_front(ids[2]), Some(0));
+
+        let ctx = dir.reopen(ctx);
+        let order: Vec<ID> = ctx.get_library_books()["Default"]
+            .iter()
+            .map(|b| b.get_id())
+            .collect();
+        assert_eq!(order, vec![ids[2], ids[1], ids[0]]);
+    }
+}
@@ -1,40 +1,94 @@
-use crate::{book::BookRef, books_context::BooksContext, id::ID, TRError};
+use crate::{
+    book::BookRef,
+    books_context::BooksContext,
+    id::ID,
+    smart::SmartCategory,
+    sort::BookSort,
+    trash::{TrashEntry, TrashEntrySerialize},
+    verify::Problem,
+    TRError,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub(super) struct LibCtxSerialize {
     pub(super) books: HashMap<String, Vec<ID>>,
     pub(super) default_category_name: String,
     pub(super) category_order: Vec<String>,
+    /// Missing from data saved before the trash existed
+    #[serde(default)]
+    pub(super) trash: Vec<TrashEntrySerialize>,
+    /// Missing from data saved before smart categories existed
+    #[serde(default)]
+    pub(super) smart_categories: Vec<SmartCategory>,
+    /// Missing from data saved before categories could be sorted
+    #[serde(default)]
+    pub(super) sort_orders: HashMap<String, BookSort>,
 }
 
 impl LibCtxSerialize {
-    pub(super) fn from_lib_ctx(lib_ctx: LibraryContext) -> Self {
+    pub(super) fn from_lib_ctx(lib_ctx: &LibraryContext) -> Self {
         let books = lib_ctx
             .books
-            .into_iter()
-            .map(|(k, v)| (k, v.into_iter().map(|b| b.get_id()).collect()))
+            .iter()
+            .map(|(k, v)| (k.clone(), v.iter().map(|b| b.get_id()).collect()))
             .collect();
 
         Self {
             books,
-            default_category_name: lib_ctx.default_category_name,
-            category_order: lib_ctx.category_order,
+            default_category_name: lib_ctx.default_category_name.clone(),
+            category_order: lib_ctx.category_order.clone(),
+            trash: lib_ctx
+                .trash
+                .iter()
+                .map(TrashEntrySerialize::from_trash_entry)
+                .collect(),
+            smart_categories: lib_ctx.smart_categories.clone(),
+            sort_orders: lib_ctx.sort_orders.clone(),
         }
     }
 
-    pub(super) fn to_lib_ctx(self, books: &BooksContext) -> LibraryContext {
-        let books = self
+    /// Books that don't exist are left out, and added to the problems found
+    pub(super) fn to_lib_ctx(
+        self,
+        books: &BooksContext,
+        problems: &mut Vec<Problem>,
+    ) -> LibraryContext {
+        let lists = self
             .books
             .into_iter()
-            .map(|(k, v)| (k, v.into_iter().map(|id| books.get(id).unwrap()).collect()))
+            .map(|(k, v)| {
+                let list = v
+                    .into_iter()
+                    .filter_map(|id| {
+                        let book = books.get(id);
+                        if book.is_none() {
+                            problems.push(Problem::DanglingReference {
+                                place: format!("category \"{k}\""),
+                                id,
+                            });
+                        }
+                        book
+                    })
+                    .collect();
+                (k, list)
+            })
+            .collect();
+
+        let trash = self
+            .trash
+            .into_iter()
+            .filter_map(|e| e.into_trash_entry(books, problems))
             .collect();
 
         LibraryContext {
-            books,
+            books: lists,
             default_category_name: self.default_category_name,
             category_order: self.category_order,
+            trash,
+            smart_categories: self.smart_categories,
+            sort_orders: self.sort_orders,
         }
     }
 }
@@ -44,6 +98,12 @@ pub(super) struct LibraryContext {
     pub(super) books: HashMap<String, Vec<BookRef>>,
     pub(super) default_category_name: String,
     pub(super) category_order: Vec<String>,
+    /// Books removed from the library, most recently removed first
+    pub(super) trash: VecDeque<TrashEntry>,
+    /// Categories holding the books that match a query, see `SmartCategory`
+    pub(super) smart_categories: Vec<SmartCategory>,
+    /// How the books of each category and smart category are sorted, if not in the order they were added
+    pub(super) sort_orders: HashMap<String, BookSort>,
 }
 
 impl LibraryContext {
@@ -55,6 +115,9 @@ impl LibraryContext {
             books: map,
             default_category_name: String::from("Default"),
             category_order: vec![String::from("Default")],
+            trash: VecDeque::new(),
+            smart_categories: Vec::new(),
+            sort_orders: HashMap::new(),
         }
     }
 
@@ -96,9 +159,28 @@ impl LibraryContext {
     //     }
     // }
 
+    /// Adds a book to a category, creating the category if it doesn't exist, or to the default category if none is given.
+    ///
+    /// This doesn't update the book itself, so is only for building up data that will be merged into a `Context`
+    pub(super) fn add_to_category(&mut self, book: BookRef, category: Option<String>) {
+        let category = category.unwrap_or_else(|| self.default_category_name.clone());
+        if !self.books.contains_key(&category) {
+            let _ = self.create_category(category.clone());
+        }
+        self.books
+            .get_mut(&category)
+            .expect("the category was just created")
+            .push(book);
+    }
+
+    /// Returns true if a category or smart category has the name
+    pub(super) fn is_name_taken(&self, name: &str) -> bool {
+        self.books.contains_key(name) || self.smart_categories.iter().any(|c| c.get_name() == name)
+    }
+
     pub(super) fn create_category(&mut self, name: String) -> Result<(), TRError> {
-        // Don't allow multiple categories with the same name.
-        if self.books.contains_key(&name) {
+        // Don't allow multiple categories with the same name, including smart categories.
+        if self.is_name_taken(&name) {
             return Err(TRError::Duplicate);
         }
         self.books.insert(name.clone(), Vec::new());
@@ -116,9 +198,10 @@ impl LibraryContext {
 
         if let Some(mut v) = self.books.remove(&name) {
             let new_l = self.books.get_mut(&self.default_category_name).unwrap();
-            v.iter_mut().for_each(|b| b.0.borrow_mut().category = None);
+            v.iter_mut().for_each(|b| b.write().category = None);
             new_l.append(&mut v);
-            self.category_order.retain(|x| x != &name)
+            self.category_order.retain(|x| x != &name);
+            self.sort_orders.remove(&name);
         }
 
         Ok(())
@@ -129,8 +212,8 @@ impl LibraryContext {
         old_name: String,
         new_name: String,
     ) -> Result<(), TRError> {
-        // Don't allow multiple categories with the same name.
-        if self.books.contains_key(&new_name) {
+        // Don't allow multiple categories with the same name, including smart categories.
+        if self.is_name_taken(&new_name) {
             return Err(TRError::Duplicate);
         }
 
@@ -144,6 +227,9 @@ impl LibraryContext {
                     let _ = std::mem::replace(val, new_name.clone());
                 }
             }
+            if let Some(order) = self.sort_orders.remove(&old_name) {
+                self.sort_orders.insert(new_name.clone(), order);
+            }
             self.books.insert(new_name, v);
         }
 
@@ -158,6 +244,41 @@ impl LibraryContext {
         self.books.len()
     }
 
+    /// Returns the list of books that the book is in, and its position in it
+    fn find_book_position(&mut self, id: ID) -> Option<(&mut Vec<BookRef>, usize)> {
+        self.books.values_mut().find_map(|list| {
+            let pos = list.iter().position(|b| b.get_id() == id)?;
+            Some((list, pos))
+        })
+    }
+
+    pub(super) fn reorder_book_forwards(&mut self, id: ID) -> Option<usize> {
+        let (list, pos) = self.find_book_position(id)?;
+        if pos == 0 {
+            // First book so can't move up
+            return Some(0);
+        }
+        list.swap(pos, pos - 1);
+        Some(pos - 1)
+    }
+
+    pub(super) fn reorder_book_backwards(&mut self, id: ID) -> Option<usize> {
+        let (list, pos) = self.find_book_position(id)?;
+        if pos == list.len() - 1 {
+            // Last book so can't move down
+            return Some(pos);
+        }
+        list.swap(pos, pos + 1);
+        Some(pos + 1)
+    }
+
+    pub(super) fn reorder_book_to_front(&mut self, id: ID) -> Option<usize> {
+        let (list, pos) = self.find_book_position(id)?;
+        let book = list.remove(pos);
+        list.insert(0, book);
+        Some(0)
+    }
+
     pub(super) fn reorder_category_forwards(&mut self, move_idx: usize) -> Option<usize> {
         let cat_count = self.category_order.len();
         if move_idx >= cat_count {
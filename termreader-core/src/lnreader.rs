@@ -0,0 +1,358 @@
+// This module contains an importer for backups created by LNReader.
+//
+// An LNReader backup is a zip archive containing:
+// - `Category.json`: a list of categories, each with the IDs of the novels in it
+// - `NovelAndChapters/<id>.json`: a novel, along with all of its chapters and their read state
+
+use crate::{
+    book::{Book, ChapterProgress},
+    books_context::BooksContext,
+    history::HistoryContext,
+    library::LibraryContext,
+    storage::LoadedData,
+    updates::UpdatesContext,
+    Context, TRError,
+};
+use chrono::{Local, NaiveDateTime, TimeZone};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{fs::File, io::Read, path::Path};
+use termreader_sources::{chapter::ChapterPreview, novel::NovelStatus, sources::Source};
+use zip::ZipArchive;
+
+const CATEGORY_FILE: &str = "Category.json";
+const NOVEL_DIR: &str = "NovelAndChapters/";
+
+/// LNReader plugin IDs that can't be matched to a source by name or site
+const PLUGIN_ALIASES: [(&str, &str); 1] = [("fwncom", "freewebnovel")];
+
+/// The result of importing an LNReader backup
+#[derive(Debug, Default)]
+pub struct LNReaderImport {
+    /// The amount of novels that were imported
+    pub imported: usize,
+    /// Novels that weren't imported, as their source isn't supported by termreader
+    pub unsupported: Vec<UnsupportedNovel>,
+}
+
+/// A novel from an LNReader backup that wasn't imported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedNovel {
+    pub name: String,
+    /// The ID of the LNReader plugin the novel is from
+    pub plugin_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LNCategory {
+    name: String,
+    #[serde(default)]
+    sort: i64,
+    #[serde(default)]
+    novel_ids: Vec<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LNNovel {
+    id: i64,
+    path: String,
+    plugin_id: String,
+    name: String,
+    summary: Option<String>,
+    author: Option<String>,
+    status: Option<String>,
+    genres: Option<String>,
+    // Booleans are stored as either booleans or integers, depending on the version of LNReader
+    #[serde(default)]
+    in_library: Value,
+    #[serde(default)]
+    is_local: Value,
+    #[serde(default)]
+    chapters: Vec<LNChapter>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LNChapter {
+    #[serde(default)]
+    id: i64,
+    path: String,
+    name: String,
+    release_time: Option<String>,
+    read_time: Option<String>,
+    #[serde(default)]
+    unread: Value,
+    position: Option<i64>,
+}
+
+impl Context {
+    /// Imports the novels, categories, and reading progress from an LNReader backup.
+    ///
+    /// Novels are matched to sources by their LNReader plugin or site. Novels that are
+    /// already in termreader are merged as when restoring a backup (see `ImportMode::Merge`).
+    /// Novels whose source isn't supported are skipped and returned
+    pub fn import_lnreader_backup(&mut self, path: &Path) -> Result<LNReaderImport, TRError> {
+        let mut zip = ZipArchive::new(File::open(path)?)?;
+
+        let mut categories: Vec<LNCategory> = match read_entry(&mut zip, CATEGORY_FILE)? {
+            Some(data) => serde_json::from_str(&data)?,
+            None => Vec::new(),
+        };
+        categories.sort_by_key(|c| c.sort);
+
+        let names: Vec<String> = zip
+            .file_names()
+            .filter(|n| n.starts_with(NOVEL_DIR) && n.ends_with(".json"))
+            .map(|n| n.to_string())
+            .collect();
+        if names.is_empty() {
+            return Err(TRError::InvalidArgument(String::from(
+                "the archive is not an LNReader backup",
+            )));
+        }
+
+        let mut result = LNReaderImport::default();
+        let mut data = LoadedData {
+            books: BooksContext::new(),
+            library: LibraryContext::new(),
+            history: HistoryContext::new(),
+            updates: UpdatesContext::new(),
+            problems: Vec::new(),
+        };
+
+        for name in names {
+            let novel: LNNovel = match read_entry(&mut zip, &name)? {
+                Some(novel) => serde_json::from_str(&novel)?,
+                None => continue,
+            };
+            let in_library = truthy(&novel.in_library);
+            let last_read = novel
+                .chapters
+                .iter()
+                .filter_map(|c| Some((parse_time(c.read_time.as_ref()?)?, c)))
+                .max_by_key(|(time, _)| *time);
+            // Novels that were only browsed aren't worth keeping
+            if !in_library && last_read.is_none() {
+                continue;
+            }
+
+            let source = if truthy(&novel.is_local) {
+                None
+            } else {
+                self.find_lnreader_source(&novel)
+            };
+            let Some(book) = source.and_then(|s| to_book(s, &novel)) else {
+                result.unsupported.push(UnsupportedNovel {
+                    name: novel.name,
+                    plugin_id: novel.plugin_id,
+                });
+                continue;
+            };
+
+            let id = book.get_id();
+            data.books.add_book(book);
+            let book = data.books.get(id).expect("we just added the book");
+
+            if in_library {
+                let category = categories
+                    .iter()
+                    .find(|c| c.novel_ids.contains(&novel.id))
+                    .map(|c| c.name.clone());
+                // LNReader's default category is named the same as ours
+                data.library.add_to_category(book.clone(), category);
+            }
+            if let Some((timestamp, chapter)) = last_read {
+                let chapter_no = chapter_number(&novel, chapter);
+                data.history.merge_entry(book, timestamp, chapter_no);
+            }
+            result.imported += 1;
+        }
+
+        self.merge_data(data);
+        Ok(result)
+    }
+
+    /// Finds the source of a novel from LNReader, by its plugin ID or its site
+    fn find_lnreader_source(&self, novel: &LNNovel) -> Option<&Source> {
+        let plugin = normalise(&novel.plugin_id);
+        let plugin = PLUGIN_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == plugin)
+            .map_or(plugin.clone(), |(_, name)| name.to_string());
+        self.sources.iter().find(|s| {
+            // The site's domain, without any subdomain or top level domain
+            let site = s
+                .get_base_url()
+                .split("://")
+                .nth(1)
+                .and_then(|host| host.split('/').next())
+                .map(|host| host.trim_start_matches("www."))
+                .and_then(|host| host.rsplit_once('.'))
+                .map(|(site, _)| normalise(site));
+            normalise(&s.get_name()) == plugin
+                || site.is_some_and(|site| site == plugin)
+                || s.is_site_url(&novel.path)
+        })
+    }
+}
+
+/// Creates a book from an LNReader novel, returning `None` if the novel's path isn't valid for the source
+fn to_book(source: &Source, novel: &LNNovel) -> Option<Book> {
+    let (novel_path, _) = source.split_url(&novel.path)?;
+    let mut source_novel = source.novel_from_path(novel_path, novel.name.clone());
+    let status = match novel.status.as_deref() {
+        Some("Ongoing") => NovelStatus::Ongoing,
+        Some("Completed") => NovelStatus::Completed,
+        _ => NovelStatus::Unknown,
+    };
+    source_novel.set_details(
+        novel.author.clone().unwrap_or_default(),
+        status,
+        novel.genres.clone().unwrap_or_default(),
+        novel.summary.clone().unwrap_or_default(),
+    );
+
+    let chapters = ordered_chapters(novel);
+    source_novel.set_chapters(
+        chapters
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                // Chapters that can't be split are most likely stored as the path termreader uses already
+                let url = source
+                    .split_url(&c.path)
+                    .map(|(_, ch)| ch)
+                    .filter(|ch| !ch.is_empty())
+                    .unwrap_or_else(|| c.path.clone());
+                ChapterPreview::new(
+                    i + 1,
+                    c.name.clone(),
+                    url,
+                    c.release_time.clone().unwrap_or_default(),
+                )
+            })
+            .collect(),
+    );
+
+    let mut book = Book::from_novel(source_novel);
+    for (i, chapter) in chapters.iter().enumerate() {
+        if !truthy(&chapter.unread) {
+            let _ = book.global_set_progress(ChapterProgress::Finished, i + 1);
+        }
+    }
+    // Continue from the most recently read chapter
+    if let Some((_, chapter)) = chapters
+        .iter()
+        .filter_map(|c| Some((parse_time(c.read_time.as_ref()?)?, c)))
+        .max_by_key(|(time, _)| *time)
+    {
+        let _ = book.global_set_ch(chapter_number(novel, chapter));
+    }
+    Some(book)
+}
+
+/// Returns the chapters of a novel in reading order
+fn ordered_chapters(novel: &LNNovel) -> Vec<&LNChapter> {
+    let mut chapters: Vec<&LNChapter> = novel.chapters.iter().collect();
+    chapters.sort_by_key(|c| (c.position.unwrap_or_default(), c.id));
+    chapters
+}
+
+/// Returns the termreader chapter number of an LNReader chapter
+fn chapter_number(novel: &LNNovel, chapter: &LNChapter) -> usize {
+    ordered_chapters(novel)
+        .iter()
+        .position(|c| std::ptr::eq(*c, chapter))
+        .map_or(1, |i| i + 1)
+}
+
+fn read_entry(zip: &mut ZipArchive<File>, name: &str) -> Result<Option<String>, TRError> {
+    let mut file = match zip.by_name(name) {
+        Ok(f) => f,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut data = String::new();
+    file.read_to_string(&mut data)?;
+    Ok(Some(data))
+}
+
+/// Parses a time stored by LNReader, which is in local time, into a UNIX timestamp
+fn parse_time(time: &str) -> Option<u64> {
+    let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").ok()?;
+    let time = Local.from_local_datetime(&time).earliest()?;
+    u64::try_from(time.timestamp()).ok()
+}
+
+/// Returns the lowercase alphanumeric characters of a string, so that names can be compared loosely
+fn normalise(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_i64().is_some_and(|n| n != 0),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestDir;
+    use std::io::Write;
+    use zip::{write::FileOptions, ZipWriter};
+
+    #[test]
+    fn import_backup() {
+        let dir = TestDir::new("lnreader-import");
+        std::fs::create_dir_all(dir.path()).unwrap();
+
+        let archive = dir.path().join("lnreader.zip");
+        let mut zip = ZipWriter::new(File::create(&archive).unwrap());
+        let files = [
+            (
+                "Category.json",
+                r#"[{"id":1,"name":"Default","sort":1,"novelIds":[]},{"id":3,"name":"Reading","sort":2,"novelIds":[1]}]"#,
+            ),
+            (
+                "NovelAndChapters/1.json",
+                r#"{"id":1,"path":"novel/some-novel/","pluginId":"boxnovel","name":"Some Novel","inLibrary":1,"isLocal":0,"chapters":[
+                    {"id":10,"path":"novel/some-novel/chapter-1/","name":"Chapter 1","unread":0,"readTime":"2024-01-01 10:00:00","position":0},
+                    {"id":11,"path":"novel/some-novel/chapter-2/","name":"Chapter 2","unread":1,"readTime":null,"position":1}]}"#,
+            ),
+            (
+                "NovelAndChapters/2.json",
+                r#"{"id":2,"path":"/book/other","pluginId":"unknownsite","name":"Other Novel","inLibrary":true,"chapters":[]}"#,
+            ),
+        ];
+        for (name, data) in files {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let mut ctx = dir.open();
+        let result = ctx.import_lnreader_backup(&archive).unwrap();
+        assert_eq!(result.imported, 1);
+        assert_eq!(
+            result.unsupported,
+            vec![UnsupportedNovel {
+                name: String::from("Other Novel"),
+                plugin_id: String::from("unknownsite"),
+            }]
+        );
+
+        let book = ctx.get_library_books()["Reading"][0].clone();
+        assert_eq!(book.get_url().unwrap(), "some-novel");
+        assert_eq!(book.get_chapter_url(2).unwrap(), "chapter-2/");
+        assert_eq!(book.global_get_ordered_chapters().unwrap(), 1);
+        assert_eq!(ctx.get_history_entry_count(), 1);
+    }
+}
@@ -0,0 +1,276 @@
+// This module contains the lock that stops several instances of termreader from writing to the same data directory.
+//
+// Each instance keeps its data in memory and writes all of it when saving, so two instances using the same data
+// would overwrite each other's changes. The lock is a file in the data directory, holding the details of the process
+// that owns it. A lock left behind by a process that has exited (e.g. one that crashed) is stale, and is replaced.
+// The lock is written to a temporary file and then linked into place, so it's never seen before it's complete.
+
+use crate::TRError;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const LOCK_FILE: &str = "termreader.lock";
+/// How long a lock held by a process on another host is kept without being refreshed before it's considered stale.
+/// Whether a process on another host is still running can't be checked directly
+const STALE_AFTER_SECS: u64 = 60 * 60;
+/// How long an unreadable lock is assumed to still be being written, rather than left behind by a crash
+const UNREADABLE_GRACE_SECS: u64 = 5;
+
+/// Details of the process holding a lock
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LockInfo {
+    pid: u32,
+    host: String,
+    /// When the lock was taken, in seconds since the UNIX epoch
+    started: u64,
+    /// When the lock was last refreshed, in seconds since the UNIX epoch
+    refreshed: u64,
+}
+
+impl Display for LockInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let started = chrono::DateTime::from_timestamp(self.started as i64, 0)
+            .map(|t| {
+                t.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        write!(
+            f,
+            "process {} on {}, started {}",
+            self.pid, self.host, started
+        )
+    }
+}
+
+impl LockInfo {
+    fn current() -> Self {
+        let now = now();
+        Self {
+            pid: std::process::id(),
+            host: hostname(),
+            started: now,
+            refreshed: now,
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        match process_exists(self.pid) {
+            Some(exists) if self.host == hostname() => !exists,
+            _ => now().saturating_sub(self.refreshed) > STALE_AFTER_SECS,
+        }
+    }
+}
+
+/// A held lock on a data directory, released when dropped
+#[derive(Debug)]
+pub(crate) struct DataLock {
+    path: PathBuf,
+    info: LockInfo,
+}
+
+impl DataLock {
+    /// Takes the lock on a data directory, replacing it if it's stale.
+    ///
+    /// Errors with `TRError::Locked` if another process holds the lock
+    pub(crate) fn acquire(data_path: &Path) -> Result<Self, TRError> {
+        fs::create_dir_all(data_path)?;
+        let path = data_path.join(LOCK_FILE);
+        let info = LockInfo::current();
+
+        // Try twice, as the first attempt may find a stale lock that then needs replacing
+        for _ in 0..2 {
+            match create_lock(&path, &info) {
+                Ok(()) => return Ok(Self { path, info }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => match read_lock(&path)? {
+                    Some(holder) if !holder.is_stale() => {
+                        return Err(TRError::Locked(holder.to_string()))
+                    }
+                    None if lock_age(&path).is_some_and(|age| age < UNREADABLE_GRACE_SECS) => {
+                        return Err(TRError::Locked(String::from(
+                            "another process is taking the lock",
+                        )))
+                    }
+                    _ => {
+                        tracing::warn!("replacing stale lock at {}", path.display());
+                        match fs::remove_file(&path) {
+                            Ok(()) => (),
+                            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                            Err(e) => return Err(e.into()),
+                        }
+                    }
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(TRError::Locked(String::from(
+            "another process took the lock while a stale lock was being replaced",
+        )))
+    }
+
+    /// Updates the time the lock was last refreshed, so that other hosts don't consider it stale
+    pub(crate) fn refresh(&mut self) -> Result<(), TRError> {
+        self.info.refreshed = now();
+        fs::write(&self.path, serde_json::to_string(&self.info)?)?;
+        Ok(())
+    }
+}
+
+impl Drop for DataLock {
+    fn drop(&mut self) {
+        // Don't remove a lock that was taken over by another process after this one was considered stale
+        if read_lock(&self.path).ok().flatten().as_ref() == Some(&self.info) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Returns true if a file in the data directory is the lock
+pub(crate) fn is_lock_file(path: &Path) -> bool {
+    path == Path::new(LOCK_FILE)
+}
+
+/// Creates the lock file holding the details of this process, erroring with `io::ErrorKind::AlreadyExists` if there
+/// already is one.
+///
+/// The details are written to a temporary file that's then hard linked into place, as linking fails if the lock
+/// exists, so the lock is complete as soon as it exists. Some filesystems don't support hard links, in which case
+/// the lock is created then written, and another process may briefly see it empty (see `UNREADABLE_GRACE_SECS`)
+fn create_lock(path: &Path, info: &LockInfo) -> io::Result<()> {
+    let tmp = path.with_extension(format!("{}.tmp", info.pid));
+    let linked = fs::write(&tmp, serde_json::to_string(info)?)
+        .and_then(|_| File::open(&tmp)?.sync_all())
+        .and_then(|_| fs::hard_link(&tmp, path));
+    let _ = fs::remove_file(&tmp);
+    match linked {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => {
+            tracing::debug!("failed to link the lock into place, writing it directly: {e}");
+            let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+            file.write_all(serde_json::to_string(info)?.as_bytes())?;
+            file.sync_all()
+        }
+        res => res,
+    }
+}
+
+/// Returns how many seconds ago a lock was last written, or `None` if it doesn't exist
+fn lock_age(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    // A lock written in the future is treated as just written
+    Some(modified.elapsed().map_or(0, |age| age.as_secs()))
+}
+
+/// Reads the details of the process holding a lock.
+/// Returns `None` if the lock is unreadable, e.g. if the process holding it crashed while writing it
+fn read_lock(path: &Path) -> Result<Option<LockInfo>, TRError> {
+    match fs::read_to_string(path) {
+        Ok(s) => Ok(serde_json::from_str(&s).ok()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time has gone backwards")
+        .as_secs()
+}
+
+fn hostname() -> String {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .find_map(|p| fs::read_to_string(p).ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+/// Returns whether a process on this host is running, or `None` if that can't be checked
+#[cfg(unix)]
+fn process_exists(pid: u32) -> Option<bool> {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return Some(false);
+    };
+    // Signal 0 only checks whether the process could be signalled
+    // SAFETY: `kill` has no memory safety requirements
+    let res = unsafe { libc::kill(pid, 0) };
+    Some(res == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM))
+}
+
+/// Processes can't be checked without platform specific APIs, so locks are treated like those of other hosts,
+/// going stale once they haven't been refreshed for a while
+#[cfg(not(unix))]
+fn process_exists(_pid: u32) -> Option<bool> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[test]
+    fn lock_is_exclusive() {
+        let dir = TestDir::new("lock-exclusive");
+        let lock = DataLock::acquire(dir.path()).unwrap();
+        assert!(matches!(
+            DataLock::acquire(dir.path()),
+            Err(TRError::Locked(_))
+        ));
+        // Only the lock is left, not the file it was written to
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        drop(lock);
+        assert!(!dir.path().join(LOCK_FILE).exists());
+    }
+
+    #[test]
+    fn stale_locks_are_replaced() {
+        let dir = TestDir::new("lock-stale");
+        fs::create_dir_all(dir.path()).unwrap();
+        let path = dir.path().join(LOCK_FILE);
+
+        // A lock held by a process that no longer exists
+        let stale = LockInfo {
+            pid: u32::MAX,
+            ..LockInfo::current()
+        };
+        fs::write(&path, serde_json::to_string(&stale).unwrap()).unwrap();
+        let lock = DataLock::acquire(dir.path()).unwrap();
+        assert_eq!(read_lock(&path).unwrap(), Some(lock.info.clone()));
+    }
+
+    #[test]
+    fn lock_being_written_is_held() {
+        let dir = TestDir::new("lock-writing");
+        fs::create_dir_all(dir.path()).unwrap();
+        let path = dir.path().join(LOCK_FILE);
+
+        // Another process has created the lock, but not written it yet
+        File::create(&path).unwrap();
+        assert!(matches!(
+            DataLock::acquire(dir.path()),
+            Err(TRError::Locked(_))
+        ));
+        assert!(path.exists());
+
+        // An unreadable lock that's been left for a while was left by a crash
+        let written = SystemTime::now() - std::time::Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(written)
+            .unwrap();
+        assert!(DataLock::acquire(dir.path()).is_ok());
+    }
+}
@@ -0,0 +1,305 @@
+// This module contains the logic for combining data from elsewhere (such as a backup) into a `Context`,
+// and for combining copies of the same book within a `Context`.
+
+use crate::{
+    book::BookRef,
+    bookmarks::merge_bookmarks,
+    highlights::merge_highlights,
+    id::ID,
+    stats::{merge_sessions, rekey_sessions},
+    storage::LoadedData,
+    timeline::{merge_events, rekey_events},
+    Context,
+};
+use std::collections::HashMap;
+
+impl Context {
+    /// Merges loaded data into the context.
+    ///
+    /// - Books that already exist keep their data, with reading progress and tags combined (see `Book::merge_progress`),
+    ///   their reading status kept if they have one, and bookmarks and highlights combined
+    /// - Books that don't exist are added
+    /// - Missing categories and smart categories are created, and books not in the library are added to their category
+    /// - Categories that aren't sorted take the sort order from the other data
+    /// - History and updates entries are combined, keeping the latest history entry for each book,
+    ///   and reading sessions and the timeline are combined, moved to the books they were merged into
+    pub(crate) fn merge_data(&mut self, mut other: LoadedData) {
+        // Categories are created first, so that books can be added to them
+        for category in other.library.category_order.iter() {
+            if !self.library.books.contains_key(category) {
+                let _ = self.library.create_category(category.clone());
+            }
+        }
+        for category in other.library.smart_categories.iter() {
+            let _ = self.create_smart_category(category.clone());
+        }
+        for (category, order) in other.library.sort_orders.iter() {
+            self.library
+                .sort_orders
+                .entry(category.clone())
+                .or_insert(*order);
+        }
+
+        // Map each book in the other data to its copy in this context
+        let mut books: HashMap<ID, BookRef> = HashMap::new();
+        for (id, book) in other.books.books.iter() {
+            let book = book.read();
+            let merged = match self.books.find_matching(&book) {
+                Some(existing) => {
+                    existing.merge_progress(&book);
+                    {
+                        let mut b = existing.write();
+                        b.tags.extend(book.tags.iter().cloned());
+                        b.reading_status = b.reading_status.or(book.reading_status);
+                        merge_bookmarks(&mut b.bookmarks, &book.bookmarks);
+                        merge_highlights(&mut b.highlights, &book.highlights);
+                    }
+                    existing
+                }
+                None => {
+                    let mut new = book.clone();
+                    // These are set again as the book is added to each part of the context
+                    new.in_library = false;
+                    new.in_history = false;
+                    new.in_updates = false;
+                    new.category = None;
+                    self.books.add_book(new);
+                    self.books.get(*id).expect("we just added the book")
+                }
+            };
+            books.insert(*id, merged);
+        }
+
+        for (category, list) in other.library.books.iter() {
+            let category = (category != &other.library.default_category_name).then_some(category);
+            for book in list {
+                let merged = &books[&book.get_id()];
+                if !merged.in_library() {
+                    let _ = self.add_to_lib(merged.get_id(), category.map(|c| c.as_str()));
+                }
+            }
+        }
+
+        for entry in other.history.history.iter() {
+            self.history.merge_entry(
+                BookRef::clone(&books[&entry.get_book_id()]),
+                entry.get_timestamp(),
+                entry.get_chapter(),
+            );
+        }
+
+        let ids: HashMap<ID, ID> = books.iter().map(|(&id, b)| (id, b.get_id())).collect();
+        rekey_sessions(&mut other.history.sessions, &ids);
+        rekey_events(&mut other.history.events, &ids);
+        merge_sessions(&mut self.history.sessions, &other.history.sessions);
+        merge_events(&mut self.history.events, &other.history.events);
+        self.trim_timeline();
+
+        for entry in other.updates.updates.iter() {
+            self.updates.merge_entry(
+                BookRef::clone(&books[&entry.book.get_id()]),
+                entry.timestamp,
+                entry.chapter.clone(),
+            );
+        }
+
+        // Drop the other data first, so that books only it referenced aren't kept alive
+        drop(books);
+        drop(other);
+        self.books.remove_unneeded();
+        self.merge_duplicates();
+    }
+
+    /// Combines copies of the same book into one, and gives every book its stable ID (see `Book::stable_id`).
+    ///
+    /// Copies exist in data from before IDs were stable, e.g. when a book was opened twice from a search.
+    /// A copy in the library is kept in preference to the others, and keeps its category. Reading progress,
+    /// tags, bookmarks and highlights are combined (see `Book::merge_progress`), the latest history entry is kept,
+    /// and updates entries, reading sessions and timeline events are moved to the kept book.
+    ///
+    /// Returns the amount of copies removed
+    pub fn merge_duplicates(&mut self) -> usize {
+        let mut copies: HashMap<ID, Vec<BookRef>> = HashMap::new();
+        for book in self.books.books.values() {
+            let id = book.read().stable_id();
+            copies.entry(id).or_default().push(book.clone());
+        }
+
+        let mut removed = 0;
+        // The ID each book was stored under, mapped to the ID of the book it's now part of
+        let mut ids: HashMap<ID, ID> = HashMap::new();
+        for (id, mut books) in copies {
+            if books.len() == 1 && books[0].get_id() == id {
+                continue;
+            }
+            // Progress is copied between books, and books are stored under a new ID, so the chapters must be loaded
+            let res: Result<Vec<_>, _> = books
+                .iter()
+                .map(|b| b.write().load_chapter_data())
+                .collect();
+            if let Err(e) = res {
+                tracing::error!("failed to load chapters to merge copies of book {id:?}: {e}");
+                continue;
+            }
+            books.sort_by_key(|b| (!b.in_library(), b.get_id() != id));
+            let kept = books.remove(0);
+
+            for copy in books {
+                let copy_id = copy.get_id();
+                ids.insert(copy_id, id);
+                kept.merge_progress(&copy.read());
+                let (tags, status, bookmarks, highlights) = {
+                    let copy = copy.read();
+                    (
+                        copy.tags.clone(),
+                        copy.reading_status,
+                        copy.bookmarks.clone(),
+                        copy.highlights.clone(),
+                    )
+                };
+                {
+                    let mut b = kept.write();
+                    b.tags.extend(tags);
+                    b.reading_status = b.reading_status.or(status);
+                    merge_bookmarks(&mut b.bookmarks, &bookmarks);
+                    merge_highlights(&mut b.highlights, &highlights);
+                }
+
+                let entry = self
+                    .history
+                    .history
+                    .iter()
+                    .find(|e| e.get_book_id() == copy_id);
+                if let Some((timestamp, chapter)) =
+                    entry.map(|e| (e.get_timestamp(), e.get_chapter()))
+                {
+                    self.history.remove_entry(copy_id);
+                    self.history.merge_entry(kept.clone(), timestamp, chapter);
+                }
+                for entry in self.updates.updates.iter_mut() {
+                    if entry.book.get_id() == copy_id {
+                        entry.book = kept.clone();
+                    }
+                }
+                if kept.in_library() {
+                    self.library.trash.retain(|e| e.book.get_id() != copy_id);
+                }
+                for entry in self.library.trash.iter_mut() {
+                    if entry.book.get_id() == copy_id {
+                        entry.book = kept.clone();
+                    }
+                }
+                kept.write().in_updates |= copy.read().in_updates;
+                self.library.remove_book(copy_id);
+                self.books.books.remove(&copy_id);
+                removed += 1;
+            }
+
+            let old_id = kept.get_id();
+            if old_id != id {
+                ids.insert(old_id, id);
+                kept.write().set_id(id);
+                self.books.books.remove(&old_id);
+                self.books.books.insert(id, kept);
+            }
+        }
+        rekey_sessions(&mut self.history.sessions, &ids);
+        rekey_events(&mut self.history.events, &ids);
+        self.rekey_sync_state();
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backup::ImportMode,
+        book::{Book, ChapterProgress},
+        id::ID,
+        testing::TestDir,
+        timeline::HistoryEventKind,
+        Context,
+    };
+    use termreader_sources::novel::Novel;
+
+    /// Adds two copies of a book from before IDs were stable, with the IDs 1 and 2, returning its stable ID.
+    /// Only the first is in the library
+    fn add_copies(ctx: &mut Context) -> ID {
+        let mut first = Book::from_novel(Novel::default());
+        let id = first.get_id();
+        let mut second = first.clone();
+        first.set_id(ID::from_u128(1));
+        second.set_id(ID::from_u128(2));
+        ctx.add_book(first);
+        ctx.add_book(second);
+        ctx.add_to_lib(ID::from_u128(1), None).unwrap();
+        id
+    }
+
+    #[test]
+    fn copies_are_merged() {
+        let dir = TestDir::new("merge-copies");
+        let mut ctx = dir.open();
+        let id = add_copies(&mut ctx);
+        ctx.get_book(ID::from_u128(2))
+            .unwrap()
+            .global_set_progress(ChapterProgress::Finished, 1)
+            .unwrap();
+        ctx.add_history_entry(ID::from_u128(2));
+
+        assert_eq!(ctx.merge_duplicates(), 1);
+        assert_eq!(ctx.books.books.len(), 1);
+        let book = ctx.get_book(id).unwrap();
+        assert!(book.in_library());
+        assert_eq!(
+            book.get_all_chapter_progress().get(&1),
+            Some(&ChapterProgress::Finished)
+        );
+        assert_eq!(ctx.get_history()[0].get_book_id(), id);
+        drop(book);
+
+        let ctx = dir.reopen(ctx);
+        assert!(ctx.get_book(id).unwrap().in_library());
+    }
+
+    #[test]
+    fn sessions_and_timeline_follow_merged_copies() {
+        let dir = TestDir::new("merge-sessions");
+        let mut ctx = dir.open();
+        let id = add_copies(&mut ctx);
+        ctx.record_reading_session(ID::from_u128(1), 1, 100, 200, 50)
+            .unwrap();
+        ctx.log_history_event(ID::from_u128(2), HistoryEventKind::Opened, 0)
+            .unwrap();
+
+        ctx.merge_duplicates();
+        assert_eq!(ctx.get_reading_sessions()[0].get_book_id(), id);
+        assert_eq!(ctx.get_timeline()[0].get_book_id(), id);
+    }
+
+    #[test]
+    fn imported_sessions_follow_the_matching_book() {
+        // A backup from before IDs were stable
+        let old = TestDir::new("merge-import-old");
+        let mut ctx = old.open();
+        ctx.set_auto_backup_count(0);
+        let mut book = Book::from_novel(Novel::default());
+        book.set_id(ID::from_u128(1));
+        ctx.add_book(book);
+        ctx.add_to_lib(ID::from_u128(1), None).unwrap();
+        ctx.record_reading_session(ID::from_u128(1), 1, 100, 200, 50)
+            .unwrap();
+        ctx.log_history_event(ID::from_u128(1), HistoryEventKind::Opened, 0)
+            .unwrap();
+        let archive = ctx.create_backup(&old.path().join("out")).unwrap();
+
+        let dir = TestDir::new("merge-import");
+        let mut ctx = dir.open();
+        let book = Book::from_novel(Novel::default());
+        let id = book.get_id();
+        ctx.add_book(book);
+        ctx.restore_backup(&archive, ImportMode::Merge).unwrap();
+        assert_eq!(ctx.get_reading_sessions()[0].get_book_id(), id);
+        assert_eq!(ctx.get_timeline()[0].get_book_id(), id);
+    }
+}
@@ -0,0 +1,161 @@
+// This module contains profiles, which are separate sets of data (and config) kept in the same data directory.
+//
+// The default profile is stored directly in the data directory, so that data from before profiles existed is kept.
+// Every other profile is stored in its own directory under `profiles/`.
+
+use crate::{
+    books_context::BooksContext, history::HistoryContext, id::ID, library::LibraryContext,
+    storage::LoadedData, updates::UpdatesContext, Context, TRError,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The name of the profile stored directly in the data directory
+pub const DEFAULT_PROFILE: &str = "default";
+/// The directory within the data directory that profiles other than the default are stored in
+pub(crate) const PROFILE_DIR: &str = "profiles";
+
+/// Returns the directory a profile's data is stored in.
+///
+/// Errors if the name isn't valid for a profile. Profile names may contain letters, numbers, `-` and `_`
+pub fn get_profile_path(data_dir: &Path, name: &str) -> Result<PathBuf, TRError> {
+    if name == DEFAULT_PROFILE {
+        return Ok(data_dir.to_path_buf());
+    }
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(TRError::InvalidArgument(format!(
+            "'{name}' is not a valid profile name, only letters, numbers, '-' and '_' are allowed"
+        )));
+    }
+    Ok(data_dir.join(PROFILE_DIR).join(name))
+}
+
+/// Returns the names of all profiles in the data directory, starting with the default profile
+pub fn list_profiles(data_dir: &Path) -> Result<Vec<String>, TRError> {
+    let mut profiles = Vec::new();
+    match fs::read_dir(data_dir.join(PROFILE_DIR)) {
+        Ok(entries) => {
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    profiles.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e.into()),
+    }
+    profiles.sort();
+    profiles.insert(0, String::from(DEFAULT_PROFILE));
+    Ok(profiles)
+}
+
+impl Context {
+    /// Copies a book, along with its reading progress, to the profile stored in `profile_path`, then saves that profile.
+    ///
+    /// The book is added to the same category in the other profile's library (creating it if needed) if it's in the
+    /// library, and its history entry is copied if it has one. If the other profile already has the book,
+    /// reading progress is combined.
+    /// The other profile shouldn't be open elsewhere, as its data would be overwritten
+    pub fn copy_book_to_profile(&self, id: ID, profile_path: &Path) -> Result<(), TRError> {
+        if profile_path == self.data_path {
+            return Err(TRError::Redundant);
+        }
+        let Some(book_ref) = self.books.get(id) else {
+            return Err(TRError::BookMissing);
+        };
+        book_ref.write().load_chapter_data()?;
+        let book = book_ref.read().clone();
+
+        let mut data = LoadedData {
+            books: BooksContext::new(),
+            library: LibraryContext::new(),
+            history: HistoryContext::new(),
+            updates: UpdatesContext::new(),
+            problems: Vec::new(),
+        };
+        let category = book
+            .category
+            .clone()
+            .filter(|c| c != &self.library.default_category_name);
+        let in_library = book.in_library;
+        data.books.add_book(book);
+        let copy = data.books.get(id).expect("we just added the book");
+        if in_library {
+            data.library.add_to_category(copy.clone(), category);
+        }
+        if let Some(entry) = self.history.history.iter().find(|e| e.get_book_id() == id) {
+            data.history
+                .merge_entry(copy, entry.get_timestamp(), entry.get_chapter());
+        }
+
+        let mut other = Context::build(profile_path.to_path_buf())?;
+        other.merge_data(data);
+        other.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::ChapterProgress;
+    use crate::testing::{add_library_book, TestDir};
+
+    #[test]
+    fn profiles_are_kept_in_the_data_directory() {
+        let dir = TestDir::new("profile-names");
+        assert_eq!(
+            get_profile_path(dir.path(), DEFAULT_PROFILE).unwrap(),
+            dir.path()
+        );
+        assert!(get_profile_path(dir.path(), "../work").is_err());
+        assert!(get_profile_path(dir.path(), "").is_err());
+        assert_eq!(list_profiles(dir.path()).unwrap(), vec!["default"]);
+
+        fs::create_dir_all(get_profile_path(dir.path(), "work").unwrap()).unwrap();
+        assert_eq!(list_profiles(dir.path()).unwrap(), vec!["default", "work"]);
+    }
+
+    #[test]
+    fn books_are_copied_between_profiles() {
+        let dir = TestDir::new("profile-copy");
+        let work = get_profile_path(dir.path(), "work").unwrap();
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        ctx.create_library_category(String::from("Reading"))
+            .unwrap();
+        ctx.move_book_category(id, Some("Reading")).unwrap();
+        ctx.get_book(id)
+            .unwrap()
+            .global_set_progress(ChapterProgress::Finished, 1)
+            .unwrap();
+
+        ctx.copy_book_to_profile(id, &work).unwrap();
+        assert_eq!(list_profiles(dir.path()).unwrap(), vec!["default", "work"]);
+
+        let other = Context::build(work).unwrap();
+        let copy = other.get_library_books()["Reading"][0].clone();
+        assert_eq!(copy.get_id(), id);
+        assert_eq!(
+            copy.get_all_chapter_progress().get(&1),
+            Some(&ChapterProgress::Finished)
+        );
+    }
+
+    #[test]
+    fn copying_to_the_same_profile_is_redundant() {
+        let dir = TestDir::new("profile-same");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        assert!(matches!(
+            ctx.copy_book_to_profile(id, dir.path()),
+            Err(TRError::Redundant)
+        ));
+    }
+}
@@ -0,0 +1,331 @@
+// This module contains smart categories, which hold the library books matching a query rather than a set list.
+//
+// Smart categories are shown alongside the categories of the library, but books are never added to them. Instead,
+// their books are found again each time they're asked for, so they always reflect the latest progress and updates.
+// They're stored as part of the library.
+
+use crate::{book::BookRef, id::ID, Context, TRError};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use termreader_sources::novel::NovelStatus;
+
+const SECS_PER_DAY: u64 = 60 * 60 * 24;
+
+/// A condition that books in a smart category must meet
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// The book has chapters that haven't been read
+    Unread,
+    /// New chapters of the book were found within this many days
+    UpdatedWithin(u64),
+    /// The book hasn't been read within this many days, or at all
+    NotOpenedWithin(u64),
+    /// The book is from the source with this name, ignoring case
+    Source(String),
+    /// The book has this status on its source
+    Status(NovelStatus),
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::Unread => write!(f, "unread"),
+            Condition::UpdatedWithin(days) => write!(f, "updated:{days}"),
+            Condition::NotOpenedWithin(days) => write!(f, "unopened:{days}"),
+            Condition::Source(name) => write!(f, "source:{name}"),
+            Condition::Status(status) => write!(f, "status:{status}"),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = TRError;
+
+    /// Parses a condition written as in its `Display` implementation, e.g. `updated:7`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| TRError::InvalidChoice(format!("\"{s}\" {reason}"));
+        let (kind, value) = match s.split_once(':') {
+            Some((kind, value)) => (kind.trim(), Some(value.trim())),
+            None => (s.trim(), None),
+        };
+        let days = || {
+            value
+                .and_then(|v| v.parse::<u64>().ok())
+                .ok_or_else(|| invalid("needs a number of days"))
+        };
+        match kind.to_lowercase().as_str() {
+            "unread" => Ok(Condition::Unread),
+            "updated" => Ok(Condition::UpdatedWithin(days()?)),
+            "unopened" => Ok(Condition::NotOpenedWithin(days()?)),
+            "source" => match value {
+                Some(v) if !v.is_empty() => Ok(Condition::Source(v.to_string())),
+                _ => Err(invalid("needs the name of a source")),
+            },
+            "status" => match value.map(|v| v.to_lowercase()).as_deref() {
+                Some("ongoing") => Ok(Condition::Status(NovelStatus::Ongoing)),
+                Some("completed") => Ok(Condition::Status(NovelStatus::Completed)),
+                Some("unknown") => Ok(Condition::Status(NovelStatus::Unknown)),
+                _ => Err(invalid("needs to be ongoing, completed or unknown")),
+            },
+            _ => Err(invalid("isn't a condition")),
+        }
+    }
+}
+
+/// A category holding every book in the library that meets all of its conditions
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SmartCategory {
+    name: String,
+    conditions: Vec<Condition>,
+}
+
+impl SmartCategory {
+    /// Parses a smart category written as its name, followed by a colon and its conditions separated by commas,
+    /// e.g. `Catching up: unread, updated:7`. See `Condition::from_str` for how conditions are written
+    pub fn parse(input: &str) -> Result<Self, TRError> {
+        let Some((name, conditions)) = input.split_once(':') else {
+            return Err(TRError::InvalidChoice(String::from(
+                "a smart category needs a name, followed by a colon and its conditions",
+            )));
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(TRError::InvalidChoice(String::from(
+                "a smart category needs a name",
+            )));
+        }
+        let conditions = conditions
+            .split(',')
+            .filter(|c| !c.trim().is_empty())
+            .map(Condition::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        if conditions.is_empty() {
+            return Err(TRError::InvalidChoice(String::from(
+                "a smart category needs at least one condition",
+            )));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            conditions,
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_conditions(&self) -> &[Condition] {
+        &self.conditions
+    }
+}
+
+impl Display for SmartCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let conditions: Vec<String> = self.conditions.iter().map(|c| c.to_string()).collect();
+        write!(f, "{}: {}", self.name, conditions.join(", "))
+    }
+}
+
+/// When each book was last read and last updated, in seconds since the UNIX epoch
+pub(crate) struct Activity {
+    pub(crate) opened: HashMap<ID, u64>,
+    pub(crate) updated: HashMap<ID, u64>,
+    pub(crate) now: u64,
+}
+
+impl Condition {
+    fn matches(&self, book: &BookRef, activity: &Activity) -> bool {
+        let id = book.get_id();
+        let within = |timestamp: Option<&u64>, days: u64| {
+            timestamp.is_some_and(|t| t + days * SECS_PER_DAY >= activity.now)
+        };
+        let book = book.read();
+        match self {
+            Condition::Unread => match (book.get_total_chs(), book.global_get_ordered_chapters()) {
+                (Some(total), Ok(read)) => read < total,
+                _ => false,
+            },
+            Condition::UpdatedWithin(days) => within(activity.updated.get(&id), *days),
+            Condition::NotOpenedWithin(days) => !within(activity.opened.get(&id), *days),
+            Condition::Source(name) => {
+                book.is_global()
+                    && book
+                        .global_get_novel()
+                        .get_source_name()
+                        .eq_ignore_ascii_case(name)
+            }
+            Condition::Status(status) => {
+                book.is_global() && book.global_get_novel().get_status() == *status
+            }
+        }
+    }
+}
+
+impl Context {
+    /// Returns the smart categories, in the order they were created
+    pub fn get_smart_categories(&self) -> &Vec<SmartCategory> {
+        &self.library.smart_categories
+    }
+
+    /// Adds a smart category
+    ///
+    /// Errors if a category or smart category with the same name already exists
+    pub fn create_smart_category(&mut self, category: SmartCategory) -> Result<(), TRError> {
+        if self.library.is_name_taken(category.get_name()) {
+            return Err(TRError::Duplicate);
+        }
+        self.library.smart_categories.push(category);
+        Ok(())
+    }
+
+    /// Deletes a smart category. The books in it are left where they are
+    ///
+    /// Errors if the smart category doesn't exist
+    pub fn delete_smart_category(&mut self, name: &str) -> Result<(), TRError> {
+        let Some(pos) = self
+            .library
+            .smart_categories
+            .iter()
+            .position(|c| c.name == name)
+        else {
+            return Err(TRError::InvalidChoice(format!(
+                "smart category \"{name}\" doesn't exist"
+            )));
+        };
+        self.library.smart_categories.remove(pos);
+        self.library.sort_orders.remove(name);
+        Ok(())
+    }
+
+    /// Returns the books in the library that are in a smart category, in the order of the categories they're in.
+    ///
+    /// Returns `None` if the smart category doesn't exist
+    pub fn get_smart_category_books(&self, name: &str) -> Option<Vec<BookRef>> {
+        let category = self
+            .library
+            .smart_categories
+            .iter()
+            .find(|c| c.name == name)?;
+        let activity = self.get_activity();
+
+        let books = self
+            .library
+            .category_order
+            .iter()
+            .filter_map(|c| self.library.books.get(c))
+            .flatten()
+            .filter(|b| category.conditions.iter().all(|c| c.matches(b, &activity)))
+            .cloned()
+            .collect();
+        Some(books)
+    }
+
+    /// Returns when each book was last read and last updated, from the history and updates
+    pub(crate) fn get_activity(&self) -> Activity {
+        let mut activity = Activity {
+            opened: HashMap::new(),
+            updated: HashMap::new(),
+            now: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("time has gone VERY backwards")
+                .as_secs(),
+        };
+        for entry in self.history.history.iter() {
+            let opened = activity.opened.entry(entry.get_book_id()).or_default();
+            *opened = (*opened).max(entry.get_timestamp());
+        }
+        for entry in self.updates.updates.iter() {
+            let updated = activity.updated.entry(entry.book.get_id()).or_default();
+            *updated = (*updated).max(entry.timestamp);
+        }
+        activity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        book::ChapterProgress,
+        testing::{add_library_book, TestDir},
+    };
+
+    #[test]
+    fn smart_categories_are_parsed() {
+        let category = SmartCategory::parse("Catching up: unread, unopened:7").unwrap();
+        assert_eq!(
+            category.get_conditions(),
+            &[Condition::Unread, Condition::NotOpenedWithin(7)]
+        );
+        assert_eq!(category.to_string(), "Catching up: unread, unopened:7");
+        assert!(SmartCategory::parse("Nothing:").is_err());
+        assert!(SmartCategory::parse(": unread").is_err());
+        assert!(SmartCategory::parse("Bad: updated:soon").is_err());
+    }
+
+    #[test]
+    fn smart_categories_hold_matching_books() {
+        let dir = TestDir::new("smart-books");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 2);
+        let category = SmartCategory::parse("Catching up: unread, unopened:7").unwrap();
+        ctx.create_smart_category(category).unwrap();
+
+        let mut ctx = dir.reopen(ctx);
+        assert_eq!(
+            ctx.get_smart_category_books("Catching up").unwrap().len(),
+            1
+        );
+        // Reading the book takes it out of the category
+        ctx.get_book(id)
+            .unwrap()
+            .global_set_progress(ChapterProgress::Finished, 1)
+            .unwrap();
+        ctx.add_history_entry(id);
+        assert!(ctx
+            .get_smart_category_books("Catching up")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn deleting_a_smart_category_leaves_its_books() {
+        let dir = TestDir::new("smart-delete");
+        let mut ctx = dir.open();
+        add_library_book(&mut ctx, "Book", 2);
+        ctx.create_smart_category(SmartCategory::parse("Unread: unread").unwrap())
+            .unwrap();
+        ctx.delete_smart_category("Unread").unwrap();
+        assert!(ctx.get_smart_category_books("Unread").is_none());
+        assert!(ctx.delete_smart_category("Unread").is_err());
+        assert_eq!(ctx.get_library_books()["Default"].len(), 1);
+    }
+
+    #[test]
+    fn categories_and_smart_categories_have_different_names() {
+        let dir = TestDir::new("smart-names");
+        let mut ctx = dir.open();
+        ctx.create_smart_category(SmartCategory::parse("Unread: unread").unwrap())
+            .unwrap();
+        assert!(matches!(
+            ctx.create_smart_category(SmartCategory::parse("Default: unread").unwrap()),
+            Err(TRError::Duplicate)
+        ));
+        assert!(matches!(
+            ctx.create_library_category(String::from("Unread")),
+            Err(TRError::Duplicate)
+        ));
+        ctx.create_library_category(String::from("Reading"))
+            .unwrap();
+        assert!(matches!(
+            ctx.rename_library_category(String::from("Reading"), String::from("Unread")),
+            Err(TRError::Duplicate)
+        ));
+        assert!(ctx.get_library_books().contains_key("Reading"));
+    }
+}
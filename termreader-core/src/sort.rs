@@ -0,0 +1,218 @@
+// This module contains the sorting and filtering of the books shown in a category.
+//
+// Books are kept in each category in the order they were added to it. Sorting doesn't change that order, it's only
+// applied when the books are asked for, so a category can go back to being unsorted. The sort order of each category
+// is stored as part of the library.
+
+use crate::{
+    book::{BookRef, ReadingStatus},
+    smart::Activity,
+    Context, TRError,
+};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, fmt::Display};
+
+/// How the books in a category are sorted. Books that are equal keep the order they were added in
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BookSort {
+    /// The order books were added to the category
+    #[default]
+    Unsorted,
+    /// Alphabetically by name, ignoring case
+    Title,
+    /// Most recently read first, followed by books that haven't been read
+    LastRead,
+    /// Most recently added to the library first
+    DateAdded,
+    /// Most unread chapters first
+    UnreadCount,
+    /// Most recently updated first, followed by books that haven't been updated
+    LatestUpdate,
+    /// Alphabetically by source, then by name
+    Source,
+}
+
+impl BookSort {
+    /// Every sort order, in the order they should be shown
+    pub const ALL: [BookSort; 7] = [
+        BookSort::Unsorted,
+        BookSort::Title,
+        BookSort::LastRead,
+        BookSort::DateAdded,
+        BookSort::UnreadCount,
+        BookSort::LatestUpdate,
+        BookSort::Source,
+    ];
+
+    /// Sorts the books in place
+    fn sort(self, books: &mut [BookRef], activity: &Activity) {
+        let title = |b: &BookRef| b.get_name().to_lowercase();
+        match self {
+            BookSort::Unsorted => (),
+            BookSort::Title => books.sort_by_cached_key(title),
+            BookSort::LastRead => {
+                books.sort_by_cached_key(|b| Reverse(activity.opened.get(&b.get_id()).copied()))
+            }
+            BookSort::DateAdded => books.sort_by_cached_key(|b| Reverse(b.read().added_to_library)),
+            BookSort::UnreadCount => books.sort_by_cached_key(|b| {
+                let book = b.read();
+                let read = book.global_get_ordered_chapters().unwrap_or(0);
+                Reverse(book.get_total_chs().unwrap_or(0).saturating_sub(read))
+            }),
+            BookSort::LatestUpdate => {
+                books.sort_by_cached_key(|b| Reverse(activity.updated.get(&b.get_id()).copied()))
+            }
+            BookSort::Source => books.sort_by_cached_key(|b| (source_name(b), title(b))),
+        }
+    }
+}
+
+impl Display for BookSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            BookSort::Unsorted => "Unsorted",
+            BookSort::Title => "Title",
+            BookSort::LastRead => "Last read",
+            BookSort::DateAdded => "Date added",
+            BookSort::UnreadCount => "Unread chapters",
+            BookSort::LatestUpdate => "Latest update",
+            BookSort::Source => "Source",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Returns the lowercase name of the source a book is from, or "local" for local books
+fn source_name(book: &BookRef) -> String {
+    let book = book.read();
+    if book.is_global() {
+        book.global_get_novel().get_source_name().to_lowercase()
+    } else {
+        String::from("local")
+    }
+}
+
+/// Which books in a category are shown. Every book matches an empty filter
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BookFilter {
+    /// Only books with every one of these tags
+    pub tags: Vec<String>,
+    /// Only books with names containing this, ignoring case
+    pub text: String,
+    /// Only books with this reading status
+    pub status: Option<ReadingStatus>,
+}
+
+impl BookFilter {
+    /// Returns true if the filter lets every book through
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.text.is_empty() && self.status.is_none()
+    }
+
+    /// Returns true if the book passes the filter
+    pub fn matches(&self, book: &BookRef) -> bool {
+        let text = self.text.to_lowercase();
+        book.has_tags(&self.tags)
+            && (text.is_empty() || book.get_name().to_lowercase().contains(&text))
+            && self.status.is_none_or(|s| book.get_reading_status() == s)
+    }
+}
+
+impl Context {
+    /// Returns how the books in a category or smart category are sorted
+    pub fn get_category_sort(&self, category: &str) -> BookSort {
+        self.library
+            .sort_orders
+            .get(category)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sets how the books in a category or smart category are sorted
+    ///
+    /// Errors if the category doesn't exist
+    pub fn set_category_sort(&mut self, category: &str, order: BookSort) -> Result<(), TRError> {
+        let exists = self.library.books.contains_key(category)
+            || self
+                .library
+                .smart_categories
+                .iter()
+                .any(|c| c.get_name() == category);
+        if !exists {
+            return Err(TRError::InvalidChoice(format!(
+                "category \"{category}\" doesn't exist"
+            )));
+        }
+        if order == BookSort::Unsorted {
+            self.library.sort_orders.remove(category);
+        } else {
+            self.library.sort_orders.insert(category.to_string(), order);
+        }
+        Ok(())
+    }
+
+    /// Returns the books in a category or smart category that pass the filter, sorted by the category's sort order.
+    ///
+    /// Returns `None` if the category doesn't exist
+    pub fn get_category_books(&self, category: &str, filter: &BookFilter) -> Option<Vec<BookRef>> {
+        let books = match self.library.books.get(category) {
+            Some(books) => books.clone(),
+            None => self.get_smart_category_books(category)?,
+        };
+        let mut books: Vec<BookRef> = books.into_iter().filter(|b| filter.matches(b)).collect();
+        let order = self.get_category_sort(category);
+        if order != BookSort::Unsorted {
+            order.sort(&mut books, &self.get_activity());
+        }
+        Some(books)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_library_book, TestDir};
+
+    /// Returns the names of the books in the default category that match the text
+    fn names(ctx: &Context, text: &str) -> Vec<String> {
+        let filter = BookFilter {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        ctx.get_category_books("Default", &filter)
+            .unwrap()
+            .iter()
+            .map(|b| b.get_name())
+            .collect()
+    }
+
+    #[test]
+    fn books_are_sorted_and_filtered_by_name() {
+        let dir = TestDir::new("sort-title");
+        let mut ctx = dir.open();
+        for name in ["b", "C", "a"] {
+            add_library_book(&mut ctx, name, 1);
+        }
+        assert_eq!(names(&ctx, ""), vec!["b", "C", "a"]);
+
+        ctx.set_category_sort("Default", BookSort::Title).unwrap();
+        assert_eq!(names(&ctx, ""), vec!["a", "b", "C"]);
+        assert_eq!(names(&ctx, "c"), vec!["C"]);
+    }
+
+    #[test]
+    fn sort_order_is_kept_and_follows_renames() {
+        let dir = TestDir::new("sort-kept");
+        let mut ctx = dir.open();
+        ctx.set_category_sort("Default", BookSort::DateAdded)
+            .unwrap();
+        assert!(ctx.set_category_sort("Missing", BookSort::Title).is_err());
+
+        let mut ctx = dir.reopen(ctx);
+        assert_eq!(ctx.get_category_sort("Default"), BookSort::DateAdded);
+        ctx.rename_library_category(String::from("Default"), String::from("Main"))
+            .unwrap();
+        assert_eq!(ctx.get_category_sort("Main"), BookSort::DateAdded);
+        assert_eq!(ctx.get_category_sort("Default"), BookSort::Unsorted);
+    }
+}
@@ -19,4 +19,8 @@ impl SourceContext {
     pub(super) fn get_source_info(&self) -> Vec<(SourceID, String)> {
         self.sources.get_source_info()
     }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = &Source> {
+        self.sources.iter()
+    }
 }
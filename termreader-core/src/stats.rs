@@ -0,0 +1,395 @@
+// This module contains reading statistics, which are worked out from the sessions spent reading.
+//
+// A session is a stretch of reading a single chapter, recorded by the reader as it's left or the chapter changes.
+// Sessions are kept with the history, but aren't removed with it, and refer to books by ID and name, so that stats
+// still include books that have since been removed.
+
+use crate::{id::ID, updates::ChapterDiff, Context, TRError};
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+/// A stretch of time spent reading a chapter of a book
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ReadingSession {
+    book: ID,
+    /// The name of the book at the time
+    name: String,
+    /// The chapter read, or `None` if it has since been removed from the book
+    chapter: Option<usize>,
+    /// When reading started, in seconds since the UNIX epoch
+    start: u64,
+    /// When reading ended, in seconds since the UNIX epoch
+    end: u64,
+    /// How many words further into the chapter the reader got
+    words: usize,
+}
+
+impl ReadingSession {
+    pub fn get_book_id(&self) -> ID {
+        self.book
+    }
+
+    pub fn get_book_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the chapter read, or `None` if it has since been removed from the book
+    pub fn get_chapter(&self) -> Option<usize> {
+        self.chapter
+    }
+
+    pub fn get_start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn get_end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn get_words(&self) -> usize {
+        self.words
+    }
+
+    /// Returns how long the session lasted, in seconds
+    pub fn get_duration(&self) -> u64 {
+        self.end - self.start
+    }
+
+    /// Returns the local date that the session started on
+    fn get_day(&self) -> NaiveDate {
+        local_date(self.start)
+    }
+}
+
+/// Returns the local date of a time in seconds since the UNIX epoch
+pub(crate) fn local_date(timestamp: u64) -> NaiveDate {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .unwrap_or_default()
+        .with_timezone(&Local)
+        .date_naive()
+}
+
+/// Moves sessions to the IDs their books now have, e.g. after copies of a book were merged
+pub(crate) fn rekey_sessions(list: &mut [ReadingSession], ids: &HashMap<ID, ID>) {
+    for session in list.iter_mut() {
+        if let Some(&id) = ids.get(&session.book) {
+            session.book = id;
+        }
+    }
+}
+
+/// Moves a book's sessions onto the new numbering of its chapters after an update. Sessions in removed chapters are
+/// kept without a chapter, so that the time spent reading still counts towards the book
+pub(crate) fn renumber_sessions(list: &mut [ReadingSession], book: ID, diff: &ChapterDiff) {
+    for session in list.iter_mut() {
+        if session.book == book && session.end <= diff.timestamp {
+            session.chapter = session.chapter.and_then(|ch| diff.renumber(ch));
+        }
+    }
+}
+
+/// Adds sessions to a list ordered by when they started, skipping any that are already in it
+pub(crate) fn merge_sessions(list: &mut Vec<ReadingSession>, other: &[ReadingSession]) {
+    let existing: HashSet<(ID, u64)> = list.iter().map(|s| (s.book, s.start)).collect();
+    list.extend(
+        other
+            .iter()
+            .filter(|s| !existing.contains(&(s.book, s.start)))
+            .cloned(),
+    );
+    list.sort_by_key(|s| s.start);
+}
+
+/// What was read on a day
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DayStats {
+    pub day: NaiveDate,
+    pub words: usize,
+    /// How many different chapters were read
+    pub chapters: usize,
+    /// How long was spent reading, in seconds
+    pub secs: u64,
+}
+
+/// What was read of a book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookStats {
+    pub id: ID,
+    pub name: String,
+    pub words: usize,
+    /// How long was spent reading the book, in seconds
+    pub secs: u64,
+}
+
+/// Statistics worked out from every reading session
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReadingStats {
+    /// Each day that had any reading, oldest first
+    pub days: Vec<DayStats>,
+    /// Each book that has been read, with the most time spent first
+    pub books: Vec<BookStats>,
+    pub total_words: usize,
+    /// How long was spent reading in total, in seconds
+    pub total_secs: u64,
+    /// The most days in a row that had any reading
+    pub longest_streak: usize,
+    /// The days in a row up to today that had any reading. Today isn't counted as missing until it's over
+    pub current_streak: usize,
+}
+
+impl ReadingStats {
+    fn from_sessions(sessions: &[ReadingSession], today: NaiveDate) -> Self {
+        let mut stats = Self::default();
+
+        let mut days: BTreeMap<NaiveDate, (DayStats, HashSet<(ID, usize)>)> = BTreeMap::new();
+        let mut books: HashMap<ID, BookStats> = HashMap::new();
+        for session in sessions {
+            let day = session.get_day();
+            let (day_stats, chapters) = days.entry(day).or_insert_with(|| {
+                let stats = DayStats {
+                    day,
+                    words: 0,
+                    chapters: 0,
+                    secs: 0,
+                };
+                (stats, HashSet::new())
+            });
+            day_stats.words += session.words;
+            day_stats.secs += session.get_duration();
+            if let Some(chapter) = session.chapter {
+                chapters.insert((session.book, chapter));
+            }
+
+            let book = books.entry(session.book).or_insert_with(|| BookStats {
+                id: session.book,
+                name: String::new(),
+                words: 0,
+                secs: 0,
+            });
+            // The latest name is used, as sessions are oldest first
+            book.name = session.name.clone();
+            book.words += session.words;
+            book.secs += session.get_duration();
+
+            stats.total_words += session.words;
+            stats.total_secs += session.get_duration();
+        }
+
+        stats.days = days
+            .into_values()
+            .map(|(mut day, chapters)| {
+                day.chapters = chapters.len();
+                day
+            })
+            .collect();
+        stats.books = books.into_values().collect();
+        stats
+            .books
+            .sort_by(|a, b| b.secs.cmp(&a.secs).then_with(|| a.name.cmp(&b.name)));
+
+        let mut streak = 0;
+        let mut prev: Option<NaiveDate> = None;
+        for day in stats.days.iter().map(|d| d.day) {
+            streak = match prev {
+                Some(prev) if prev.succ_opt() == Some(day) => streak + 1,
+                _ => 1,
+            };
+            stats.longest_streak = stats.longest_streak.max(streak);
+            prev = Some(day);
+        }
+        stats.current_streak = match prev {
+            Some(last) if last == today || last.succ_opt() == Some(today) => streak,
+            _ => 0,
+        };
+        stats
+    }
+
+    /// Returns the average amount of words read per minute, or `None` if no time has been spent reading
+    pub fn words_per_minute(&self) -> Option<f64> {
+        (self.total_secs > 0).then(|| self.total_words as f64 * 60.0 / self.total_secs as f64)
+    }
+}
+
+/// A session in an exported CSV file
+#[derive(Serialize)]
+struct SessionRow<'a> {
+    book: &'a str,
+    chapter: Option<usize>,
+    start: String,
+    end: String,
+    seconds: u64,
+    words: usize,
+}
+
+impl Context {
+    /// Records a session spent reading a chapter of a book, between two times in seconds since the UNIX epoch.
+    /// Sessions where no time passed and nothing was read are ignored
+    ///
+    /// Errors if:
+    /// - The book is missing from memory
+    /// - The session ends before it starts
+    pub fn record_reading_session(
+        &mut self,
+        id: ID,
+        chapter: usize,
+        start: u64,
+        end: u64,
+        words: usize,
+    ) -> Result<(), TRError> {
+        let Some(book) = self.books.get(id) else {
+            return Err(TRError::BookMissing);
+        };
+        if end < start {
+            return Err(TRError::InvalidArgument(String::from(
+                "the session ends before it starts",
+            )));
+        }
+        if end == start && words == 0 {
+            return Ok(());
+        }
+        let session = ReadingSession {
+            book: id,
+            name: book.get_name(),
+            chapter: Some(chapter),
+            start,
+            end,
+            words,
+        };
+        let sessions = &mut self.history.sessions;
+        let pos = sessions.partition_point(|s| s.start <= start);
+        sessions.insert(pos, session);
+        Ok(())
+    }
+
+    /// Returns every reading session, oldest first
+    pub fn get_reading_sessions(&self) -> &[ReadingSession] {
+        &self.history.sessions
+    }
+
+    /// Returns statistics worked out from every reading session
+    pub fn get_reading_stats(&self) -> ReadingStats {
+        ReadingStats::from_sessions(&self.history.sessions, Local::now().date_naive())
+    }
+
+    /// Exports every reading session to a CSV file, with a header row and one row per session.
+    /// Times are written in the local timezone
+    ///
+    /// Returns the amount of sessions exported
+    pub fn export_reading_sessions(&self, path: &Path) -> Result<usize, TRError> {
+        let format_time = |timestamp: u64| {
+            DateTime::from_timestamp(timestamp as i64, 0)
+                .unwrap_or_default()
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        };
+
+        // Write to a temporary file first, so that an existing export is never left incomplete
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        {
+            let mut writer = csv::Writer::from_writer(&mut file);
+            for session in self.history.sessions.iter() {
+                writer.serialize(SessionRow {
+                    book: &session.name,
+                    chapter: session.chapter,
+                    start: format_time(session.start),
+                    end: format_time(session.end),
+                    seconds: session.get_duration(),
+                    words: session.words,
+                })?;
+            }
+            writer.flush()?;
+        }
+        file.flush()?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(self.history.sessions.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_library_book, TestDir};
+
+    const DAY: u64 = 60 * 60 * 24;
+    /// Midday UTC is on the same day everywhere, so adding days to it is always on a different local day
+    const NOON: u64 = 19_000 * DAY + DAY / 2;
+
+    /// Records sessions on the first, second and fourth days after `NOON`
+    fn record_sessions(ctx: &mut Context, id: ID) {
+        for (chapter, start, end, words) in [
+            (1, NOON, NOON + 600, 3000),
+            (2, NOON + 700, NOON + 1300, 3000),
+            (2, NOON + DAY, NOON + DAY + 300, 500),
+            (3, NOON + 3 * DAY, NOON + 3 * DAY + 100, 100),
+        ] {
+            ctx.record_reading_session(id, chapter, start, end, words)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn sessions_are_kept() {
+        let dir = TestDir::new("stats-kept");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        record_sessions(&mut ctx, id);
+
+        let ctx = dir.reopen(ctx);
+        let sessions = ctx.get_reading_sessions();
+        assert_eq!(sessions.len(), 4);
+        assert_eq!(sessions[0].get_book_name(), "Book");
+        assert_eq!(sessions[3].get_duration(), 100);
+    }
+
+    #[test]
+    fn empty_and_backwards_sessions_are_not_recorded() {
+        let dir = TestDir::new("stats-invalid");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        ctx.record_reading_session(id, 3, NOON, NOON, 0).unwrap();
+        assert!(ctx
+            .record_reading_session(id, 3, NOON + DAY, NOON, 10)
+            .is_err());
+        assert!(ctx.get_reading_sessions().is_empty());
+    }
+
+    #[test]
+    fn stats_are_worked_out_from_sessions() {
+        let dir = TestDir::new("stats-days");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        record_sessions(&mut ctx, id);
+
+        let today = local_date(NOON + 4 * DAY);
+        let stats = ReadingStats::from_sessions(ctx.get_reading_sessions(), today);
+        let days: Vec<(usize, usize)> = stats.days.iter().map(|d| (d.words, d.chapters)).collect();
+        assert_eq!(days, vec![(6000, 2), (500, 1), (100, 1)]);
+        assert_eq!(stats.books[0].secs, 1600);
+        assert_eq!(stats.words_per_minute(), Some(6600.0 * 60.0 / 1600.0));
+        assert_eq!(stats.longest_streak, 2);
+        assert_eq!(stats.current_streak, 1);
+    }
+
+    #[test]
+    fn sessions_are_exported_as_csv() {
+        let dir = TestDir::new("stats-export");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        record_sessions(&mut ctx, id);
+
+        let path = dir.path().join("sessions.csv");
+        assert_eq!(ctx.export_reading_sessions(&path).unwrap(), 4);
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert!(csv.starts_with("book,chapter,start,end,seconds,words\n"));
+        assert_eq!(csv.lines().count(), 5);
+    }
+}
@@ -0,0 +1,522 @@
+use super::{ChapterSource, LoadedData, SaveData, Storage};
+use crate::{
+    book::{Book, ChapterProgress},
+    books_context::BooksContext,
+    history::HistCtxSerialize,
+    id::ID,
+    library::LibCtxSerialize,
+    updates::{UpdatesContext, UpdatesCtxSerialize},
+    HistoryContext, LibraryContext, TRError,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use termreader_sources::chapter::ChapterPreview;
+
+/// The version of the save format written by this build of termreader.
+///
+/// This must be increased whenever a change is made that would make older save data unreadable,
+/// with a matching migration added to `MIGRATIONS`.
+const SAVE_VERSION: u32 = 2;
+
+const VERSION_FILE: &str = "version.json";
+const BOOKS_FILE: &str = "books.json";
+const LIBRARY_FILE: &str = "lib.json";
+const HISTORY_FILE: &str = "history.json";
+const UPDATES_FILE: &str = "updates.json";
+/// The directory containing the chapter list and chapter progress of each book, with one file per book
+const BOOK_DATA_DIR: &str = "books";
+
+/// A function that upgrades the save data by a single version
+type Migration = fn(&mut RawSave) -> Result<(), TRError>;
+
+/// All migrations, in order. `MIGRATIONS[n]` upgrades a save from version `n` to version `n + 1`
+const MIGRATIONS: [Migration; SAVE_VERSION as usize] = [migrate_v0_to_v1, migrate_v1_to_v2];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct SaveMeta {
+    version: u32,
+}
+
+/// The contents of the save files before being turned into contexts.
+///
+/// Migrations are performed on this representation, so that they don't depend on the current
+/// layout of any types. A value of `None` means that the file does not exist.
+#[derive(Clone, Debug, Default)]
+struct RawSave {
+    books: Option<Value>,
+    library: Option<Value>,
+    history: Option<Value>,
+    updates: Option<Value>,
+    /// Whether the chapter list and chapter progress of each book is stored in the books file,
+    /// rather than in a file per book
+    chapters_inline: bool,
+}
+
+/// Saves from before versioning was introduced have no version file, but are otherwise identical
+fn migrate_v0_to_v1(_save: &mut RawSave) -> Result<(), TRError> {
+    Ok(())
+}
+
+/// Version 1 stored every book's chapter list and progress in the books file.
+/// These books are loaded in full, and their chapter data is moved to their own files on the next save
+fn migrate_v1_to_v2(save: &mut RawSave) -> Result<(), TRError> {
+    save.chapters_inline = true;
+    Ok(())
+}
+
+/// The data for a single book that's stored in its own file, and loaded on demand
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct BookChapterData<'a> {
+    chapters: Cow<'a, [ChapterPreview]>,
+    progress: Cow<'a, HashMap<usize, ChapterProgress>>,
+}
+
+/// The books file, containing each book without its chapter data
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct BooksSerialize {
+    books: Vec<(ID, Book)>,
+}
+
+/// Loads the chapter list and chapter progress for a book from its own file
+pub(super) fn load_chapter_data(
+    path: &Path,
+) -> Result<(Vec<ChapterPreview>, HashMap<usize, ChapterProgress>), TRError> {
+    match fs::read_to_string(path) {
+        Ok(data) => {
+            let data: BookChapterData = serde_json::from_str(&data)?;
+            Ok((data.chapters.into_owned(), data.progress.into_owned()))
+        }
+        // A book whose data was never written has no chapters or progress
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Default::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns true if the file at the given path, relative to the data directory, is part of a JSON save
+pub(super) fn is_storage_file(path: &Path) -> bool {
+    if path.starts_with(BOOK_DATA_DIR) {
+        return true;
+    }
+    [
+        VERSION_FILE,
+        BOOKS_FILE,
+        LIBRARY_FILE,
+        HISTORY_FILE,
+        UPDATES_FILE,
+    ]
+    .iter()
+    .any(|f| path == Path::new(f))
+}
+
+fn book_data_file(id: ID) -> String {
+    format!("{}.json", id.as_u128())
+}
+
+fn hash_str(s: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    s.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Storage as a set of JSON files
+#[derive(Clone, Debug)]
+pub(crate) struct JsonStorage {
+    path: PathBuf,
+    /// Hashes of the per-book files as they were last written, so that unchanged books aren't rewritten
+    written: HashMap<ID, u64>,
+}
+
+impl JsonStorage {
+    pub(crate) fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            written: HashMap::new(),
+        }
+    }
+
+    /// Returns true if there is any JSON save data in the given directory
+    pub(super) fn exists(path: &Path) -> bool {
+        [VERSION_FILE, BOOKS_FILE, LIBRARY_FILE]
+            .iter()
+            .any(|f| path.join(f).exists())
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load(&mut self) -> Result<LoadedData, TRError> {
+        load(&self.path)
+    }
+
+    fn store(&mut self, data: SaveData<'_>) -> Result<(), TRError> {
+        // Per-book files are written first, so the books file never refers to data that doesn't exist yet
+        let written = store_book_data(data.books, &self.path.join(BOOK_DATA_DIR), &self.written)?;
+        store(
+            data.books,
+            data.library,
+            data.history,
+            data.updates,
+            &self.path,
+        )?;
+        self.written.extend(written);
+        self.written
+            .retain(|id, _| data.books.books.contains_key(id));
+        remove_stale_book_data(data.books, &self.path.join(BOOK_DATA_DIR))
+    }
+}
+
+/// Writes the chapter data of every loaded book that has changed since it was last written.
+///
+/// Returns the hashes of the files that were written
+fn store_book_data(
+    books: &BooksContext,
+    dir: &Path,
+    written: &HashMap<ID, u64>,
+) -> Result<HashMap<ID, u64>, TRError> {
+    let mut files = Vec::new();
+    let mut hashes = HashMap::new();
+    for (id, book) in books.books.iter() {
+        let b = book.read();
+        // Books that haven't been loaded can't have changed
+        let Some((chapters, progress)) = b.get_chapter_data() else {
+            continue;
+        };
+        let json = to_json(&BookChapterData {
+            chapters: Cow::Borrowed(chapters),
+            progress: Cow::Borrowed(progress),
+        })?;
+        let hash = hash_str(&json);
+        if written.get(id) != Some(&hash) {
+            files.push((book_data_file(*id), json));
+            hashes.insert(*id, hash);
+        }
+    }
+
+    let files: Vec<(&str, String)> = files.iter().map(|(n, d)| (n.as_str(), d.clone())).collect();
+    if !files.is_empty() {
+        write_files_atomic(dir, &files)?;
+    }
+    Ok(hashes)
+}
+
+/// Removes the chapter data of books that no longer exist
+fn remove_stale_book_data(books: &BooksContext, dir: &Path) -> Result<(), TRError> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let id = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse::<u128>().ok())
+            .map(ID::from_u128);
+        if id.is_some_and(|id| !books.books.contains_key(&id)) {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Load all data from the save files at the given path, migrating it to the current version if needed
+fn load(path: &Path) -> Result<LoadedData, TRError> {
+    let version = load_version(path)?;
+    if version > SAVE_VERSION {
+        return Err(TRError::UnsupportedVersion(version));
+    }
+
+    let mut raw = RawSave {
+        books: read_json(&path.join(BOOKS_FILE))?,
+        library: read_json(&path.join(LIBRARY_FILE))?,
+        history: read_json(&path.join(HISTORY_FILE))?,
+        updates: read_json(&path.join(UPDATES_FILE))?,
+        chapters_inline: false,
+    };
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut raw)?;
+    }
+
+    let mut books = BooksContext::new();
+    if let Some(v) = raw.books {
+        for (id, mut book) in serde_json::from_value::<BooksSerialize>(v)?.books {
+            if !raw.chapters_inline {
+                let file = path.join(BOOK_DATA_DIR).join(book_data_file(id));
+                book.set_chapter_source(ChapterSource::JsonFile(file));
+            }
+            books.add_book(book);
+        }
+    }
+    let mut problems = Vec::new();
+    let library = match raw.library {
+        Some(v) => serde_json::from_value::<LibCtxSerialize>(v)?.to_lib_ctx(&books, &mut problems),
+        None => LibraryContext::new(),
+    };
+    let history = match raw.history {
+        Some(v) => {
+            serde_json::from_value::<HistCtxSerialize>(v)?.to_hist_ctx(&books, &mut problems)
+        }
+        None => HistoryContext::new(),
+    };
+    let updates = match raw.updates {
+        Some(v) => {
+            serde_json::from_value::<UpdatesCtxSerialize>(v)?.to_updates_ctx(&books, &mut problems)
+        }
+        None => UpdatesContext::new(),
+    };
+
+    Ok(LoadedData {
+        books,
+        library,
+        history,
+        updates,
+        problems,
+    })
+}
+
+/// Store all data to the save files at the given path.
+///
+/// Every file is fully written and synced to a temporary file before any of the existing files
+/// are replaced, so a crash part way through will never leave a truncated file behind.
+fn store(
+    books: &BooksContext,
+    library: &LibraryContext,
+    history: &HistoryContext,
+    updates: &UpdatesContext,
+    path: &Path,
+) -> Result<(), TRError> {
+    // Serialize everything up front so that a serialization failure doesn't write anything
+    let files = [
+        (
+            BOOKS_FILE,
+            to_json(&BooksSerialize {
+                books: books
+                    .books
+                    .iter()
+                    .map(|(id, b)| (*id, b.read().without_chapter_data()))
+                    .collect(),
+            })?,
+        ),
+        (
+            LIBRARY_FILE,
+            to_json(&LibCtxSerialize::from_lib_ctx(library))?,
+        ),
+        (
+            HISTORY_FILE,
+            to_json(&HistCtxSerialize::from_hist_ctx(history))?,
+        ),
+        (
+            UPDATES_FILE,
+            to_json(&UpdatesCtxSerialize::from_updates_ctx(updates))?,
+        ),
+        // The version is written last, as it describes the files written before it
+        (
+            VERSION_FILE,
+            to_json(&SaveMeta {
+                version: SAVE_VERSION,
+            })?,
+        ),
+    ];
+
+    write_files_atomic(path, &files)
+}
+
+fn to_json<T: Serialize + ?Sized>(data: &T) -> Result<String, TRError> {
+    let json = if cfg!(debug_assertions) {
+        serde_json::to_string_pretty(data)?
+    } else {
+        serde_json::to_string(data)?
+    };
+    Ok(json)
+}
+
+/// Reads a JSON file, returning `None` if it does not exist
+fn read_json(path: &Path) -> Result<Option<Value>, TRError> {
+    match fs::read_to_string(path) {
+        Ok(data) => Ok(Some(serde_json::from_str(&data)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns the version of the save at the given path.
+///
+/// Saves without a version file are from before versioning was introduced, so are version 0.
+fn load_version(path: &Path) -> Result<u32, TRError> {
+    match read_json(&path.join(VERSION_FILE))? {
+        Some(v) => Ok(serde_json::from_value::<SaveMeta>(v)?.version),
+        None => Ok(0),
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Writes a set of files to a directory, replacing each one atomically.
+///
+/// All contents are written to temporary files and synced to disk first, then each temporary
+/// file is renamed over the original. Renames are atomic, so each file will either contain the
+/// old contents or the new contents, never a mix of the two.
+pub(crate) fn write_files_atomic(dir: &Path, files: &[(&str, String)]) -> Result<(), TRError> {
+    fs::create_dir_all(dir)?;
+
+    for (name, contents) in files {
+        let mut f = File::create(temp_path(&dir.join(name)))?;
+        f.write_all(contents.as_bytes())?;
+        f.sync_all()?;
+    }
+
+    for (name, _) in files {
+        let target = dir.join(name);
+        fs::rename(temp_path(&target), target)?;
+    }
+
+    sync_dir(dir)
+}
+
+/// Syncs a directory so that renames within it are durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<(), TRError> {
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Directories can't be opened as files on all platforms, in which case renames are left to the OS
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<(), TRError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestDir;
+
+    /// Makes an empty directory for a test to store data in directly
+    fn test_dir(name: &str) -> TestDir {
+        let dir = TestDir::new(&format!("save-{name}"));
+        fs::create_dir_all(dir.path()).unwrap();
+        dir
+    }
+
+    #[test]
+    fn store_then_load() {
+        let dir = test_dir("roundtrip");
+        store(
+            &BooksContext::new(),
+            &LibraryContext::new(),
+            &HistoryContext::new(),
+            &UpdatesContext::new(),
+            dir.path(),
+        )
+        .unwrap();
+
+        assert_eq!(load_version(dir.path()).unwrap(), SAVE_VERSION);
+        let loaded = load(dir.path()).unwrap();
+        assert_eq!(
+            loaded.library.get_categories(),
+            &vec![String::from("Default")]
+        );
+        // No temporary files should be left behind
+        for entry in fs::read_dir(dir.path()).unwrap() {
+            let name = entry.unwrap().file_name();
+            assert!(!name.to_string_lossy().ends_with(".tmp"));
+        }
+    }
+
+    #[test]
+    fn chapter_data_is_loaded_on_demand() {
+        let dir = test_dir("lazy");
+        let mut books = BooksContext::new();
+        let book = Book::from_novel(termreader_sources::novel::Novel::default());
+        let id = book.get_id();
+        books.add_book(book);
+        books
+            .get(id)
+            .unwrap()
+            .global_set_progress(ChapterProgress::Finished, 1)
+            .unwrap();
+
+        let (library, history, updates) = (
+            LibraryContext::new(),
+            HistoryContext::new(),
+            UpdatesContext::new(),
+        );
+        let mut storage = JsonStorage::new(dir.path());
+        storage
+            .store(SaveData {
+                books: &books,
+                library: &library,
+                history: &history,
+                updates: &updates,
+            })
+            .unwrap();
+        assert!(dir
+            .path()
+            .join(BOOK_DATA_DIR)
+            .join(book_data_file(id))
+            .exists());
+
+        // Saving without ever loading the chapter data must not lose it
+        let loaded = JsonStorage::new(dir.path()).load().unwrap();
+        assert!(!loaded.books.get(id).unwrap().read().chapters_loaded());
+        JsonStorage::new(dir.path())
+            .store(SaveData {
+                books: &loaded.books,
+                library: &loaded.library,
+                history: &loaded.history,
+                updates: &loaded.updates,
+            })
+            .unwrap();
+
+        let loaded = JsonStorage::new(dir.path()).load().unwrap();
+        assert_eq!(
+            loaded
+                .books
+                .get(id)
+                .unwrap()
+                .get_all_chapter_progress()
+                .get(&1),
+            Some(&ChapterProgress::Finished)
+        );
+    }
+
+    #[test]
+    fn unversioned_save_is_migrated() {
+        let dir = test_dir("unversioned");
+        fs::write(
+            dir.path().join(LIBRARY_FILE),
+            r#"{"books":{"Default":[],"Other":[]},"default_category_name":"Default","category_order":["Default","Other"]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(load_version(dir.path()).unwrap(), 0);
+        let loaded = load(dir.path()).unwrap();
+        assert_eq!(loaded.library.get_categories().len(), 2);
+    }
+
+    #[test]
+    fn newer_save_is_rejected() {
+        let dir = test_dir("newer");
+        fs::write(
+            dir.path().join(VERSION_FILE),
+            format!(r#"{{"version":{}}}"#, SAVE_VERSION + 1),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            load(dir.path()),
+            Err(TRError::UnsupportedVersion(_))
+        ));
+    }
+}
@@ -0,0 +1,101 @@
+// This module contains the different ways that a `Context` can be persisted to disk.
+mod json;
+mod sqlite;
+
+use crate::{
+    book::ChapterProgress, books_context::BooksContext, history::HistoryContext, id::ID,
+    library::LibraryContext, updates::UpdatesContext, verify::Problem, TRError,
+};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
+use termreader_sources::chapter::ChapterPreview;
+
+pub(crate) use json::{write_files_atomic, JsonStorage};
+pub(crate) use sqlite::SqliteStorage;
+
+/// The available storage backends
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum StorageBackend {
+    /// A set of JSON files, rewritten in full on every save
+    #[default]
+    Json,
+    /// An embedded SQLite database, which is updated incrementally
+    Sqlite,
+}
+
+impl StorageBackend {
+    /// Creates the storage for this backend, storing data in the given directory
+    pub(crate) fn open(self, path: &Path) -> Result<Box<dyn Storage>, TRError> {
+        Ok(match self {
+            StorageBackend::Json => Box::new(JsonStorage::new(path)),
+            StorageBackend::Sqlite => Box::new(SqliteStorage::open(path)?),
+        })
+    }
+
+    /// Guesses the backend in use for a directory, defaulting to JSON if there's no existing data
+    pub fn detect(path: &Path) -> Self {
+        if SqliteStorage::exists(path) {
+            StorageBackend::Sqlite
+        } else {
+            StorageBackend::Json
+        }
+    }
+}
+
+/// Returns true if the file at the given path, relative to the data directory, is managed by a storage backend
+pub(crate) fn is_storage_file(path: &Path) -> bool {
+    json::is_storage_file(path) || sqlite::is_storage_file(path)
+}
+
+/// A way of persisting all data stored in a `Context`
+pub(crate) trait Storage: Debug + Send {
+    /// Load all data, migrating it to the current format if required
+    fn load(&mut self) -> Result<LoadedData, TRError>;
+
+    /// Store all data. Implementations are free to only write what has changed since the last load/store
+    fn store(&mut self, data: SaveData<'_>) -> Result<(), TRError>;
+}
+
+/// All data loaded from storage
+pub(crate) struct LoadedData {
+    pub(crate) books: BooksContext,
+    pub(crate) library: LibraryContext,
+    pub(crate) history: HistoryContext,
+    pub(crate) updates: UpdatesContext,
+    /// Problems found while loading, such as references to books that don't exist
+    pub(crate) problems: Vec<Problem>,
+}
+
+/// References to all data that should be stored
+#[derive(Clone, Copy)]
+pub(crate) struct SaveData<'a> {
+    pub(crate) books: &'a BooksContext,
+    pub(crate) library: &'a LibraryContext,
+    pub(crate) history: &'a HistoryContext,
+    pub(crate) updates: &'a UpdatesContext,
+}
+
+/// Where the chapter list and chapter progress of a book are stored, so that they can be loaded on demand
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ChapterSource {
+    /// A JSON file containing only the data for that book
+    JsonFile(PathBuf),
+    /// An SQLite database
+    Sqlite(PathBuf),
+}
+
+impl ChapterSource {
+    /// Loads the chapter list and chapter progress for the given book
+    pub(crate) fn load(
+        &self,
+        id: ID,
+    ) -> Result<(Vec<ChapterPreview>, HashMap<usize, ChapterProgress>), TRError> {
+        match self {
+            ChapterSource::JsonFile(path) => json::load_chapter_data(path),
+            ChapterSource::Sqlite(path) => sqlite::load_chapter_data(path, id),
+        }
+    }
+}
@@ -0,0 +1,460 @@
+use super::{ChapterSource, JsonStorage, LoadedData, SaveData, Storage};
+use crate::{
+    book::{Book, ChapterProgress},
+    books_context::BooksContext,
+    history::HistCtxSerialize,
+    id::ID,
+    library::LibCtxSerialize,
+    updates::UpdatesCtxSerialize,
+    HistoryContext, LibraryContext, TRError, UpdatesContext,
+};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Transaction};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use termreader_sources::chapter::ChapterPreview;
+
+const DATABASE_FILE: &str = "termreader.db";
+
+/// The version of the database schema written by this build of termreader
+const SCHEMA_VERSION: u32 = 1;
+
+const LIBRARY_DOC: &str = "library";
+const HISTORY_DOC: &str = "history";
+const UPDATES_DOC: &str = "updates";
+
+/// Returns true if the file at the given path, relative to the data directory, is part of the database
+pub(super) fn is_storage_file(path: &Path) -> bool {
+    // This includes the journal files that SQLite keeps next to the database
+    path.components().count() == 1 && path.to_str().is_some_and(|p| p.starts_with(DATABASE_FILE))
+}
+
+/// Storage as an SQLite database.
+///
+/// Chapter lists and chapter progress are stored as individual rows. A copy of the data last
+/// written is kept so that saving only touches the rows that have changed.
+#[derive(Debug)]
+pub(crate) struct SqliteStorage {
+    path: PathBuf,
+    conn: Connection,
+    /// The books as they were last written to/read from the database
+    books: HashMap<ID, StoredBook>,
+    /// Other data (library, history, etc.) as it was last written to/read from the database
+    documents: HashMap<String, String>,
+}
+
+/// The chapter list and chapter progress of a book
+type ChapterData = (Vec<ChapterPreview>, HashMap<usize, ChapterProgress>);
+
+/// A book as it is stored in the database
+#[derive(Debug, Default, PartialEq)]
+struct StoredBook {
+    /// The book as JSON, without its chapters or chapter progress
+    data: String,
+    /// The chapters and chapter progress, if they are known. They aren't known for books whose
+    /// chapter data hasn't been loaded, which also means that it can't have changed
+    chapters: Option<ChapterData>,
+}
+
+impl StoredBook {
+    fn from_book(book: &Book) -> Result<Self, TRError> {
+        Ok(Self {
+            data: serde_json::to_string(&book.without_chapter_data())?,
+            chapters: book
+                .get_chapter_data()
+                .map(|(chapters, progress)| (chapters.clone(), progress.clone())),
+        })
+    }
+}
+
+/// Loads the chapter list and chapter progress for a book from the database in the given file
+pub(super) fn load_chapter_data(path: &Path, id: ID) -> Result<ChapterData, TRError> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    read_chapter_data(&conn, &id.as_u128().to_string())
+}
+
+fn read_chapter_data(conn: &Connection, key: &str) -> Result<ChapterData, TRError> {
+    let mut chapters = Vec::new();
+    let mut stmt =
+        conn.prepare_cached("SELECT data FROM chapters WHERE book_id = ?1 ORDER BY position")?;
+    for row in stmt.query_map([key], |r| r.get::<_, String>(0))? {
+        chapters.push(serde_json::from_str(&row?)?);
+    }
+
+    let mut progress = HashMap::new();
+    let mut stmt =
+        conn.prepare_cached("SELECT chapter_no, progress FROM progress WHERE book_id = ?1")?;
+    let rows = stmt.query_map([key], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))?;
+    for row in rows {
+        let (chapter, data) = row?;
+        progress.insert(chapter as usize, serde_json::from_str(&data)?);
+    }
+
+    Ok((chapters, progress))
+}
+
+impl SqliteStorage {
+    /// Opens (creating if required) the database in the given directory
+    pub(crate) fn open(path: &Path) -> Result<Self, TRError> {
+        std::fs::create_dir_all(path)?;
+        let conn = Connection::open(path.join(DATABASE_FILE))?;
+        // WAL keeps the database consistent if we crash mid-write, and makes small writes cheap
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+
+        let mut storage = Self {
+            path: path.to_path_buf(),
+            conn,
+            books: HashMap::new(),
+            documents: HashMap::new(),
+        };
+        storage.migrate()?;
+        Ok(storage)
+    }
+
+    /// Returns true if a database exists in the given directory
+    pub(super) fn exists(path: &Path) -> bool {
+        path.join(DATABASE_FILE).exists()
+    }
+
+    /// Brings the database schema up to date
+    fn migrate(&mut self) -> Result<(), TRError> {
+        let version: u32 = self
+            .conn
+            .pragma_query_value(None, "user_version", |r| r.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(TRError::UnsupportedVersion(version));
+        }
+
+        if version < 1 {
+            self.conn.execute_batch(
+                "BEGIN;
+                CREATE TABLE books (
+                    id TEXT PRIMARY KEY,
+                    data TEXT NOT NULL
+                );
+                CREATE TABLE chapters (
+                    book_id TEXT NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+                    position INTEGER NOT NULL,
+                    data TEXT NOT NULL,
+                    PRIMARY KEY (book_id, position)
+                );
+                CREATE TABLE progress (
+                    book_id TEXT NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+                    chapter_no INTEGER NOT NULL,
+                    progress TEXT NOT NULL,
+                    PRIMARY KEY (book_id, chapter_no)
+                );
+                CREATE TABLE documents (
+                    name TEXT PRIMARY KEY,
+                    data TEXT NOT NULL
+                );
+                PRAGMA user_version = 1;
+                COMMIT;",
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if nothing has been stored in the database yet
+    fn is_empty(&self) -> Result<bool, TRError> {
+        let book: Option<String> = self
+            .conn
+            .query_row("SELECT id FROM books LIMIT 1", [], |r| r.get(0))
+            .optional()?;
+        let doc: Option<String> = self
+            .conn
+            .query_row("SELECT name FROM documents LIMIT 1", [], |r| r.get(0))
+            .optional()?;
+        Ok(book.is_none() && doc.is_none())
+    }
+
+    /// Loads every book without its chapter data, which is loaded from the database on demand
+    fn load_books(&mut self) -> Result<BooksContext, TRError> {
+        let mut books = BooksContext::new();
+        let mut stored = HashMap::new();
+        let db_path = self.path.join(DATABASE_FILE);
+
+        let mut stmt = self.conn.prepare("SELECT id, data FROM books")?;
+        let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?;
+        for row in rows {
+            let (id, data) = row?;
+            let mut book: Book = serde_json::from_str(&data)?;
+            book.set_chapter_source(ChapterSource::Sqlite(db_path.clone()));
+            books.add_book(book);
+            stored.insert(
+                parse_id(&id)?,
+                StoredBook {
+                    data,
+                    chapters: None,
+                },
+            );
+        }
+
+        self.books = stored;
+        Ok(books)
+    }
+
+    fn load_document(&mut self, name: &str) -> Result<Option<String>, TRError> {
+        let data: Option<String> = self
+            .conn
+            .query_row("SELECT data FROM documents WHERE name = ?1", [name], |r| {
+                r.get(0)
+            })
+            .optional()?;
+        if let Some(d) = &data {
+            self.documents.insert(name.to_string(), d.clone());
+        }
+        Ok(data)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&mut self) -> Result<LoadedData, TRError> {
+        // If we're switching over from JSON storage, start with that data.
+        // Nothing is cached, so it will all be written to the database on the next save.
+        if self.is_empty()? && JsonStorage::exists(&self.path) {
+            let data = JsonStorage::new(&self.path).load()?;
+            // The chapter data is only in the JSON files, so it must be read before it can be moved over
+            for book in data.books.books.values() {
+                book.write().load_chapter_data()?;
+            }
+            return Ok(data);
+        }
+
+        let books = self.load_books()?;
+
+        let mut problems = Vec::new();
+        let library = match self.load_document(LIBRARY_DOC)? {
+            Some(d) => {
+                serde_json::from_str::<LibCtxSerialize>(&d)?.to_lib_ctx(&books, &mut problems)
+            }
+            None => LibraryContext::new(),
+        };
+        let history = match self.load_document(HISTORY_DOC)? {
+            Some(d) => {
+                serde_json::from_str::<HistCtxSerialize>(&d)?.to_hist_ctx(&books, &mut problems)
+            }
+            None => HistoryContext::new(),
+        };
+        let updates = match self.load_document(UPDATES_DOC)? {
+            Some(d) => serde_json::from_str::<UpdatesCtxSerialize>(&d)?
+                .to_updates_ctx(&books, &mut problems),
+            None => UpdatesContext::new(),
+        };
+
+        Ok(LoadedData {
+            books,
+            library,
+            history,
+            updates,
+            problems,
+        })
+    }
+
+    fn store(&mut self, data: SaveData<'_>) -> Result<(), TRError> {
+        let documents = [
+            (
+                LIBRARY_DOC,
+                serde_json::to_string(&LibCtxSerialize::from_lib_ctx(data.library))?,
+            ),
+            (
+                HISTORY_DOC,
+                serde_json::to_string(&HistCtxSerialize::from_hist_ctx(data.history))?,
+            ),
+            (
+                UPDATES_DOC,
+                serde_json::to_string(&UpdatesCtxSerialize::from_updates_ctx(data.updates))?,
+            ),
+        ];
+
+        let mut new_books = HashMap::with_capacity(data.books.books.len());
+        for (id, book) in data.books.books.iter() {
+            let mut stored = StoredBook::from_book(&book.read())?;
+            // Chapter data that hasn't been loaded is unchanged from what was stored before
+            if stored.chapters.is_none() {
+                stored.chapters = self.books.get_mut(id).and_then(|b| b.chapters.take());
+            }
+            new_books.insert(*id, stored);
+        }
+
+        let tx = self.conn.transaction()?;
+
+        // Remove books that no longer exist. Their chapters and progress are removed by cascading.
+        for id in self.books.keys().filter(|id| !new_books.contains_key(id)) {
+            tx.execute(
+                "DELETE FROM books WHERE id = ?1",
+                [id.as_u128().to_string()],
+            )?;
+        }
+
+        for (id, book) in new_books.iter() {
+            store_book(&tx, *id, book, self.books.get(id))?;
+        }
+
+        for (name, doc) in documents.iter() {
+            if self.documents.get(*name) != Some(doc) {
+                tx.execute(
+                    "INSERT INTO documents (name, data) VALUES (?1, ?2)
+                    ON CONFLICT(name) DO UPDATE SET data = excluded.data",
+                    params![name, doc],
+                )?;
+            }
+        }
+
+        tx.commit()?;
+
+        // Only update what we think is stored once it's definitely been stored
+        self.books = new_books;
+        self.documents = documents
+            .into_iter()
+            .map(|(name, doc)| (name.to_string(), doc))
+            .collect();
+        Ok(())
+    }
+}
+
+/// Writes the parts of a book that differ from what was previously stored
+fn store_book(
+    tx: &Transaction,
+    id: ID,
+    book: &StoredBook,
+    previous: Option<&StoredBook>,
+) -> Result<(), TRError> {
+    if previous == Some(book) {
+        return Ok(());
+    }
+    let key = id.as_u128().to_string();
+
+    if previous.map(|p| &p.data) != Some(&book.data) {
+        tx.execute(
+            "INSERT INTO books (id, data) VALUES (?1, ?2)
+            ON CONFLICT(id) DO UPDATE SET data = excluded.data",
+            params![key, book.data],
+        )?;
+    }
+
+    let Some((chapters, progress)) = &book.chapters else {
+        return Ok(());
+    };
+    // If the book's chapter data was loaded after the last store, we don't know what was
+    // previously stored, so read it back to find what changed
+    let read;
+    let old = match previous.and_then(|p| p.chapters.as_ref()) {
+        Some(old) => old,
+        None if previous.is_some() => {
+            read = read_chapter_data(tx, &key)?;
+            &read
+        }
+        None => {
+            read = Default::default();
+            &read
+        }
+    };
+    let (old_chapters, old_progress) = old;
+
+    if old_chapters != chapters {
+        let mut upsert = tx.prepare_cached(
+            "INSERT INTO chapters (book_id, position, data) VALUES (?1, ?2, ?3)
+            ON CONFLICT(book_id, position) DO UPDATE SET data = excluded.data",
+        )?;
+        for (pos, chapter) in chapters.iter().enumerate() {
+            if old_chapters.get(pos) != Some(chapter) {
+                upsert.execute(params![key, pos as i64, serde_json::to_string(chapter)?])?;
+            }
+        }
+        if old_chapters.len() > chapters.len() {
+            tx.execute(
+                "DELETE FROM chapters WHERE book_id = ?1 AND position >= ?2",
+                params![key, chapters.len() as i64],
+            )?;
+        }
+    }
+
+    if old_progress != progress {
+        let mut upsert = tx.prepare_cached(
+            "INSERT INTO progress (book_id, chapter_no, progress) VALUES (?1, ?2, ?3)
+            ON CONFLICT(book_id, chapter_no) DO UPDATE SET progress = excluded.progress",
+        )?;
+        for (chapter, p) in progress.iter() {
+            if old_progress.get(chapter) != Some(p) {
+                upsert.execute(params![key, *chapter as i64, serde_json::to_string(p)?])?;
+            }
+        }
+        let mut delete =
+            tx.prepare_cached("DELETE FROM progress WHERE book_id = ?1 AND chapter_no = ?2")?;
+        for chapter in old_progress.keys() {
+            if !progress.contains_key(chapter) {
+                delete.execute(params![key, *chapter as i64])?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_id(id: &str) -> Result<ID, TRError> {
+    id.parse::<u128>()
+        .map(ID::from_u128)
+        .map_err(|_| TRError::InvalidArgument(format!("invalid book ID in database: {id}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestDir;
+    use termreader_sources::novel::Novel;
+
+    #[test]
+    fn store_then_load() {
+        let dir = TestDir::new("sqlite-roundtrip");
+
+        let mut books = BooksContext::new();
+        let book = Book::from_novel(Novel::default());
+        let id = book.get_id();
+        books.add_book(book);
+        books
+            .get(id)
+            .unwrap()
+            .global_set_progress(ChapterProgress::Finished, 1)
+            .unwrap();
+
+        let (library, history, updates) = (
+            LibraryContext::new(),
+            HistoryContext::new(),
+            UpdatesContext::new(),
+        );
+        let data = SaveData {
+            books: &books,
+            library: &library,
+            history: &history,
+            updates: &updates,
+        };
+        let mut storage = SqliteStorage::open(dir.path()).unwrap();
+        storage.store(data).unwrap();
+        // Storing again with nothing changed should be a no-op
+        storage.store(data).unwrap();
+
+        // Saving without ever loading the chapter data must not lose it
+        let mut storage = SqliteStorage::open(dir.path()).unwrap();
+        let loaded = storage.load().unwrap();
+        assert!(!loaded.books.get(id).unwrap().read().chapters_loaded());
+        storage
+            .store(SaveData {
+                books: &loaded.books,
+                library: &loaded.library,
+                history: &loaded.history,
+                updates: &loaded.updates,
+            })
+            .unwrap();
+
+        let loaded = SqliteStorage::open(dir.path()).unwrap().load().unwrap();
+        let book = loaded.books.get(id).unwrap();
+        assert_eq!(
+            book.get_all_chapter_progress().get(&1),
+            Some(&ChapterProgress::Finished)
+        );
+    }
+}
@@ -0,0 +1,598 @@
+// This module contains syncing data between devices through a shared folder, such as one synced with Syncthing.
+//
+// Sharing the data directory itself loses progress whenever two devices save, as each save replaces the other.
+// Instead, each device appends the changes it makes to its own log in the shared folder, and applies the logs of
+// every other device:
+// - Reading progress is combined, keeping the furthest position and current chapter, and finished chapters stay finished
+// - Library adds, removes, and category moves are applied if they're newer than the last change to the book's category
+
+use crate::{
+    book::{Book, BookRef, ProgressSnapshot},
+    id::ID,
+    storage::write_files_atomic,
+    Context, TRError,
+};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The file within the data directory that the state of syncing is stored in
+const SYNC_STATE_FILE: &str = "sync.json";
+const LOG_EXTENSION: &str = "jsonl";
+
+/// Identifies a book across devices. IDs are kept when books are synced, but a book may have been added on
+/// several devices separately, in which case it's matched by its URL, or for local books, the hash of its file
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+struct BookKey {
+    id: ID,
+    url: Option<String>,
+    hash: Option<String>,
+}
+
+impl BookKey {
+    fn from_book(book: &Book) -> Self {
+        Self {
+            id: book.get_id(),
+            url: book.get_full_url().map(|u| u.to_string()),
+            hash: book.get_local_hash().map(|h| h.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum Change {
+    /// Only the chapters whose progress changed are included
+    Progress {
+        book: BookKey,
+        progress: ProgressSnapshot,
+    },
+    /// The whole book is included, so that devices that don't have it can add it
+    LibraryAdd {
+        book: Box<Book>,
+        category: Option<String>,
+    },
+    LibraryRemove {
+        book: BookKey,
+    },
+    CategoryMove {
+        book: BookKey,
+        category: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct LogEntry {
+    /// Milliseconds since the UNIX epoch
+    timestamp: u64,
+    change: Change,
+}
+
+/// The state of a book when changes were last recorded
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct BookState {
+    key: BookKey,
+    in_library: bool,
+    /// `None` is the default category
+    category: Option<String>,
+    /// When the book was last added, removed, or moved, on any device
+    category_changed: u64,
+    progress: Option<ProgressSnapshot>,
+}
+
+/// The state of syncing for this device
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct SyncState {
+    /// The folder shared between devices
+    dir: PathBuf,
+    /// The name of this device's log in the shared folder
+    device: String,
+    /// The amount of entries that have been applied from each other device's log
+    applied: HashMap<String, usize>,
+    #[serde_as(as = "Vec<(_, _)>")]
+    books: HashMap<ID, BookState>,
+}
+
+impl SyncState {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            device: format!("device-{:x}", ID::generate().as_u128()),
+            applied: HashMap::new(),
+            books: HashMap::new(),
+        }
+    }
+
+    fn log_path(&self) -> PathBuf {
+        self.dir.join(&self.device).with_extension(LOG_EXTENSION)
+    }
+}
+
+impl Context {
+    /// Sync data with other devices through the given folder, which should be shared between them.
+    ///
+    /// From now on, changes are written to the folder whenever the data is saved.
+    /// Other devices' changes are applied by calling `Context::sync`.
+    /// The folder should be separate from the data directory
+    pub fn enable_sync(&mut self, dir: PathBuf) -> Result<(), TRError> {
+        fs::create_dir_all(&dir)?;
+        let state = match fs::read_to_string(self.data_path.join(SYNC_STATE_FILE)) {
+            Ok(s) => {
+                let mut state: SyncState = serde_json::from_str(&s)?;
+                if state.dir != dir {
+                    // A different folder has different logs
+                    state.applied.clear();
+                    state.dir = dir;
+                }
+                state
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => SyncState::new(dir),
+            Err(e) => return Err(e.into()),
+        };
+        self.sync = Some(state);
+        self.rekey_sync_state();
+        Ok(())
+    }
+
+    /// Stop syncing data with other devices
+    pub fn disable_sync(&mut self) {
+        self.sync = None;
+    }
+
+    /// Returns the folder data is synced through, if syncing is enabled
+    pub fn get_sync_dir(&self) -> Option<&Path> {
+        self.sync.as_ref().map(|s| s.dir.as_path())
+    }
+
+    /// Records any changes made on this device, then applies the changes other devices have made since the last sync,
+    /// then saves.
+    ///
+    /// Returns the amount of changes applied. Does nothing if syncing isn't enabled
+    pub fn sync(&mut self) -> Result<usize, TRError> {
+        if self.sync.is_none() {
+            return Ok(0);
+        }
+        self.record_sync_changes()?;
+
+        let Some(state) = self.sync.as_mut() else {
+            return Ok(0);
+        };
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&state.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == LOG_EXTENSION)
+                && path.file_stem().is_some_and(|name| *name != *state.device)
+            {
+                let device = path
+                    .file_stem()
+                    .expect("the file has a name")
+                    .to_string_lossy()
+                    .to_string();
+                let applied = state.applied.entry(device).or_default();
+                entries.append(&mut read_log(&path, applied)?);
+            }
+        }
+        // Changes from different devices should be applied in the order they were made
+        entries.sort_by_key(|e| e.timestamp);
+
+        let count = entries.len();
+        for entry in entries {
+            self.apply_sync_change(entry);
+        }
+        self.books.remove_unneeded();
+        // Books added by other devices may have been added before IDs were stable
+        self.merge_duplicates();
+
+        // Changes from other devices shouldn't be recorded as this device's
+        self.update_sync_state(None);
+        // The applied changes are only marked as applied once they're saved
+        self.save()?;
+        Ok(count)
+    }
+
+    /// Appends any changes made since they were last recorded to this device's log. Called when saving
+    pub(crate) fn record_sync_changes(&mut self) -> Result<(), TRError> {
+        let Some(state) = self.sync.as_ref() else {
+            return Ok(());
+        };
+        let now = now();
+        let mut entries = Vec::new();
+
+        for (id, book) in self.books.books.iter() {
+            let previous = state.books.get(id);
+            let (in_library, category) = self.get_sync_category(book);
+            // Books that have never been in the library aren't synced
+            if previous.is_none() && !in_library {
+                continue;
+            }
+            let key = BookKey::from_book(&book.read());
+
+            match (previous.is_some_and(|p| p.in_library), in_library) {
+                (false, true) => {
+                    // The book should be added with all of its progress
+                    if let Err(e) = book.write().load_chapter_data() {
+                        tracing::error!("failed to load chapters for book {id:?}: {e}");
+                        continue;
+                    }
+                    entries.push(LogEntry {
+                        timestamp: now,
+                        change: Change::LibraryAdd {
+                            book: Box::new(book.read().clone()),
+                            category: category.clone(),
+                        },
+                    });
+                    continue;
+                }
+                (true, false) => entries.push(LogEntry {
+                    timestamp: now,
+                    change: Change::LibraryRemove { book: key.clone() },
+                }),
+                (true, true) if previous.is_some_and(|p| p.category != category) => {
+                    entries.push(LogEntry {
+                        timestamp: now,
+                        change: Change::CategoryMove {
+                            book: key.clone(),
+                            category,
+                        },
+                    })
+                }
+                _ => (),
+            }
+
+            // Progress can only have changed if it's been loaded
+            let book = book.read();
+            if !book.chapters_loaded() {
+                continue;
+            }
+            let progress = book.get_progress_snapshot();
+            if let Some(changed) =
+                changed_progress(previous.and_then(|p| p.progress.as_ref()), progress)
+            {
+                entries.push(LogEntry {
+                    timestamp: now,
+                    change: Change::Progress {
+                        book: key,
+                        progress: changed,
+                    },
+                });
+            }
+        }
+
+        // Books that are no longer stored at all have been removed
+        for (id, previous) in state.books.iter() {
+            if previous.in_library && self.books.get(*id).is_none() {
+                entries.push(LogEntry {
+                    timestamp: now,
+                    change: Change::LibraryRemove {
+                        book: previous.key.clone(),
+                    },
+                });
+            }
+        }
+
+        if !entries.is_empty() {
+            append_log(&state.log_path(), &entries)?;
+        }
+        self.update_sync_state(Some(now));
+        self.store_sync_state()
+    }
+
+    /// Returns whether a book is in the library, and its category. The default category is `None`
+    fn get_sync_category(&self, book: &BookRef) -> (bool, Option<String>) {
+        let book = book.read();
+        let category = book
+            .category
+            .clone()
+            .filter(|c| c != &self.library.default_category_name);
+        (book.in_library, category)
+    }
+
+    /// Updates the recorded state of every book to its current state.
+    /// If a time is given, books whose category has changed are marked as changed at that time
+    fn update_sync_state(&mut self, changed: Option<u64>) {
+        let mut books = HashMap::new();
+        let Some(state) = self.sync.as_ref() else {
+            return;
+        };
+        for (id, book) in self.books.books.iter() {
+            let previous = state.books.get(id);
+            let (in_library, category) = self.get_sync_category(book);
+            if previous.is_none() && !in_library {
+                continue;
+            }
+            let b = book.read();
+            let category_changed = match previous {
+                Some(p) if p.in_library == in_library && p.category == category => {
+                    p.category_changed
+                }
+                _ => changed.unwrap_or_else(|| previous.map_or(0, |p| p.category_changed)),
+            };
+            let progress = if b.chapters_loaded() {
+                Some(b.get_progress_snapshot())
+            } else {
+                previous.and_then(|p| p.progress.clone())
+            };
+            books.insert(
+                *id,
+                BookState {
+                    key: BookKey::from_book(&b),
+                    in_library,
+                    category,
+                    category_changed,
+                    progress,
+                },
+            );
+        }
+        if let Some(state) = self.sync.as_mut() {
+            state.books = books;
+        }
+    }
+
+    fn store_sync_state(&self) -> Result<(), TRError> {
+        let Some(state) = self.sync.as_ref() else {
+            return Ok(());
+        };
+        write_files_atomic(
+            &self.data_path,
+            &[(SYNC_STATE_FILE, serde_json::to_string(state)?)],
+        )
+    }
+
+    /// Moves the recorded state of books whose ID has changed (see `Context::merge_duplicates`) to their new ID,
+    /// so that they aren't seen as removed
+    pub(crate) fn rekey_sync_state(&mut self) {
+        let Some(mut state) = self.sync.take() else {
+            return;
+        };
+        for (id, mut book) in std::mem::take(&mut state.books) {
+            let id = self.find_synced_book(&book.key).map_or(id, |b| b.get_id());
+            book.key.id = id;
+            state.books.insert(id, book);
+        }
+        self.sync = Some(state);
+    }
+
+    /// Finds this device's copy of a book from another device
+    fn find_synced_book(&self, key: &BookKey) -> Option<BookRef> {
+        if let Some(book) = self.books.get(key.id) {
+            return Some(book);
+        }
+        if let Some(url) = &key.url {
+            return self.books.find_book_by_url(url.clone());
+        }
+        let hash = key.hash.as_deref()?;
+        self.books
+            .books
+            .values()
+            .find(|b| b.read().get_local_hash() == Some(hash))
+            .cloned()
+    }
+
+    fn apply_sync_change(&mut self, entry: LogEntry) {
+        let timestamp = entry.timestamp;
+        match entry.change {
+            Change::Progress { book, progress } => {
+                if let Some(book) = self.find_synced_book(&book) {
+                    book.merge_progress_snapshot(&progress);
+                }
+            }
+            Change::LibraryAdd { book, category } => {
+                let existing = self.find_synced_book(&BookKey::from_book(&book));
+                let id = match existing {
+                    Some(existing) => {
+                        existing.merge_progress(&book);
+                        existing.get_id()
+                    }
+                    None => {
+                        let mut new = *book;
+                        new.in_library = false;
+                        new.in_history = false;
+                        new.in_updates = false;
+                        new.category = None;
+                        let id = new.get_id();
+                        self.books.add_book(new);
+                        id
+                    }
+                };
+                if self.category_change_is_newer(id, timestamp) {
+                    self.sync_category(id, Some(category));
+                }
+            }
+            Change::LibraryRemove { book } => {
+                if let Some(book) = self.find_synced_book(&book) {
+                    if self.category_change_is_newer(book.get_id(), timestamp) {
+                        self.sync_category(book.get_id(), None);
+                    }
+                }
+            }
+            Change::CategoryMove { book, category } => {
+                if let Some(book) = self.find_synced_book(&book) {
+                    if book.in_library() && self.category_change_is_newer(book.get_id(), timestamp)
+                    {
+                        self.sync_category(book.get_id(), Some(category));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if a change made at the given time is newer than the last change to the book's category.
+    /// If so, the book's category is marked as changed at that time
+    fn category_change_is_newer(&mut self, id: ID, timestamp: u64) -> bool {
+        let Some(state) = self.sync.as_mut() else {
+            return false;
+        };
+        match state.books.get_mut(&id) {
+            Some(book) if book.category_changed >= timestamp => false,
+            Some(book) => {
+                book.category_changed = timestamp;
+                true
+            }
+            None => {
+                // `update_sync_state` fills in the rest once the change has been applied
+                if let Some(book) = self.books.get(id) {
+                    state.books.insert(
+                        id,
+                        BookState {
+                            key: BookKey::from_book(&book.read()),
+                            in_library: false,
+                            category: None,
+                            category_changed: timestamp,
+                            progress: None,
+                        },
+                    );
+                }
+                true
+            }
+        }
+    }
+
+    /// Puts a book in a category of the library, or removes it from the library if no category is given.
+    /// Within a category, `None` is the default category
+    fn sync_category(&mut self, id: ID, category: Option<Option<String>>) {
+        let Some(category) = category else {
+            self.remove_from_lib(id);
+            return;
+        };
+        if let Some(c) = &category {
+            if !self.library.books.contains_key(c) {
+                let _ = self.library.create_category(c.clone());
+            }
+        }
+        let res = match self.books.get(id) {
+            Some(book) if book.in_library() => self.move_book_category(id, category.as_deref()),
+            _ => self.add_to_lib(id, category.as_deref()),
+        };
+        if let Err(e) = res {
+            if !matches!(e, TRError::Redundant) {
+                tracing::error!("failed to sync the category of book {id:?}: {e}");
+            }
+        }
+    }
+}
+
+/// Returns the progress that has changed since it was last recorded, or `None` if nothing has changed
+fn changed_progress(
+    previous: Option<&ProgressSnapshot>,
+    current: ProgressSnapshot,
+) -> Option<ProgressSnapshot> {
+    match (previous, current) {
+        (Some(previous), current) if *previous == current => None,
+        (
+            Some(ProgressSnapshot::Global {
+                chapters: previous, ..
+            }),
+            ProgressSnapshot::Global {
+                current_chapter,
+                chapters,
+            },
+        ) => Some(ProgressSnapshot::Global {
+            current_chapter,
+            chapters: chapters
+                .into_iter()
+                .filter(|(ch, p)| previous.get(ch) != Some(p))
+                .collect(),
+        }),
+        (_, current) => Some(current),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time has gone backwards")
+        .as_millis() as u64
+}
+
+fn append_log(path: &Path, entries: &[LogEntry]) -> Result<(), TRError> {
+    let mut data = String::new();
+    for entry in entries {
+        data.push_str(&serde_json::to_string(entry)?);
+        data.push('\n');
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(data.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Reads the entries of a log after the ones that have already been applied, updating the amount applied
+fn read_log(path: &Path, applied: &mut usize) -> Result<Vec<LogEntry>, TRError> {
+    let data = fs::read_to_string(path)?;
+    // The last line may still be being written or synced, so only read up to the last complete line
+    let Some((complete, _)) = data.rsplit_once('\n') else {
+        return Ok(Vec::new());
+    };
+    let mut entries = Vec::new();
+    let lines: Vec<&str> = complete.split('\n').collect();
+    for line in lines.iter().skip(*applied) {
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            // A corrupt entry can't be applied, but shouldn't stop the rest from being
+            Err(e) => tracing::error!("skipping invalid entry in {}: {e}", path.display()),
+        }
+    }
+    *applied = (*applied).max(lines.len());
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::ChapterProgress;
+    use crate::testing::{add_library_book, TestDir};
+
+    fn device(dir: &Path, name: &str) -> Context {
+        let mut ctx = Context::build(dir.join(name)).unwrap();
+        ctx.set_auto_backup_count(0);
+        ctx.enable_sync(dir.join("shared")).unwrap();
+        ctx
+    }
+
+    #[test]
+    fn devices_merge_changes() {
+        let dir = TestDir::new("sync-merge");
+        let mut desktop = device(dir.path(), "desktop");
+        let mut laptop = device(dir.path(), "laptop");
+
+        let id = add_library_book(&mut desktop, "Book", 3);
+        desktop.save().unwrap();
+
+        laptop.sync().unwrap();
+        let copy = laptop.get_book(id).unwrap();
+        assert!(copy.in_library());
+
+        // Both devices read, and the laptop moves the book to a category
+        let mut book = desktop.get_book(id).unwrap();
+        book.global_set_progress(ChapterProgress::Finished, 1)
+            .unwrap();
+        desktop.save().unwrap();
+        let mut copy = laptop.get_book(id).unwrap();
+        copy.global_set_progress(ChapterProgress::Finished, 2)
+            .unwrap();
+        copy.global_set_chapter(3).unwrap();
+        laptop
+            .create_library_category(String::from("Reading"))
+            .unwrap();
+        laptop.move_book_category(id, Some("Reading")).unwrap();
+        laptop.save().unwrap();
+
+        desktop.sync().unwrap();
+        laptop.sync().unwrap();
+        for ctx in [&desktop, &laptop] {
+            let book = ctx.get_book(id).unwrap();
+            assert_eq!(book.global_get_ordered_chapters().unwrap(), 2);
+            assert_eq!(book.get_current_ch(), Some(3));
+            assert_eq!(ctx.get_library_books()["Reading"].len(), 1);
+        }
+
+        // Nothing is applied twice
+        assert_eq!(desktop.sync().unwrap(), 0);
+    }
+}
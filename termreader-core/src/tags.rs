@@ -0,0 +1,162 @@
+// This module contains tags, which are free-form labels that books can have any number of.
+//
+// Categories are still where books are kept in the library, tags are a way of grouping books across categories.
+// Tags are stored with each book, so a tag exists for as long as a book has it.
+
+use crate::{book::BookRef, id::ID, Context, TRError};
+use std::collections::BTreeSet;
+
+/// Trims a tag, erroring if nothing is left
+fn clean_tag(tag: &str) -> Result<String, TRError> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err(TRError::InvalidChoice(String::from("a tag can't be empty")));
+    }
+    Ok(tag.to_string())
+}
+
+impl Context {
+    /// Returns every tag that a book in the library has, in alphabetical order
+    pub fn get_library_tags(&self) -> Vec<String> {
+        let mut tags = BTreeSet::new();
+        for book in self.library.books.values().flatten() {
+            tags.extend(book.read().tags.iter().cloned());
+        }
+        tags.into_iter().collect()
+    }
+
+    /// Returns the books in a library category that have every one of the tags. All of the books are returned if no
+    /// tags are given. Returns `None` if the category doesn't exist
+    pub fn get_library_books_tagged(
+        &self,
+        category: &str,
+        tags: &[String],
+    ) -> Option<Vec<BookRef>> {
+        let books = self.library.books.get(category)?;
+        Some(books.iter().filter(|b| b.has_tags(tags)).cloned().collect())
+    }
+
+    /// Adds a tag to a book. Surrounding whitespace is removed from the tag.
+    ///
+    /// Errors if:
+    /// - The book is missing from memory
+    /// - The tag is empty
+    /// - The book already has the tag
+    pub fn add_book_tag(&mut self, id: ID, tag: &str) -> Result<(), TRError> {
+        let Some(book) = self.books.get(id) else {
+            return Err(TRError::BookMissing);
+        };
+        let tag = clean_tag(tag)?;
+        if !book.write().tags.insert(tag) {
+            return Err(TRError::Duplicate);
+        }
+        Ok(())
+    }
+
+    /// Removes a tag from a book
+    ///
+    /// Errors if the book is missing from memory, or doesn't have the tag
+    pub fn remove_book_tag(&mut self, id: ID, tag: &str) -> Result<(), TRError> {
+        let Some(book) = self.books.get(id) else {
+            return Err(TRError::BookMissing);
+        };
+        if !book.write().tags.remove(tag) {
+            return Err(TRError::Redundant);
+        }
+        Ok(())
+    }
+
+    /// Renames a tag on every book that has it, returning how many books were changed.
+    /// Books that already have the new tag keep it once.
+    ///
+    /// Errors if the new tag is empty
+    pub fn rename_tag(&mut self, old: &str, new: &str) -> Result<usize, TRError> {
+        let new = clean_tag(new)?;
+        let mut renamed = 0;
+        for book in self.books.books.values() {
+            let mut book = book.write();
+            if book.tags.remove(old) {
+                book.tags.insert(new.clone());
+                renamed += 1;
+            }
+        }
+        Ok(renamed)
+    }
+
+    /// Removes a tag from every book that has it, returning how many books were changed
+    pub fn delete_tag(&mut self, tag: &str) -> usize {
+        let mut deleted = 0;
+        for book in self.books.books.values() {
+            if book.write().tags.remove(tag) {
+                deleted += 1;
+            }
+        }
+        deleted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        testing::{add_library_book, TestDir},
+        TRError,
+    };
+
+    #[test]
+    fn tags_are_trimmed_and_not_repeated() {
+        let dir = TestDir::new("tags-add");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+
+        ctx.add_book_tag(id, " fantasy ").unwrap();
+        assert!(matches!(
+            ctx.add_book_tag(id, "fantasy"),
+            Err(TRError::Duplicate)
+        ));
+        assert!(matches!(
+            ctx.add_book_tag(id, "  "),
+            Err(TRError::InvalidChoice(_))
+        ));
+        let ctx = dir.reopen(ctx);
+        assert_eq!(ctx.get_library_tags(), vec!["fantasy"]);
+    }
+
+    #[test]
+    fn books_can_be_filtered_by_tags() {
+        let dir = TestDir::new("tags-filter");
+        let mut ctx = dir.open();
+        let both = add_library_book(&mut ctx, "Both", 1);
+        let one = add_library_book(&mut ctx, "One", 1);
+        ctx.add_book_tag(both, "fantasy").unwrap();
+        ctx.add_book_tag(both, "favourite").unwrap();
+        ctx.add_book_tag(one, "fantasy").unwrap();
+
+        let tagged = |tags: &[&str]| -> Vec<String> {
+            let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+            ctx.get_library_books_tagged("Default", &tags)
+                .unwrap()
+                .iter()
+                .map(|b| b.get_name())
+                .collect()
+        };
+        assert_eq!(tagged(&["fantasy", "favourite"]), vec!["Both"]);
+        assert_eq!(tagged(&["fantasy"]), vec!["Both", "One"]);
+        assert_eq!(tagged(&[]).len(), 2);
+        assert!(ctx.get_library_books_tagged("Missing", &[]).is_none());
+    }
+
+    #[test]
+    fn tags_are_renamed_and_deleted_on_every_book() {
+        let dir = TestDir::new("tags-rename");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+        ctx.add_book_tag(id, "fantasy").unwrap();
+        ctx.add_book_tag(id, "favourite").unwrap();
+
+        // The book already has the new name, so keeps it once
+        assert_eq!(ctx.rename_tag("favourite", "fantasy").unwrap(), 1);
+        assert_eq!(ctx.get_library_tags(), vec!["fantasy"]);
+        assert_eq!(ctx.delete_tag("fantasy"), 1);
+        assert!(ctx.get_library_tags().is_empty());
+    }
+}
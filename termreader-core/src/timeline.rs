@@ -0,0 +1,288 @@
+// This module contains the timeline, an append-only log of the chapters opened and finished.
+//
+// The history only keeps the latest chapter read of each book, the timeline keeps every chapter. Like reading sessions,
+// events are kept with the history and refer to books by ID and name, so they outlive the books. The timeline is
+// trimmed to the retention settings (see `Context::set_history_retention`) rather than growing forever.
+
+use crate::{id::ID, stats::local_date, updates::ChapterDiff, Context, TRError};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SECS_PER_DAY: u64 = 60 * 60 * 24;
+/// How many events are kept by default
+pub const DEFAULT_HISTORY_MAX_EVENTS: usize = 10_000;
+/// How many days events are kept for by default, where 0 is forever
+pub const DEFAULT_HISTORY_MAX_AGE_DAYS: u64 = 0;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time has gone VERY backwards")
+        .as_secs()
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HistoryEventKind {
+    /// A chapter was opened in the reader
+    Opened,
+    /// The end of a chapter was reached
+    Finished,
+}
+
+/// Something that happened to a chapter of a book
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HistoryEvent {
+    book: ID,
+    /// The name of the book at the time
+    name: String,
+    /// The chapter, or 0 for local books
+    chapter: usize,
+    kind: HistoryEventKind,
+    /// In seconds since the UNIX epoch
+    timestamp: u64,
+    /// How long the chapter was read for before it was finished, in seconds. Always 0 when it was opened
+    duration: u64,
+}
+
+impl HistoryEvent {
+    pub fn get_book_id(&self) -> ID {
+        self.book
+    }
+
+    pub fn get_book_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_chapter(&self) -> usize {
+        self.chapter
+    }
+
+    pub fn get_kind(&self) -> HistoryEventKind {
+        self.kind
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn get_duration(&self) -> u64 {
+        self.duration
+    }
+
+    fn key(&self) -> (ID, u64, usize, HistoryEventKind) {
+        (self.book, self.timestamp, self.chapter, self.kind)
+    }
+}
+
+/// How much of the timeline is kept. Nothing is trimmed for a limit of 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct HistoryRetention {
+    pub(crate) max_events: usize,
+    pub(crate) max_age_days: u64,
+}
+
+impl Default for HistoryRetention {
+    fn default() -> Self {
+        Self {
+            max_events: DEFAULT_HISTORY_MAX_EVENTS,
+            max_age_days: DEFAULT_HISTORY_MAX_AGE_DAYS,
+        }
+    }
+}
+
+/// Moves events to the IDs their books now have, e.g. after copies of a book were merged
+pub(crate) fn rekey_events(list: &mut [HistoryEvent], ids: &HashMap<ID, ID>) {
+    for event in list.iter_mut() {
+        if let Some(&id) = ids.get(&event.book) {
+            event.book = id;
+        }
+    }
+}
+
+/// Adds events to a list ordered by time, skipping any that are already in it
+pub(crate) fn merge_events(list: &mut Vec<HistoryEvent>, other: &[HistoryEvent]) {
+    let existing: HashSet<_> = list.iter().map(|e| e.key()).collect();
+    list.extend(
+        other
+            .iter()
+            .filter(|e| !existing.contains(&e.key()))
+            .cloned(),
+    );
+    list.sort_by_key(|e| e.timestamp);
+}
+
+/// Moves a book's events onto the new numbering of its chapters after an update, dropping those in removed chapters
+pub(crate) fn renumber_events(list: &mut Vec<HistoryEvent>, book: ID, diff: &ChapterDiff) {
+    list.retain_mut(|e| {
+        if e.book != book || e.timestamp > diff.timestamp {
+            return true;
+        }
+        match diff.renumber(e.chapter) {
+            Some(chapter) => {
+                e.chapter = chapter;
+                true
+            }
+            None => false,
+        }
+    });
+}
+
+/// Groups items under the local day they happened on, keeping their order. Items should be ordered by time
+pub fn group_by_day<T>(
+    items: impl IntoIterator<Item = T>,
+    timestamp: impl Fn(&T) -> u64,
+) -> Vec<(NaiveDate, Vec<T>)> {
+    let mut days: Vec<(NaiveDate, Vec<T>)> = Vec::new();
+    for item in items {
+        let day = local_date(timestamp(&item));
+        match days.last_mut() {
+            Some((last, list)) if *last == day => list.push(item),
+            _ => days.push((day, vec![item])),
+        }
+    }
+    days
+}
+
+impl Context {
+    /// Adds an event to the timeline for the chapter of a book that's being read,
+    /// along with how long it was read for if it was finished
+    ///
+    /// Errors if the book is missing from memory
+    pub fn log_history_event(
+        &mut self,
+        id: ID,
+        kind: HistoryEventKind,
+        duration: u64,
+    ) -> Result<(), TRError> {
+        let Some(book) = self.books.get(id) else {
+            return Err(TRError::BookMissing);
+        };
+        let event = HistoryEvent {
+            book: id,
+            name: book.get_name(),
+            chapter: book.get_current_ch().unwrap_or(0),
+            kind,
+            timestamp: now(),
+            duration: match kind {
+                HistoryEventKind::Opened => 0,
+                HistoryEventKind::Finished => duration,
+            },
+        };
+        self.history.events.push(event);
+        self.trim_timeline();
+        Ok(())
+    }
+
+    /// Returns every event in the timeline, oldest first
+    pub fn get_timeline(&self) -> &[HistoryEvent] {
+        &self.history.events
+    }
+
+    /// Sets how much of the timeline is kept, by the amount of events and by how many days ago they were.
+    /// Nothing is trimmed for a limit of 0
+    pub fn set_history_retention(&mut self, max_events: usize, max_age_days: u64) {
+        self.history_retention = HistoryRetention {
+            max_events,
+            max_age_days,
+        };
+        self.trim_timeline();
+    }
+
+    /// Removes the events that are older or further back than the retention settings allow
+    pub(crate) fn trim_timeline(&mut self) {
+        let HistoryRetention {
+            max_events,
+            max_age_days,
+        } = self.history_retention;
+        let events = &mut self.history.events;
+        if max_age_days != 0 {
+            let cutoff = now().saturating_sub(max_age_days * SECS_PER_DAY);
+            events.retain(|e| e.timestamp >= cutoff);
+        }
+        if max_events != 0 && events.len() > max_events {
+            events.drain(..events.len() - max_events);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_library_book, TestDir};
+
+    /// Opens and finishes each of the book's 3 chapters in turn, like the reader does, a minute each
+    fn read_chapters(ctx: &mut Context, id: ID) {
+        let mut book = ctx.get_book(id).unwrap();
+        for ch in 1..=3 {
+            book.global_set_chapter(ch).unwrap();
+            ctx.log_history_event(id, HistoryEventKind::Opened, 60)
+                .unwrap();
+            ctx.log_history_event(id, HistoryEventKind::Finished, 60)
+                .unwrap();
+            ctx.remove_history_entry(id);
+            ctx.add_history_entry(id);
+        }
+    }
+
+    #[test]
+    fn timeline_keeps_every_chapter() {
+        let dir = TestDir::new("timeline-kept");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        read_chapters(&mut ctx, id);
+
+        let ctx = dir.reopen(ctx);
+        // The history only has the latest chapter, while the timeline has all of them
+        assert_eq!(ctx.get_history().len(), 1);
+        let timeline = ctx.get_timeline();
+        assert_eq!(timeline.len(), 6);
+        assert_eq!(timeline[0].get_duration(), 0);
+        assert_eq!(timeline[5].get_chapter(), 3);
+        assert_eq!(timeline[5].get_duration(), 60);
+    }
+
+    #[test]
+    fn events_are_grouped_by_day() {
+        let dir = TestDir::new("timeline-days");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        read_chapters(&mut ctx, id);
+
+        let days = group_by_day(ctx.get_timeline().iter(), |e| e.get_timestamp());
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].1.len(), 6);
+    }
+
+    #[test]
+    fn oldest_events_are_trimmed() {
+        let dir = TestDir::new("timeline-retention");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        read_chapters(&mut ctx, id);
+
+        ctx.set_history_retention(4, 30);
+        assert_eq!(ctx.get_timeline().len(), 4);
+        assert_eq!(ctx.get_timeline()[0].get_chapter(), 2);
+
+        // Trimmed events aren't brought back by saving and loading
+        let ctx = dir.reopen(ctx);
+        assert_eq!(ctx.get_timeline().len(), 4);
+    }
+
+    #[test]
+    fn clearing_history_clears_timeline() {
+        let dir = TestDir::new("timeline-clear");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        read_chapters(&mut ctx, id);
+
+        ctx.clear_history();
+        assert!(ctx.get_timeline().is_empty());
+        let ctx = dir.reopen(ctx);
+        assert!(ctx.get_timeline().is_empty());
+    }
+}
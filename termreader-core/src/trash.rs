@@ -0,0 +1,259 @@
+// This module contains the trash, which keeps books removed from the library for a while, so that they can be restored.
+//
+// Books only referenced by the trash would otherwise be dropped (see `BooksContext::remove_unneeded`), losing their
+// progress. The trash is stored as part of the library.
+
+use crate::{
+    book::BookRef, books_context::BooksContext, id::ID, verify::Problem, Context, TRError,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// How many days books are kept in the trash by default
+pub const DEFAULT_TRASH_RETENTION_DAYS: u64 = 30;
+
+const SECS_PER_DAY: u64 = 60 * 60 * 24;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(super) struct TrashEntrySerialize {
+    book: ID,
+    category: Option<String>,
+    removed: u64,
+}
+
+impl TrashEntrySerialize {
+    pub(super) fn from_trash_entry(entry: &TrashEntry) -> Self {
+        Self {
+            book: entry.book.get_id(),
+            category: entry.category.clone(),
+            removed: entry.removed,
+        }
+    }
+
+    /// Returns `None` if the book doesn't exist, adding it to the problems found
+    pub(super) fn into_trash_entry(
+        self,
+        books: &BooksContext,
+        problems: &mut Vec<Problem>,
+    ) -> Option<TrashEntry> {
+        let Some(book) = books.get(self.book) else {
+            problems.push(Problem::DanglingReference {
+                place: String::from("trash"),
+                id: self.book,
+            });
+            return None;
+        };
+        Some(TrashEntry {
+            book,
+            category: self.category,
+            removed: self.removed,
+        })
+    }
+}
+
+/// A book that has been removed from the library
+#[derive(Clone, Debug)]
+pub struct TrashEntry {
+    pub(super) book: BookRef,
+    /// The category the book was in. `None` is the default category
+    pub(super) category: Option<String>,
+    /// When the book was removed, in seconds since the UNIX epoch
+    pub(super) removed: u64,
+}
+
+impl TrashEntry {
+    pub(super) fn new(book: BookRef, category: Option<String>) -> Self {
+        Self {
+            book,
+            category,
+            removed: now(),
+        }
+    }
+
+    pub fn get_book_ref(&self) -> BookRef {
+        BookRef::clone(&self.book)
+    }
+
+    pub fn get_book_id(&self) -> ID {
+        self.book.get_id()
+    }
+
+    /// Returns the category the book was removed from, or `None` if it was in the default category
+    pub fn get_category(&self) -> Option<&str> {
+        self.category.as_deref()
+    }
+
+    /// Returns when the book was removed, in seconds since the UNIX epoch
+    pub fn get_removed(&self) -> u64 {
+        self.removed
+    }
+}
+
+impl Context {
+    /// Returns the books in the trash, most recently removed first
+    pub fn get_trash(&self) -> &VecDeque<TrashEntry> {
+        &self.library.trash
+    }
+
+    /// Set how many days books are kept in the trash before being deleted, deleting any that have been kept for longer.
+    /// Books are kept forever when this is 0. Expired books are also deleted on each save
+    pub fn set_trash_retention_days(&mut self, days: u64) {
+        self.trash_retention_days = days;
+        self.purge_trash();
+    }
+
+    /// Returns how many days are left before a book in the trash is deleted, or `None` if it's kept forever
+    pub fn get_trash_days_left(&self, entry: &TrashEntry) -> Option<u64> {
+        if self.trash_retention_days == 0 {
+            return None;
+        }
+        let expires = entry.removed + self.trash_retention_days * SECS_PER_DAY;
+        Some(expires.saturating_sub(now()).div_ceil(SECS_PER_DAY))
+    }
+
+    /// Moves a book from the trash back into the library, in the category it was removed from.
+    /// The default category is used if that category no longer exists
+    pub fn restore_from_trash(&mut self, id: ID) -> Result<(), TRError> {
+        let Some(pos) = self
+            .library
+            .trash
+            .iter()
+            .position(|e| e.book.get_id() == id)
+        else {
+            return Err(TRError::BookMissing);
+        };
+        let entry = self
+            .library
+            .trash
+            .remove(pos)
+            .expect("the position was just found");
+        let category = entry
+            .category
+            .filter(|c| self.library.books.contains_key(c));
+        self.add_to_lib(id, category.as_deref())
+    }
+
+    /// Deletes a book in the trash for good, along with its progress, unless it's in the history or updates
+    pub fn delete_from_trash(&mut self, id: ID) {
+        self.library.trash.retain(|e| e.book.get_id() != id);
+        self.journal.forget_book(id);
+        self.books.remove_unneeded();
+    }
+
+    /// Deletes every book in the trash. See `Context::delete_from_trash`
+    pub fn empty_trash(&mut self) {
+        for entry in self.library.trash.drain(..) {
+            self.journal.forget_book(entry.book.get_id());
+        }
+        self.books.remove_unneeded();
+    }
+
+    /// Deletes books that have been in the trash for longer than the retention period
+    pub(crate) fn purge_trash(&mut self) {
+        if self.trash_retention_days == 0 {
+            return;
+        }
+        let cutoff = now().saturating_sub(self.trash_retention_days * SECS_PER_DAY);
+        let (kept, expired) = self
+            .library
+            .trash
+            .drain(..)
+            .partition(|e| e.removed >= cutoff);
+        self.library.trash = kept;
+        if !expired.is_empty() {
+            for entry in expired {
+                self.journal.forget_book(entry.book.get_id());
+            }
+            self.books.remove_unneeded();
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time has gone VERY backwards")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        book::ChapterProgress,
+        testing::{add_library_book, TestDir},
+    };
+
+    /// Removes a book from the library, as if it had been removed the given amount of days ago
+    fn trash_days_ago(ctx: &mut Context, id: ID, days: u64) {
+        ctx.remove_from_lib(id);
+        ctx.library.trash[0].removed = now() - days * SECS_PER_DAY;
+    }
+
+    #[test]
+    fn removed_books_are_restored_to_their_category() {
+        let dir = TestDir::new("trash-restore");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+        ctx.create_library_category(String::from("Reading"))
+            .unwrap();
+        ctx.move_book_category(id, Some("Reading")).unwrap();
+        ctx.get_book(id)
+            .unwrap()
+            .global_set_progress(ChapterProgress::Finished, 1)
+            .unwrap();
+        ctx.remove_from_lib(id);
+        assert!(!ctx.get_book(id).unwrap().in_library());
+
+        let mut ctx = dir.reopen(ctx);
+        assert_eq!(ctx.get_trash()[0].get_category(), Some("Reading"));
+        ctx.restore_from_trash(id).unwrap();
+        assert!(ctx.get_trash().is_empty());
+        let book = &ctx.get_library_books()["Reading"][0];
+        assert_eq!(
+            book.get_all_chapter_progress().get(&1),
+            Some(&ChapterProgress::Finished)
+        );
+    }
+
+    #[test]
+    fn emptying_the_trash_deletes_books() {
+        let dir = TestDir::new("trash-empty");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+        ctx.remove_from_lib(id);
+        ctx.empty_trash();
+        assert!(ctx.get_book(id).is_none());
+    }
+
+    #[test]
+    fn expired_books_are_deleted() {
+        let dir = TestDir::new("trash-expired");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+        trash_days_ago(&mut ctx, id, 40);
+        ctx.set_trash_retention_days(30);
+        assert!(ctx.get_trash().is_empty());
+        assert!(ctx.get_book(id).is_none());
+    }
+
+    #[test]
+    fn books_kept_forever_survive_reopening() {
+        let dir = TestDir::new("trash-forever");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+        ctx.set_trash_retention_days(0);
+        trash_days_ago(&mut ctx, id, 40);
+
+        // The retention is only set after opening, so opening mustn't purge with the default
+        let mut ctx = dir.reopen(ctx);
+        assert_eq!(ctx.get_trash().len(), 1);
+        ctx.set_trash_retention_days(0);
+        let ctx = dir.reopen(ctx);
+        assert_eq!(ctx.get_trash()[0].get_book_id(), id);
+        assert!(ctx.get_book(id).is_some());
+    }
+}
@@ -1,6 +1,9 @@
-use crate::{book::BookRef, books_context::BooksContext, id::ID};
+use crate::{book::BookRef, books_context::BooksContext, id::ID, verify::Problem};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::time::{SystemTime, UNIX_EPOCH};
+use termreader_sources::chapter::ChapterPreview;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub(super) struct UpdatesCtxSerialize {
@@ -8,22 +11,27 @@ pub(super) struct UpdatesCtxSerialize {
 }
 
 impl UpdatesCtxSerialize {
-    pub(super) fn from_updates_ctx(updates_ctx: UpdatesContext) -> Self {
+    pub(super) fn from_updates_ctx(updates_ctx: &UpdatesContext) -> Self {
         Self {
             updates: updates_ctx
                 .updates
-                .into_iter()
+                .iter()
                 .map(|x| UpdatesEntrySerialize::from_updates_entry(x))
                 .collect(),
         }
     }
 
-    pub(super) fn to_updates_ctx(self, books: &BooksContext) -> UpdatesContext {
+    /// Entries for books that don't exist are left out, and added to the problems found
+    pub(super) fn to_updates_ctx(
+        self,
+        books: &BooksContext,
+        problems: &mut Vec<Problem>,
+    ) -> UpdatesContext {
         UpdatesContext {
             updates: self
                 .updates
                 .into_iter()
-                .map(|x| UpdatesEntrySerialize::to_updates_entry(x, books))
+                .filter_map(|x| UpdatesEntrySerialize::to_updates_entry(x, books, problems))
                 .collect(),
         }
     }
@@ -49,6 +57,34 @@ impl UpdatesContext {
         self.updates.retain(|x| x.book.get_id() != book_id)
     }
 
+    /// Adds an entry from elsewhere, such as a backup, unless it's already present
+    pub(super) fn merge_entry(&mut self, book: BookRef, timestamp: u64, chapter: UpdatedChapters) {
+        let id = book.get_id();
+        if self
+            .updates
+            .iter()
+            .any(|u| u.timestamp == timestamp && u.book.get_id() == id)
+        {
+            return;
+        }
+        book.write().in_updates = true;
+
+        // Updates are ordered from newest to oldest
+        let pos = self
+            .updates
+            .iter()
+            .position(|u| u.timestamp < timestamp)
+            .unwrap_or(self.updates.len());
+        self.updates.insert(
+            pos,
+            UpdatesEntry {
+                book,
+                timestamp,
+                chapter,
+            },
+        );
+    }
+
     pub(super) fn get_updates(&self) -> &VecDeque<UpdatesEntry> {
         &self.updates
     }
@@ -66,20 +102,31 @@ pub struct UpdatesEntrySerialize {
 }
 
 impl UpdatesEntrySerialize {
-    fn from_updates_entry(entry: UpdatesEntry) -> Self {
+    fn from_updates_entry(entry: &UpdatesEntry) -> Self {
         Self {
             book: entry.book.get_id(),
             timestamp: entry.timestamp,
-            chapter: entry.chapter,
+            chapter: entry.chapter.clone(),
         }
     }
 
-    fn to_updates_entry(self, books: &BooksContext) -> UpdatesEntry {
-        UpdatesEntry {
-            book: books.get(self.book).unwrap(),
+    fn to_updates_entry(
+        self,
+        books: &BooksContext,
+        problems: &mut Vec<Problem>,
+    ) -> Option<UpdatesEntry> {
+        let Some(book) = books.get(self.book) else {
+            problems.push(Problem::DanglingReference {
+                place: String::from("updates"),
+                id: self.book,
+            });
+            return None;
+        };
+        Some(UpdatesEntry {
+            book,
             timestamp: self.timestamp,
             chapter: self.chapter,
-        }
+        })
     }
 }
 
@@ -90,32 +137,286 @@ pub struct UpdatesEntry {
     pub(super) chapter: UpdatedChapters,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum UpdatedChapters {
     /// An fully inclusive range
     Range((usize, usize)),
     Single(usize),
+    /// Chapters were removed, renamed or moved, rather than only added to the end
+    Changed(ChapterChanges),
     None,
 }
 
+/// How the chapter list of a book changed, by comparing the chapters' URLs
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChapterChanges {
+    /// The chapters added, by their new number
+    pub added: Vec<usize>,
+    /// The names of the chapters removed
+    pub removed: Vec<String>,
+    /// The chapters that were given a different name, by their new number
+    pub renamed: Vec<usize>,
+    /// How many of the chapters that were kept were given a different number
+    pub renumbered: usize,
+}
+
+/// The result of comparing a book's chapter list with an updated one.
+///
+/// Updating a book returns this, so that the records kept outside of it can be moved onto the new numbering of its
+/// chapters through `Context::record_update`
+#[derive(Debug)]
+pub struct ChapterDiff {
+    /// The new number of each chapter that was kept, by its old number
+    pub(crate) numbers: HashMap<usize, usize>,
+    /// The numbers of the chapters that were removed
+    pub(crate) removed: HashSet<usize>,
+    pub(crate) updated: UpdatedChapters,
+    /// When the chapters were compared, in seconds since the UNIX epoch. Records made after this already use the
+    /// new numbering
+    pub(crate) timestamp: u64,
+}
+
+impl ChapterDiff {
+    /// A diff for a book whose chapters weren't changed
+    pub(crate) fn unchanged() -> Self {
+        Self {
+            numbers: HashMap::new(),
+            removed: HashSet::new(),
+            updated: UpdatedChapters::None,
+            timestamp: now(),
+        }
+    }
+
+    /// Returns the chapters that were added or changed, as shown in the updates
+    pub fn get_updated(&self) -> &UpdatedChapters {
+        &self.updated
+    }
+
+    /// Returns the new number of a chapter. Chapters that were removed have none, and chapters that weren't
+    /// in the old chapter list (e.g. progress past the end) keep their number
+    pub(crate) fn renumber(&self, chapter: usize) -> Option<usize> {
+        match self.numbers.get(&chapter) {
+            Some(&new) => Some(new),
+            None if self.removed.contains(&chapter) => None,
+            None => Some(chapter),
+        }
+    }
+}
+
+/// Compares a chapter list with an updated one, matching chapters by their URL rather than their position,
+/// as sources can remove and reorder chapters as well as add them
+pub(crate) fn diff_chapters(old: &[ChapterPreview], new: &[ChapterPreview]) -> ChapterDiff {
+    let mut old_by_url: HashMap<&str, &ChapterPreview> = HashMap::new();
+    for ch in old {
+        old_by_url.entry(ch.get_url()).or_insert(ch);
+    }
+
+    let mut numbers = HashMap::new();
+    let mut changes = ChapterChanges::default();
+    for ch in new {
+        let Some(old_ch) = old_by_url.get(ch.get_url()) else {
+            changes.added.push(ch.get_chapter_no());
+            continue;
+        };
+        if numbers.contains_key(&old_ch.get_chapter_no()) {
+            // The chapter is listed more than once, so only the first is the same chapter
+            changes.added.push(ch.get_chapter_no());
+            continue;
+        }
+        numbers.insert(old_ch.get_chapter_no(), ch.get_chapter_no());
+        if old_ch.get_name() != ch.get_name() {
+            changes.renamed.push(ch.get_chapter_no());
+        }
+        if old_ch.get_chapter_no() != ch.get_chapter_no() {
+            changes.renumbered += 1;
+        }
+    }
+    let mut removed = HashSet::new();
+    for ch in old {
+        if !numbers.contains_key(&ch.get_chapter_no()) && removed.insert(ch.get_chapter_no()) {
+            changes.removed.push(ch.get_name().to_string());
+        }
+    }
+
+    // Chapters only being added to the end is by far the most common update, so it's kept simple
+    let appended = changes.removed.is_empty()
+        && changes.renamed.is_empty()
+        && changes.renumbered == 0
+        && changes.added.windows(2).all(|w| w[1] == w[0] + 1);
+    let updated = match changes.added.as_slice() {
+        [] if appended => UpdatedChapters::None,
+        [ch] if appended => UpdatedChapters::Single(*ch),
+        [first, .., last] if appended => UpdatedChapters::Range((*first, *last)),
+        _ => UpdatedChapters::Changed(changes),
+    };
+    ChapterDiff {
+        numbers,
+        removed,
+        updated,
+        timestamp: now(),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time has gone VERY backwards")
+        .as_secs()
+}
+
 impl UpdatesEntry {
     pub fn get_timestamp(&self) -> u64 {
         self.timestamp
     }
 
     pub fn display_new_chs(&self) -> String {
-        match self.chapter {
+        match &self.chapter {
             UpdatedChapters::Range((start, end)) => format!("Chs. {} - {}", start, end),
             UpdatedChapters::Single(ch) => format!("Ch. {}", ch),
+            UpdatedChapters::Changed(changes) => changes.to_string(),
             UpdatedChapters::None => format!("(seeing text is an error and should be reported)"),
         }
     }
 
-    pub fn get_chapter(&self) -> UpdatedChapters {
-        self.chapter
+    pub fn get_chapter(&self) -> &UpdatedChapters {
+        &self.chapter
     }
 
     pub fn get_book_ref(&self) -> BookRef {
         BookRef::clone(&self.book)
     }
 }
+
+impl Display for ChapterChanges {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        match self.added.as_slice() {
+            [] => (),
+            [ch] => parts.push(format!("Ch. {ch} added")),
+            added => parts.push(format!("{} chs. added", added.len())),
+        }
+        if !self.removed.is_empty() {
+            parts.push(format!("{} removed", self.removed.len()));
+        }
+        if !self.renamed.is_empty() {
+            parts.push(format!("{} renamed", self.renamed.len()));
+        }
+        if self.renumbered > 0 {
+            parts.push(format!("{} renumbered", self.renumbered));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_library_book, TestDir};
+    use crate::timeline::HistoryEventKind;
+
+    fn chapters(list: &[(&str, &str)]) -> Vec<ChapterPreview> {
+        list.iter()
+            .enumerate()
+            .map(|(i, (name, url))| {
+                ChapterPreview::new(i + 1, name.to_string(), url.to_string(), String::new())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chapters_are_compared_by_url() {
+        let old = chapters(&[("One", "/1"), ("Two", "/2"), ("Three", "/3")]);
+
+        let appended = chapters(&[
+            ("One", "/1"),
+            ("Two", "/2"),
+            ("Three", "/3"),
+            ("Four", "/4"),
+        ]);
+        assert_eq!(
+            diff_chapters(&old, &appended).updated,
+            UpdatedChapters::Single(4)
+        );
+        assert_eq!(diff_chapters(&old, &old).updated, UpdatedChapters::None);
+
+        // A chapter is removed and another is renamed, so the rest move up
+        let changed = chapters(&[("One", "/1"), ("Three (edited)", "/3"), ("Four", "/4")]);
+        let diff = diff_chapters(&old, &changed);
+        assert_eq!(
+            diff.updated,
+            UpdatedChapters::Changed(ChapterChanges {
+                added: vec![3],
+                removed: vec![String::from("Two")],
+                renamed: vec![2],
+                renumbered: 1,
+            })
+        );
+        assert_eq!(diff.renumber(1), Some(1));
+        assert_eq!(diff.renumber(2), None);
+        assert_eq!(diff.renumber(3), Some(2));
+        // Chapters that weren't listed before keep their number
+        assert_eq!(diff.renumber(7), Some(7));
+        let UpdatedChapters::Changed(changes) = diff.updated else {
+            unreachable!()
+        };
+        assert_eq!(
+            changes.to_string(),
+            "Ch. 3 added, 1 removed, 1 renamed, 1 renumbered"
+        );
+    }
+
+    #[test]
+    fn records_follow_renumbered_chapters() {
+        let dir = TestDir::new("updates-renumber");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 3);
+        let mut book = ctx.get_book(id).unwrap();
+        for ch in 1..=3 {
+            book.global_set_chapter(ch).unwrap();
+            ctx.log_history_event(id, HistoryEventKind::Opened, 0)
+                .unwrap();
+            ctx.record_reading_session(id, ch, ch as u64 * 100, ch as u64 * 100 + 60, 500)
+                .unwrap();
+        }
+        ctx.add_history_entry(id);
+
+        // The second chapter is taken down and a new one is added, so the third moves up
+        let mut novel = book.read().global_get_novel().clone();
+        novel.set_chapters(vec![
+            ChapterPreview::new(
+                1,
+                String::from("Part 1"),
+                String::from("ch-1"),
+                String::new(),
+            ),
+            ChapterPreview::new(
+                2,
+                String::from("Part 3"),
+                String::from("ch-3"),
+                String::new(),
+            ),
+            ChapterPreview::new(
+                3,
+                String::from("Part 4"),
+                String::from("ch-4"),
+                String::new(),
+            ),
+        ]);
+        let diff = book.write().apply_update(novel);
+        ctx.record_update(id, diff);
+
+        let ctx = dir.reopen(ctx);
+        assert_eq!(ctx.get_history()[0].get_chapter(), 2);
+        let events: Vec<usize> = ctx.get_timeline().iter().map(|e| e.get_chapter()).collect();
+        assert_eq!(events, vec![1, 2]);
+        // The session in the removed chapter is kept for the time spent reading, but no longer has a chapter
+        let sessions: Vec<Option<usize>> = ctx
+            .get_reading_sessions()
+            .iter()
+            .map(|s| s.get_chapter())
+            .collect();
+        assert_eq!(sessions, vec![Some(1), None, Some(2)]);
+        assert_eq!(ctx.get_reading_stats().books[0].secs, 180);
+        assert_eq!(ctx.get_updates().len(), 1);
+    }
+}
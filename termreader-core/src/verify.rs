@@ -0,0 +1,298 @@
+// This module contains checking the data for problems, and repairing them.
+//
+// Problems can be left behind by bugs in older versions, by a save that was interrupted, or by editing the save files.
+// References to books that don't exist are dropped while loading, as there's nothing they could refer to, and are
+// reported along with the problems found in the loaded data.
+
+use crate::{book::BookRef, id::ID, Context};
+use std::fmt::Display;
+
+/// A problem found in the data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The library, history, or updates referred to a book that doesn't exist. The reference is dropped when loading
+    DanglingReference { place: String, id: ID },
+    /// A book in the library, history, or updates isn't among the stored books, so would be lost when saving
+    UnstoredBook { book: String },
+    /// A book is marked as being in the library, but isn't in any category
+    NotInCategory { book: String },
+    /// A book is in a category, but isn't marked as being in the library
+    NotMarkedInLibrary { book: String, category: String },
+    /// A category isn't in the category order, so isn't shown
+    UnorderedCategory(String),
+    /// The category order includes a category that doesn't exist
+    MissingCategory(String),
+    /// The default category doesn't exist, so books can't be added to the library without a category
+    MissingDefaultCategory(String),
+    /// Progress is recorded for chapters past the last chapter of a book
+    ProgressPastEnd { book: String, chapters: Vec<usize> },
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::DanglingReference { place, id } => {
+                write!(
+                    f,
+                    "{place} refers to book {} which doesn't exist",
+                    id.as_u128()
+                )
+            }
+            Problem::UnstoredBook { book } => {
+                write!(f, "\"{book}\" isn't stored with the other books")
+            }
+            Problem::NotInCategory { book } => {
+                write!(f, "\"{book}\" is in the library but isn't in any category")
+            }
+            Problem::NotMarkedInLibrary { book, category } => write!(
+                f,
+                "\"{book}\" is in category \"{category}\" but isn't marked as in the library"
+            ),
+            Problem::UnorderedCategory(c) => {
+                write!(f, "category \"{c}\" is missing from the category order")
+            }
+            Problem::MissingCategory(c) => {
+                write!(f, "the category order includes \"{c}\" which doesn't exist")
+            }
+            Problem::MissingDefaultCategory(c) => {
+                write!(f, "the default category \"{c}\" doesn't exist")
+            }
+            Problem::ProgressPastEnd { book, chapters } => write!(
+                f,
+                "\"{book}\" has progress for chapters past its last chapter: {}",
+                chapters
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl Context {
+    /// Checks the data for problems, including any found while it was loaded.
+    ///
+    /// Every book's chapter data is loaded to check its progress, so this may take a while
+    pub fn verify(&self) -> Vec<Problem> {
+        let mut problems = self.load_problems.clone();
+
+        for book in self.referenced_books() {
+            if self.books.get(book.get_id()).is_none() {
+                problems.push(Problem::UnstoredBook {
+                    book: book.get_name(),
+                });
+            }
+        }
+
+        for book in self.books.books.values() {
+            if book.in_library() && self.get_book_category(book).is_none() {
+                problems.push(Problem::NotInCategory {
+                    book: book.get_name(),
+                });
+            }
+            let chapters = book.get_progress_past_end();
+            if !chapters.is_empty() {
+                problems.push(Problem::ProgressPastEnd {
+                    book: book.get_name(),
+                    chapters,
+                });
+            }
+        }
+
+        for (category, books) in self.library.books.iter() {
+            for book in books.iter().filter(|b| !b.in_library()) {
+                problems.push(Problem::NotMarkedInLibrary {
+                    book: book.get_name(),
+                    category: category.clone(),
+                });
+            }
+            if !self.library.category_order.contains(category) {
+                problems.push(Problem::UnorderedCategory(category.clone()));
+            }
+        }
+        let default = &self.library.default_category_name;
+        if !self.library.books.contains_key(default) {
+            problems.push(Problem::MissingDefaultCategory(default.clone()));
+        }
+        for category in self.library.category_order.iter() {
+            if !self.library.books.contains_key(category) && category != default {
+                problems.push(Problem::MissingCategory(category.clone()));
+            }
+        }
+
+        problems
+    }
+
+    /// Fixes the problems found by `Context::verify`, returning them. The fixes are kept once the data is saved.
+    ///
+    /// - Books missing from the stored books are stored
+    /// - Books in the library but not in a category are added to their category (see `Context::add_to_lib`)
+    /// - Books in a category are marked as being in the library
+    /// - Categories missing from the category order are added to the end of it,
+    ///   and categories that don't exist are removed from it
+    /// - The default category is recreated if it doesn't exist
+    /// - Progress past the last chapter of a book is removed
+    pub fn repair(&mut self) -> Vec<Problem> {
+        let problems = self.verify();
+        self.load_problems.clear();
+
+        for book in self.referenced_books() {
+            if self.books.get(book.get_id()).is_none() {
+                self.books.books.insert(book.get_id(), book);
+            }
+        }
+
+        for book in self.books.books.values() {
+            if book.in_library() && self.get_book_category(book).is_none() {
+                let category = book
+                    .read()
+                    .category
+                    .clone()
+                    .filter(|c| self.library.books.contains_key(c));
+                book.write().category = category.clone();
+                self.library.add_to_category(book.clone(), category);
+            }
+            book.remove_progress_past_end();
+        }
+
+        for (category, books) in self.library.books.iter() {
+            let category =
+                (category != &self.library.default_category_name).then(|| category.clone());
+            for book in books.iter().filter(|b| !b.in_library()) {
+                let mut b = book.write();
+                b.in_library = true;
+                b.category = category.clone();
+            }
+        }
+
+        for problem in problems.iter() {
+            match problem {
+                Problem::UnorderedCategory(c) => self.library.category_order.push(c.clone()),
+                Problem::MissingCategory(c) => self.library.category_order.retain(|x| x != c),
+                // Books in the library without a category may have already recreated it
+                Problem::MissingDefaultCategory(c) => {
+                    self.library.books.entry(c.clone()).or_default();
+                    if !self.library.category_order.contains(c) {
+                        self.library.category_order.push(c.clone());
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        problems
+    }
+
+    /// Returns every book referred to by the library, history, updates, and trash
+    fn referenced_books(&self) -> Vec<BookRef> {
+        let library = self.library.books.values().flatten().cloned();
+        let history = self.history.history.iter().map(|e| e.get_book_ref());
+        let updates = self.updates.updates.iter().map(|e| e.book.clone());
+        let trash = self.library.trash.iter().map(|e| e.get_book_ref());
+        library.chain(history).chain(updates).chain(trash).collect()
+    }
+
+    /// Returns the category that a book is in, if any
+    fn get_book_category(&self, book: &BookRef) -> Option<&String> {
+        let id = book.get_id();
+        self.library
+            .books
+            .iter()
+            .find(|(_, books)| books.iter().any(|b| b.get_id() == id))
+            .map(|(category, _)| category)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::ChapterProgress;
+    use crate::testing::{add_library_book, TestDir};
+
+    #[test]
+    fn healthy_data_has_no_problems() {
+        let dir = TestDir::new("verify-healthy");
+        let mut ctx = dir.open();
+        add_library_book(&mut ctx, "Book", 1);
+        assert_eq!(ctx.verify(), Vec::new());
+        assert_eq!(dir.reopen(ctx).verify(), Vec::new());
+    }
+
+    #[test]
+    fn dangling_references_are_reported_from_loading() {
+        let dir = TestDir::new("verify-dangling");
+        let ctx = dir.open();
+        drop(dir.reopen(ctx));
+        std::fs::write(
+            dir.path().join("history.json"),
+            r#"{"history":[{"book":{"id":1},"timestamp":0,"chapter":1}]}"#,
+        )
+        .unwrap();
+
+        let mut ctx = dir.open();
+        let problem = Problem::DanglingReference {
+            place: String::from("history"),
+            id: ID::from_u128(1),
+        };
+        assert_eq!(ctx.verify(), vec![problem.clone()]);
+        assert_eq!(ctx.repair(), vec![problem]);
+        assert_eq!(ctx.verify(), Vec::new());
+    }
+
+    #[test]
+    fn problems_in_the_library_are_repaired() {
+        let dir = TestDir::new("verify-repair");
+        let mut ctx = dir.open();
+        let id = add_library_book(&mut ctx, "Book", 1);
+        drop(dir.reopen(ctx));
+
+        // A category that isn't in the order
+        let lib = dir.path().join("lib.json");
+        let mut data: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&lib).unwrap()).unwrap();
+        data["category_order"] = serde_json::json!([]);
+        std::fs::write(&lib, data.to_string()).unwrap();
+
+        let mut ctx = dir.open();
+        // Progress past the end of the book
+        ctx.get_book(id)
+            .unwrap()
+            .global_set_progress(ChapterProgress::Finished, 5)
+            .unwrap();
+        let problems = ctx.verify();
+        assert_eq!(
+            problems,
+            vec![
+                Problem::ProgressPastEnd {
+                    book: ctx.get_book(id).unwrap().get_name(),
+                    chapters: vec![5]
+                },
+                Problem::UnorderedCategory(String::from("Default")),
+            ]
+        );
+        assert_eq!(ctx.repair(), problems);
+        assert_eq!(ctx.verify(), Vec::new());
+    }
+
+    #[test]
+    fn missing_default_category_is_recreated() {
+        let dir = TestDir::new("verify-default");
+        drop(dir.reopen(dir.open()));
+
+        let lib = dir.path().join("lib.json");
+        let mut data: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&lib).unwrap()).unwrap();
+        data["books"] = serde_json::json!({});
+        data["category_order"] = serde_json::json!([]);
+        std::fs::write(&lib, data.to_string()).unwrap();
+
+        let mut ctx = dir.open();
+        let problems = vec![Problem::MissingDefaultCategory(String::from("Default"))];
+        assert_eq!(ctx.verify(), problems);
+        assert_eq!(ctx.repair(), problems);
+        assert_eq!(ctx.verify(), Vec::new());
+        // Adding a book to the library needs the default category
+        add_library_book(&mut ctx, "Book", 1);
+    }
+}
@@ -10,6 +10,15 @@ pub struct ChapterPreview {
 }
 
 impl ChapterPreview {
+    pub fn new(chapter_no: usize, name: String, url: String, release_date: String) -> Self {
+        Self {
+            chapter_no,
+            release_date,
+            name,
+            url,
+        }
+    }
+
     #[inline]
     pub fn get_name(&self) -> &str {
         &self.name
@@ -19,6 +28,11 @@ impl ChapterPreview {
     pub fn get_chapter_no(&self) -> usize {
         self.chapter_no
     }
+
+    #[inline]
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Hash)]
@@ -12,6 +12,17 @@ pub enum NovelStatus {
     Completed,
 }
 
+impl std::fmt::Display for NovelStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            NovelStatus::Ongoing => "Ongoing",
+            NovelStatus::Completed => "Completed",
+            NovelStatus::Unknown => "Unknown",
+        };
+        write!(f, "{}", status)
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
 pub struct NovelPreview {
     pub(crate) source: SourceID,
@@ -57,16 +68,18 @@ impl Novel {
         &self.name
     }
 
-    pub fn get_synopsis(&self) -> String {
-        let status = match self.status {
-            NovelStatus::Ongoing => "Ongoing",
-            NovelStatus::Completed => "Completed",
-            NovelStatus::Unknown => "Unknown",
-        };
+    pub fn get_source_name(&self) -> &str {
+        &self.source_name
+    }
+
+    pub fn get_status(&self) -> NovelStatus {
+        self.status
+    }
 
+    pub fn get_synopsis(&self) -> String {
         format!(
             "Author: {}\nStatus: {}\nGenres: {}\n\n{}",
-            self.author, status, self.genres, self.summary
+            self.author, self.status, self.genres, self.summary
         )
     }
 
@@ -74,18 +87,33 @@ impl Novel {
         &self.chapters
     }
 
+    /// Removes the chapter list from the novel, returning it
+    pub fn take_chapters(&mut self) -> Vec<ChapterPreview> {
+        std::mem::take(&mut self.chapters)
+    }
+
+    /// Replaces the chapter list of the novel
+    pub fn set_chapters(&mut self, chapters: Vec<ChapterPreview>) {
+        self.chapters = chapters
+    }
+
     pub fn get_length(&self) -> usize {
         self.chapters.len()
     }
 
-    // TODO: Index it rather than looping through
     pub fn get_chapter_url(&self, chapter: usize) -> Option<&str> {
-        for ch in &self.chapters {
-            if ch.chapter_no == chapter {
-                return Some(&ch.url);
-            }
+        // Chapters are almost always numbered from 1 in order, so try indexing directly first
+        if let Some(ch) = chapter
+            .checked_sub(1)
+            .and_then(|i| self.chapters.get(i))
+            .filter(|ch| ch.chapter_no == chapter)
+        {
+            return Some(&ch.url);
         }
-        None
+        self.chapters
+            .iter()
+            .find(|ch| ch.chapter_no == chapter)
+            .map(|ch| ch.url.as_str())
     }
 
     pub fn get_url(&self) -> &str {
@@ -106,4 +134,18 @@ impl Novel {
     pub fn set_alias(&mut self, alias: String) {
         self.alias = Some(alias)
     }
+
+    /// Replaces the details of the novel shown in its synopsis
+    pub fn set_details(
+        &mut self,
+        author: String,
+        status: NovelStatus,
+        genres: String,
+        summary: String,
+    ) {
+        self.author = author;
+        self.status = status;
+        self.genres = genres;
+        self.summary = summary;
+    }
 }
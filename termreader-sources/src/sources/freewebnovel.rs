@@ -16,6 +16,29 @@ impl FreeWebNovelScraper {
     pub fn new(source_id: SourceID) -> Self {
         Self { source_id }
     }
+
+    /// Returns the URL of a novel's page, given its path
+    pub(super) fn novel_url(&self, novel_path: &str) -> String {
+        format!("https://freewebnovel.com/{}.html", novel_path)
+    }
+
+    pub(super) fn chapter_url(&self, novel_path: &str, chapter_path: &str) -> String {
+        format!(
+            "https://freewebnovel.com/{}/{}.html",
+            novel_path, chapter_path
+        )
+    }
+
+    /// Splits a path relative to the site into the novel path and the chapter path
+    pub(super) fn split_path(&self, path: &str) -> Option<(String, String)> {
+        let path = path.trim_end_matches(".html").trim_end_matches('/');
+        let path = path.strip_prefix("novel/").unwrap_or(path);
+        let (novel, chapter) = path.split_once('/').unwrap_or((path, ""));
+        if novel.is_empty() {
+            return None;
+        }
+        Some((novel.to_string(), chapter.to_string()))
+    }
 }
 
 impl Scrape for FreeWebNovelScraper {
@@ -156,10 +179,7 @@ impl Scrape for FreeWebNovelScraper {
     }
 
     fn parse_chapter(&self, novel_path: String, chapter_path: String) -> Result<Chapter> {
-        let url = format!(
-            "https://freewebnovel.com/{}/{}.html",
-            novel_path, chapter_path
-        );
+        let url = self.chapter_url(&novel_path, &chapter_path);
         let html = Html::parse_document(&get_html(url)?);
 
         let chapter_name = html
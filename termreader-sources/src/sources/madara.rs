@@ -84,6 +84,30 @@ impl MadaraScraper {
         }
     }
 
+    /// Returns the URL of a novel's page, given its path
+    pub(super) fn novel_url(&self, novel_path: &str) -> String {
+        let url = &self.path.clone().unwrap_or_default().novel;
+        format!("{}{}/{}/", self.base_url, url, novel_path)
+    }
+
+    pub(super) fn chapter_url(&self, novel_path: &str, chapter_path: &str) -> String {
+        format!(
+            "{}{}/{}/{}",
+            self.base_url,
+            self.path.clone().unwrap_or_default().chapter,
+            novel_path,
+            chapter_path
+        )
+    }
+
+    /// Splits a path relative to the site into the novel path and the chapter path
+    pub(super) fn split_path(&self, path: &str) -> Option<(String, String)> {
+        let url = &self.path.clone().unwrap_or_default().novel;
+        let rest = path.strip_prefix(url.as_str())?.strip_prefix('/')?;
+        let (novel, chapter) = rest.split_once('/').unwrap_or((rest, ""));
+        Some((novel.to_string(), chapter.to_string()))
+    }
+
     fn get_popular(&self, order: SortOrder, page: usize) -> Result<Vec<NovelPreview>> {
         let sort_order = match order {
             SortOrder::Latest => "?m_orderby=latest",
@@ -140,8 +164,7 @@ impl MadaraScraper {
     }
 
     fn parse_novel_and_chapters(&self, novel_path: String) -> Result<Novel> {
-        let url = &self.path.clone().unwrap_or_default().novel;
-        let url = format!("{}{}/{}/", self.base_url, url, novel_path);
+        let url = self.novel_url(&novel_path);
 
         let mut html = Html::parse_document(&get_html(&url)?);
 
@@ -349,13 +372,7 @@ impl MadaraScraper {
     }
 
     fn parse_chapter(&self, novel_path: String, chapter_path: String) -> Result<Chapter> {
-        let url = format!(
-            "{}{}/{}/{}",
-            self.base_url,
-            self.path.clone().unwrap_or_default().chapter,
-            novel_path,
-            chapter_path
-        );
+        let url = self.chapter_url(&novel_path, &chapter_path);
 
         let html = Html::parse_document(&get_html(url)?);
 
@@ -38,6 +38,12 @@ impl From<usize> for SourceID {
     }
 }
 
+impl From<SourceID> for usize {
+    fn from(value: SourceID) -> Self {
+        value.0
+    }
+}
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Source {
     Madara(MadaraScraper),
@@ -58,6 +64,71 @@ impl Source {
             Source::FreeWebNovel(s) => s.source_id,
         }
     }
+
+    /// Returns the URL of the site the source scrapes
+    pub fn get_base_url(&self) -> &str {
+        match self {
+            Source::Madara(s) => &s.base_url,
+            Source::FreeWebNovel(_) => "https://freewebnovel.com/",
+        }
+    }
+
+    /// Returns the URL of a novel's page, given its path
+    pub fn get_novel_url(&self, novel_path: &str) -> String {
+        match self {
+            Source::Madara(s) => s.novel_url(novel_path),
+            Source::FreeWebNovel(s) => s.novel_url(novel_path),
+        }
+    }
+
+    /// Returns the URL of a chapter's page, given the paths of its novel and itself
+    pub fn get_chapter_url(&self, novel_path: &str, chapter_path: &str) -> String {
+        match self {
+            Source::Madara(s) => s.chapter_url(novel_path, chapter_path),
+            Source::FreeWebNovel(s) => s.chapter_url(novel_path, chapter_path),
+        }
+    }
+
+    /// Returns true if a URL is on the source's site
+    pub fn is_site_url(&self, url: &str) -> bool {
+        let host = |url: &str| {
+            let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+            let host = url.split('/').next().unwrap_or_default().to_lowercase();
+            host.strip_prefix("www.")
+                .map(str::to_string)
+                .unwrap_or(host)
+        };
+        url.contains("://") && host(url) == host(self.get_base_url())
+    }
+
+    /// Splits the URL of a novel or chapter, or its path relative to the site,
+    /// into the paths used by the source for the novel and the chapter.
+    ///
+    /// The chapter path is empty if the URL is for a novel.
+    /// Returns `None` if the URL isn't for a novel on the source
+    pub fn split_url(&self, url: &str) -> Option<(String, String)> {
+        let path = match url.split_once("://") {
+            Some((_, rest)) => rest.split_once('/').map_or("", |(_, path)| path),
+            None => url,
+        };
+        let path = path.trim_start_matches('/');
+        match self {
+            Source::Madara(s) => s.split_path(path),
+            Source::FreeWebNovel(s) => s.split_path(path),
+        }
+    }
+
+    /// Creates a novel on the source without fetching it, so that it has no details or chapters
+    pub fn novel_from_path(&self, novel_path: String, name: String) -> Novel {
+        Novel {
+            source: self.get_id(),
+            source_name: self.get_name(),
+            full_url: self.get_novel_url(&novel_path),
+            novel_url: novel_path,
+            name,
+            ..Default::default()
+        }
+    }
 }
 
 impl Scrape for Source {
@@ -110,6 +181,11 @@ impl Sources {
         return None;
     }
 
+    /// Returns an iterator over all sources
+    pub fn iter(&self) -> impl Iterator<Item = &Source> {
+        self.sources.iter()
+    }
+
     pub fn get_source_info(&self) -> Vec<(SourceID, String)> {
         let mut v = Vec::with_capacity(self.sources.len());
 
@@ -0,0 +1,42 @@
+// This module contains parsing of the command line arguments.
+
+use crate::logging::PROFILE_ENV;
+use anyhow::Result;
+
+/// The options given on the command line
+#[derive(Default)]
+pub struct Args {
+    /// The profile requested with `--profile <name>` (or `-p <name>`), or else the profile environment variable.
+    /// If none is requested, the user should pick one if there are several
+    pub profile: Option<String>,
+    /// Set by `--read-only`, to open the data without being able to change it, e.g. while another instance is using it
+    pub read_only: bool,
+    /// Set by `--fsck`, to check the data for problems and offer to repair them, instead of starting the TUI
+    pub fsck: bool,
+}
+
+impl Args {
+    /// Parses the arguments the program was run with
+    pub fn parse() -> Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--profile" | "-p" => match args.next() {
+                    Some(name) => parsed.profile = Some(name),
+                    None => anyhow::bail!("{} requires a profile name", arg),
+                },
+                "--read-only" => parsed.read_only = true,
+                "--fsck" => parsed.fsck = true,
+                _ => match arg.strip_prefix("--profile=") {
+                    Some(name) => parsed.profile = Some(name.to_string()),
+                    None => anyhow::bail!("unknown argument '{}'", arg),
+                },
+            }
+        }
+        if parsed.profile.is_none() {
+            parsed.profile = std::env::var(PROFILE_ENV.clone()).ok();
+        }
+        Ok(parsed)
+    }
+}
@@ -1,29 +1,53 @@
-use crossterm::event::KeyCode;
-use termreader_core::Context;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use termreader_core::{backup::ImportMode, journal::Action, Context};
 
-use crate::helpers::StatefulList;
 use crate::setup::{
-    add_book_to_lib, continue_book_history, continue_reading_global_select, create_category,
-    delete_category, enter_book_opts_categories, enter_book_view, enter_category_options,
-    enter_category_select, enter_typing, exit_typing, goto_next_ch, goto_prev_ch,
-    move_book_category, move_category_down, move_category_up, remove_history_entry, rename_book,
-    rename_category, search_book_details, search_source, start_book_from_beginning,
-    start_book_from_ch, BookViewType,
+    add_book_tag, add_book_to_lib, add_bookmark, add_highlight, continue_book_history,
+    continue_reading_global_select, copy_book_to_profile, create_backup, create_category,
+    create_smart_category, delete_bookmark, delete_category, delete_from_trash, delete_highlight,
+    delete_smart_category, edit_book_tag, enter_annotations_export, enter_backup_select,
+    enter_book_opts_bookmarks, enter_book_opts_categories, enter_book_opts_highlights,
+    enter_book_opts_profiles, enter_book_opts_status, enter_book_opts_tags, enter_book_reorder,
+    enter_book_view, enter_category_options, enter_category_select, enter_library_filter,
+    enter_reader_selection, enter_reading_stats, enter_smart_category_select,
+    enter_smart_category_typing, enter_sort_select, enter_text_filter, enter_timeline_book_view,
+    enter_trash, enter_typing, exit_typing, export_annotations, export_library,
+    export_reading_sessions, goto_next_ch, goto_prev_ch, import_library, import_lnreader_backup,
+    jump_to_bookmark, jump_to_highlight, mark_reader_selection, move_book, move_book_category,
+    move_category_down, move_category_up, perform_action, redo_change, reload_from_disk,
+    remove_history_entry, rename_book, rename_category, restore_backup, restore_from_trash,
+    search_book_details, search_source, set_book_status, set_category_sort, set_sync_dir,
+    start_book_from_beginning, start_book_from_ch, sync_now, toggle_library_filter, undo_change,
+    update_book, update_library, BookMove, BookViewType,
 };
 use crate::state::{
-    channels::BookInfoDetails, sources::SourceNovelPreviewSelection, AppState, HistoryScreen,
-    LibScreen, Screen, SettingsScreen, SourceScreen, UpdateScreen,
+    channels::BookInfoDetails, history::HistoryView, sources::SourceNovelPreviewSelection,
+    AppState, HistoryScreen, LibScreen, Screen, SettingsScreen, SourceScreen, UpdateScreen,
 };
 use crate::ui::sources::BookViewOption;
 use open;
 
-pub fn handle_controls(ctx: &mut Context, app_state: &mut AppState, mut key: KeyCode) {
+pub fn handle_controls(ctx: &mut Context, app_state: &mut AppState, event: KeyEvent) {
     // Handle typing seperately to other controls
     if app_state.typing {
-        handle_typing(ctx, app_state, key);
+        handle_typing(ctx, app_state, event.code);
         return;
     }
 
+    // Status messages are shown while typing, then until the next key press
+    app_state.status_message = None;
+
+    let mut key = event.code;
+    if app_state.in_main_screen() {
+        match key {
+            KeyCode::Char('u') => return undo_change(app_state, ctx),
+            KeyCode::Char('r') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                return redo_change(app_state, ctx)
+            }
+            _ => (),
+        }
+    }
+
     // Simple aliasing
     key = match key {
         // Vim bindings
@@ -35,6 +59,11 @@ pub fn handle_controls(ctx: &mut Context, app_state: &mut AppState, mut key: Key
     };
 
     if matches!(key, KeyCode::Esc) || matches!(key, KeyCode::Char('q')) {
+        // Leaving the reader while selecting words only stops selecting them
+        if app_state.screen == Screen::Reader && app_state.reader_data.get_selection().is_some() {
+            app_state.reader_data.clear_selection();
+            return;
+        }
         control_back(app_state, ctx)
     }
 
@@ -45,14 +74,25 @@ pub fn handle_controls(ctx: &mut Context, app_state: &mut AppState, mut key: Key
                 control_main_menu(app_state, key);
                 control_library_menu(ctx, app_state, key);
             }
-            LibScreen::BookView | LibScreen::BookViewCategory => {
-                control_book_view_opts(ctx, app_state, key)
-            }
+            LibScreen::BookView
+            | LibScreen::BookViewCategory
+            | LibScreen::BookViewProfile
+            | LibScreen::BookViewTags
+            | LibScreen::BookViewStatus
+            | LibScreen::BookViewBookmarks
+            | LibScreen::BookViewHighlights => control_book_view_opts(ctx, app_state, key),
             LibScreen::CategorySelect => control_library_category_select(ctx, app_state, key),
             LibScreen::CategoryOptions => control_library_category_options(ctx, app_state, key),
+            LibScreen::Trash => control_library_trash(ctx, app_state, key),
+            LibScreen::Filter => control_library_filter(ctx, app_state, key),
+            LibScreen::Sort => control_library_sort(ctx, app_state, key),
+            LibScreen::Reorder => control_library_reorder(ctx, app_state, key),
         },
         Screen::Updates(s) => match s {
-            UpdateScreen::Main => control_main_menu(app_state, key),
+            UpdateScreen::Main => {
+                control_main_menu(app_state, key);
+                control_updates_menu(ctx, app_state, key);
+            }
         },
         Screen::Sources(s) => match s {
             SourceScreen::Main => {
@@ -73,7 +113,13 @@ pub fn handle_controls(ctx: &mut Context, app_state: &mut AppState, mut key: Key
             HistoryScreen::BookView => control_book_view_opts(ctx, app_state, key),
         },
         Screen::Settings(s) => match s {
-            SettingsScreen::Main => control_main_menu(app_state, key),
+            SettingsScreen::Main => {
+                control_main_menu(app_state, key);
+                control_settings_menu(ctx, app_state, key);
+            }
+            SettingsScreen::BackupSelect => control_settings_backup_select(ctx, app_state, key),
+            // The stats are only looked at, so there's nothing to control
+            SettingsScreen::Stats => (),
         },
     }
 }
@@ -135,6 +181,11 @@ fn control_library_category_select(ctx: &mut Context, app_state: &mut AppState,
                         app_state.update_screen(Screen::Lib(LibScreen::Main))
                     }
                 }
+                // Delete smart category
+                5 => {
+                    delete_smart_category(app_state, ctx);
+                    app_state.update_screen(Screen::Lib(LibScreen::Main))
+                }
                 _ => unreachable!(),
             }
         }
@@ -161,6 +212,10 @@ fn control_library_category_options(ctx: &mut Context, app_state: &mut AppState,
             0 => enter_typing(app_state),
             // Re-order categories / Rename categories / Delete categories
             1 | 2 | 3 => enter_category_select(app_state, ctx),
+            // Create smart category
+            4 => enter_smart_category_typing(app_state),
+            // Delete smart categories
+            5 => enter_smart_category_select(app_state, ctx),
             _ => unreachable!(),
         },
         KeyCode::Char('c') => app_state.update_screen(Screen::Lib(LibScreen::Main)),
@@ -184,7 +239,17 @@ fn control_main_menu(app_state: &mut AppState, key: KeyCode) {
     };
 }
 
-fn control_library_menu(ctx: &Context, app_state: &mut AppState, key: KeyCode) {
+fn control_updates_menu(ctx: &Context, app_state: &mut AppState, key: KeyCode) {
+    match key {
+        KeyCode::Up => app_state.updates_data.select_prev_entry(ctx),
+        KeyCode::Down => app_state.updates_data.select_next_entry(ctx),
+        // Update every book in the library
+        KeyCode::Char('U') => update_library(app_state, ctx),
+        _ => (),
+    }
+}
+
+fn control_library_menu(ctx: &mut Context, app_state: &mut AppState, key: KeyCode) {
     match key {
         KeyCode::Char('}') | KeyCode::Right => app_state.lib_data.select_next_category(ctx),
         KeyCode::Char('{') | KeyCode::Left => app_state.lib_data.select_previous_category(ctx),
@@ -204,6 +269,102 @@ fn control_library_menu(ctx: &Context, app_state: &mut AppState, key: KeyCode) {
             }
         }
         KeyCode::Char('c') => enter_category_options(app_state),
+        KeyCode::Char('t') => enter_trash(app_state, ctx),
+        KeyCode::Char('f') => enter_library_filter(app_state, ctx),
+        KeyCode::Char('s') => enter_sort_select(app_state, ctx),
+        KeyCode::Char('/') => enter_text_filter(app_state),
+        KeyCode::Char('m') => enter_book_reorder(app_state, ctx),
+        KeyCode::Char('T') => move_book(app_state, ctx, BookMove::ToTop),
+        _ => (),
+    }
+}
+
+fn control_library_reorder(ctx: &mut Context, app_state: &mut AppState, key: KeyCode) {
+    match key {
+        KeyCode::Up => move_book(app_state, ctx, BookMove::Up),
+        KeyCode::Down => move_book(app_state, ctx, BookMove::Down),
+        KeyCode::Char('T') => move_book(app_state, ctx, BookMove::ToTop),
+        KeyCode::Enter | KeyCode::Char('m') => control_back(app_state, ctx),
+        _ => (),
+    }
+}
+
+fn control_library_sort(ctx: &mut Context, app_state: &mut AppState, key: KeyCode) {
+    match key {
+        KeyCode::Up => app_state.buffer.temporary_list.previous(),
+        KeyCode::Down => app_state.buffer.temporary_list.next(),
+        KeyCode::Enter => {
+            set_category_sort(app_state, ctx);
+            app_state.update_screen(Screen::Lib(LibScreen::Main))
+        }
+        _ => (),
+    }
+}
+
+fn control_library_filter(ctx: &Context, app_state: &mut AppState, key: KeyCode) {
+    match key {
+        KeyCode::Up => app_state.buffer.temporary_list.previous(),
+        KeyCode::Down => app_state.buffer.temporary_list.next(),
+        KeyCode::Enter => toggle_library_filter(app_state, ctx),
+        _ => (),
+    }
+}
+
+fn control_library_trash(ctx: &mut Context, app_state: &mut AppState, key: KeyCode) {
+    match key {
+        KeyCode::Up => app_state.lib_data.select_prev_trash_entry(ctx),
+        KeyCode::Down => app_state.lib_data.select_next_trash_entry(ctx),
+        KeyCode::Enter | KeyCode::Char('r') => restore_from_trash(app_state, ctx),
+        KeyCode::Char('d') => delete_from_trash(app_state, ctx),
+        KeyCode::Char('D') => {
+            ctx.empty_trash();
+            app_state.lib_data.reset_trash_selection(ctx);
+        }
+        _ => (),
+    }
+}
+
+fn control_settings_menu(ctx: &mut Context, app_state: &mut AppState, key: KeyCode) {
+    match key {
+        KeyCode::Up => app_state.settings_data.options.previous(),
+        KeyCode::Down => app_state.settings_data.options.next(),
+        KeyCode::Enter => match app_state
+            .settings_data
+            .options
+            .selected_idx()
+            .expect("an option should always be selected")
+        {
+            // Create backup
+            0 => create_backup(app_state, ctx),
+            // Restore backup (replace)
+            1 => enter_backup_select(app_state, ctx, ImportMode::Replace),
+            // Restore backup (merge)
+            2 => enter_backup_select(app_state, ctx, ImportMode::Merge),
+            // Import LNReader backup, export library, import library
+            3..=5 => enter_typing(app_state),
+            // Sync now
+            6 => sync_now(app_state, ctx),
+            // Set sync folder
+            7 => enter_typing(app_state),
+            // Reload from disk
+            8 => reload_from_disk(app_state, ctx),
+            // Reading statistics
+            9 => enter_reading_stats(app_state, ctx),
+            // Export reading sessions
+            10 => enter_typing(app_state),
+            _ => unreachable!(),
+        },
+        _ => (),
+    }
+}
+
+fn control_settings_backup_select(ctx: &mut Context, app_state: &mut AppState, key: KeyCode) {
+    match key {
+        KeyCode::Up => app_state.buffer.temporary_list.previous(),
+        KeyCode::Down => app_state.buffer.temporary_list.next(),
+        KeyCode::Enter => {
+            let _ = restore_backup(app_state, ctx);
+        }
         _ => (),
     }
 }
@@ -272,14 +433,25 @@ fn handle_typing(ctx: &mut Context, app_state: &mut AppState, key: KeyCode) {
         KeyCode::Char(c) => {
             app_state.buffer.text.push(c);
         }
-        KeyCode::Esc => exit_typing(app_state),
+        KeyCode::Esc => {
+            exit_typing(app_state);
+            // Cancelling the comment of a highlight cancels the highlight
+            app_state.reader_data.clear_selection();
+        }
         KeyCode::Enter => {
             match app_state.screen {
+                // The text filter is already applied as it's typed
+                Screen::Lib(LibScreen::Main) => (),
                 Screen::Sources(SourceScreen::Select) => {
                     let id = app_state.source_data.get_selected_source_id(ctx);
                     search_source(app_state, ctx, id, Some(app_state.buffer.text.clone()))
                         .expect("source should exist");
                 }
+                Screen::Lib(LibScreen::BookView)
+                    if app_state.lib_data.global_selected_book_opts.selected_idx() == Some(12) =>
+                {
+                    export_annotations(app_state, ctx, app_state.buffer.text.clone());
+                }
                 Screen::Lib(LibScreen::BookView) => {
                     // Rename a book
                     let mut book = app_state.lib_data
@@ -292,6 +464,21 @@ fn handle_typing(ctx: &mut Context, app_state: &mut AppState, key: KeyCode) {
                     };
                     rename_book(&mut book, new_name);
                 }
+                Screen::Lib(LibScreen::BookViewTags) => {
+                    add_book_tag(app_state, ctx, app_state.buffer.text.clone());
+                }
+                Screen::Reader if app_state.reader_data.get_selection().is_some() => {
+                    add_highlight(app_state, ctx, app_state.buffer.text.clone())
+                }
+                Screen::Reader => add_bookmark(app_state, ctx, app_state.buffer.text.clone()),
+                // Creating a smart category
+                Screen::Lib(LibScreen::CategoryOptions)
+                    if app_state.lib_data.category_options.selected_idx() == Some(4) =>
+                {
+                    if create_smart_category(app_state, ctx, app_state.buffer.text.clone()) {
+                        app_state.update_screen(Screen::Lib(LibScreen::Main))
+                    }
+                }
                 // Creating a category
                 Screen::Lib(LibScreen::CategoryOptions) => {
                     let created =
@@ -306,18 +493,57 @@ fn handle_typing(ctx: &mut Context, app_state: &mut AppState, key: KeyCode) {
                     rename_category(app_state, ctx, app_state.buffer.text.to_string());
                     app_state.update_screen(Screen::Lib(LibScreen::Main))
                 }
+                Screen::Settings(SettingsScreen::Main) => {
+                    let path = app_state.buffer.text.to_string();
+                    match app_state.settings_data.options.selected_idx() {
+                        Some(3) => import_lnreader_backup(app_state, ctx, path),
+                        Some(4) => export_library(app_state, ctx, path),
+                        Some(5) => import_library(app_state, ctx, path),
+                        Some(7) => set_sync_dir(app_state, ctx, path),
+                        Some(10) => export_reading_sessions(app_state, ctx, path),
+                        _ => unreachable!(),
+                    }
+                }
                 _ => unreachable!(),
             }
             app_state.typing = false;
         }
         _ => (),
     }
+
+    // Filter the library as the filter is typed, clearing it if typing is cancelled
+    if app_state.screen == Screen::Lib(LibScreen::Main) {
+        let filter = if app_state.typing {
+            app_state.buffer.text.clone()
+        } else if key == KeyCode::Esc {
+            String::new()
+        } else {
+            return;
+        };
+        app_state.lib_data.set_text_filter(ctx, filter);
+    }
 }
 
 fn control_book_view_opts(ctx: &mut Context, app_state: &mut AppState, key: KeyCode) {
     match key {
+        KeyCode::Char('d') if app_state.screen == Screen::Lib(LibScreen::BookViewBookmarks) => {
+            delete_bookmark(app_state, ctx)
+        }
+        KeyCode::Char('d') if app_state.screen == Screen::Lib(LibScreen::BookViewHighlights) => {
+            delete_highlight(app_state, ctx)
+        }
         KeyCode::Char(']') | KeyCode::Tab => {
-            if matches!(app_state.screen, Screen::Lib(LibScreen::BookViewCategory)) {
+            if matches!(
+                app_state.screen,
+                Screen::Lib(
+                    LibScreen::BookViewCategory
+                        | LibScreen::BookViewProfile
+                        | LibScreen::BookViewTags
+                        | LibScreen::BookViewStatus
+                        | LibScreen::BookViewBookmarks
+                        | LibScreen::BookViewHighlights
+                )
+            ) {
                 return;
             }
             app_state
@@ -326,7 +552,17 @@ fn control_book_view_opts(ctx: &mut Context, app_state: &mut AppState, key: KeyC
                 .next_opts();
         }
         KeyCode::Char('[') | KeyCode::BackTab => {
-            if matches!(app_state.screen, Screen::Lib(LibScreen::BookViewCategory)) {
+            if matches!(
+                app_state.screen,
+                Screen::Lib(
+                    LibScreen::BookViewCategory
+                        | LibScreen::BookViewProfile
+                        | LibScreen::BookViewTags
+                        | LibScreen::BookViewStatus
+                        | LibScreen::BookViewBookmarks
+                        | LibScreen::BookViewHighlights
+                )
+            ) {
                 return;
             }
             app_state
@@ -338,7 +574,17 @@ fn control_book_view_opts(ctx: &mut Context, app_state: &mut AppState, key: KeyC
             SourceNovelPreviewSelection::Options => match app_state.buffer.book_view_option {
                 BookViewOption::None => unreachable!(),
                 BookViewOption::LibOptions => {
-                    if matches!(app_state.screen, Screen::Lib(LibScreen::BookViewCategory)) {
+                    if matches!(
+                        app_state.screen,
+                        Screen::Lib(
+                            LibScreen::BookViewCategory
+                                | LibScreen::BookViewProfile
+                                | LibScreen::BookViewTags
+                                | LibScreen::BookViewStatus
+                                | LibScreen::BookViewBookmarks
+                                | LibScreen::BookViewHighlights
+                        )
+                    ) {
                         app_state.buffer.temporary_list.previous()
                     } else {
                         app_state.lib_data.global_selected_book_opts.previous()
@@ -360,7 +606,17 @@ fn control_book_view_opts(ctx: &mut Context, app_state: &mut AppState, key: KeyC
             SourceNovelPreviewSelection::Options => match app_state.buffer.book_view_option {
                 BookViewOption::None => unreachable!(),
                 BookViewOption::LibOptions => {
-                    if matches!(app_state.screen, Screen::Lib(LibScreen::BookViewCategory)) {
+                    if matches!(
+                        app_state.screen,
+                        Screen::Lib(
+                            LibScreen::BookViewCategory
+                                | LibScreen::BookViewProfile
+                                | LibScreen::BookViewTags
+                                | LibScreen::BookViewStatus
+                                | LibScreen::BookViewBookmarks
+                                | LibScreen::BookViewHighlights
+                        )
+                    ) {
                         app_state.buffer.temporary_list.next()
                     } else {
                         app_state.lib_data.global_selected_book_opts.next()
@@ -379,9 +635,32 @@ fn control_book_view_opts(ctx: &mut Context, app_state: &mut AppState, key: KeyC
             match app_state.source_data.novel_preview_selected_field {
                 SourceNovelPreviewSelection::Summary => (),
                 SourceNovelPreviewSelection::Options => {
-                    if matches!(app_state.screen, Screen::Lib(LibScreen::BookViewCategory)) {
-                        move_book_category(app_state, ctx)
-                            .expect("a book and category should always be selected here");
+                    if matches!(
+                        app_state.screen,
+                        Screen::Lib(
+                            LibScreen::BookViewCategory
+                                | LibScreen::BookViewProfile
+                                | LibScreen::BookViewTags
+                                | LibScreen::BookViewStatus
+                                | LibScreen::BookViewBookmarks
+                                | LibScreen::BookViewHighlights
+                        )
+                    ) {
+                        if app_state.screen == Screen::Lib(LibScreen::BookViewProfile) {
+                            copy_book_to_profile(app_state, ctx)
+                                .expect("a book and profile should always be selected here");
+                        } else if app_state.screen == Screen::Lib(LibScreen::BookViewTags) {
+                            edit_book_tag(app_state, ctx);
+                        } else if app_state.screen == Screen::Lib(LibScreen::BookViewStatus) {
+                            set_book_status(app_state, ctx);
+                        } else if app_state.screen == Screen::Lib(LibScreen::BookViewBookmarks) {
+                            jump_to_bookmark(app_state, ctx);
+                        } else if app_state.screen == Screen::Lib(LibScreen::BookViewHighlights) {
+                            jump_to_highlight(app_state, ctx);
+                        } else {
+                            move_book_category(app_state, ctx)
+                                .expect("a book and category should always be selected here");
+                        }
                         return;
                     }
 
@@ -402,6 +681,12 @@ fn control_book_view_opts(ctx: &mut Context, app_state: &mut AppState, key: KeyC
                                 // 4 => Restart
                                 // 5 => Remove from lib
                                 // 6 => Open in browser
+                                // 7 => Copy to profile
+                                // 8 => Edit tags
+                                // 9 => Set reading status
+                                // 10 => Bookmarks
+                                // 11 => Highlights
+                                // 12 => Export annotations
                                 0 => {
                                     match continue_reading_global_select(app_state, ctx) {
                                         Ok(()) => (),
@@ -415,34 +700,27 @@ fn control_book_view_opts(ctx: &mut Context, app_state: &mut AppState, key: KeyC
                                         .lib_data
                                         .get_selected_book(ctx)
                                         .expect("a book must be selected to be in this menu");
-                                    let id = book.get_id();
-                                    let source =
-                                        ctx.get_book_source(book.get_id()).unwrap().clone();
-
-                                    let mut real_book = book.get_book();
-                                    real_book.update(&source);
-                                    ctx.replace_book(id, real_book);
-
-                                    let book = ctx.get_book(id).unwrap();
-
-                                    app_state.buffer.chapter_previews =
-                                        StatefulList::from(book.get_chapters().unwrap());
-                                    app_state.buffer.novel = Some(book);
+                                    update_book(app_state, ctx, book);
                                 }
                                 2 => enter_book_opts_categories(app_state, ctx),
                                 3 => enter_typing(app_state),
                                 4 => {
-                                    let mut book = app_state.lib_data
+                                    let book_id = app_state.lib_data
                                         .get_selected_book(ctx)
-                                        .expect("a book has not been selected, even though this menu is only accessible on a selected book");
-                                    book.reset_progress();
+                                        .expect("a book has not been selected, even though this menu is only accessible on a selected book")
+                                        .get_id();
+                                    perform_action(app_state, ctx, Action::ResetProgress(book_id));
                                 }
                                 5 => {
                                     let book_id = app_state.lib_data
                                         .get_selected_book(ctx)
                                         .expect("a book has not been selected, even though this menu is only accessible on a selected book")
                                         .get_id();
-                                    ctx.remove_from_lib(book_id);
+                                    perform_action(
+                                        app_state,
+                                        ctx,
+                                        Action::RemoveFromLibrary(book_id),
+                                    );
                                     app_state.lib_data.reset_selection(ctx);
                                     app_state.lib_data.global_selected_book_opts.select_first();
                                     app_state.update_screen(Screen::Lib(LibScreen::Main))
@@ -452,6 +730,12 @@ fn control_book_view_opts(ctx: &mut Context, app_state: &mut AppState, key: KeyC
                                     let link = book.get_full_url().unwrap();
                                     open::that_detached(link).unwrap();
                                 }
+                                7 => enter_book_opts_profiles(app_state),
+                                8 => enter_book_opts_tags(app_state, ctx),
+                                9 => enter_book_opts_status(app_state, ctx),
+                                10 => enter_book_opts_bookmarks(app_state, ctx),
+                                11 => enter_book_opts_highlights(app_state, ctx),
+                                12 => enter_annotations_export(app_state, ctx),
                                 _ => unreachable!(),
                             };
                         }
@@ -477,7 +761,11 @@ fn control_book_view_opts(ctx: &mut Context, app_state: &mut AppState, key: KeyC
                                         // TODO: maybe remove this?
                                         app_state.screen = app_state.prev_screens.pop().unwrap();
                                     } else {
-                                        ctx.remove_from_lib(novel.get_id())
+                                        perform_action(
+                                            app_state,
+                                            ctx,
+                                            Action::RemoveFromLibrary(novel.get_id()),
+                                        )
                                     }
                                     app_state.source_data.swap_library_options();
                                 }
@@ -535,7 +823,11 @@ fn control_book_view_opts(ctx: &mut Context, app_state: &mut AppState, key: KeyC
                                         .get_book_ref();
 
                                     if book.in_library() {
-                                        ctx.remove_from_lib(book.get_id());
+                                        perform_action(
+                                            app_state,
+                                            ctx,
+                                            Action::RemoveFromLibrary(book.get_id()),
+                                        );
                                     } else {
                                         ctx.add_to_lib(book.get_id(), None).expect(
                                             "we've checked that the book isn't in the library",
@@ -593,6 +885,23 @@ fn control_back(app_state: &mut AppState, ctx: &mut Context) {
 }
 
 fn control_reader(ctx: &mut Context, app_state: &mut AppState, key: KeyCode) {
+    // Time spent away from the reader isn't counted as reading, so that session ends here
+    if let Some(session) = app_state.reader_data.note_activity() {
+        app_state.record_reading_session(ctx, session);
+    }
+
+    if app_state.reader_data.get_selection().is_some() {
+        match key {
+            KeyCode::Up => app_state.reader_data.scroll_selection(true),
+            KeyCode::Down => app_state.reader_data.scroll_selection(false),
+            KeyCode::Left => app_state.reader_data.move_selection(true),
+            KeyCode::Right => app_state.reader_data.move_selection(false),
+            KeyCode::Enter => mark_reader_selection(app_state),
+            _ => (),
+        }
+        return;
+    }
+
     match key {
         KeyCode::Up => app_state.reader_data.scroll_up(),
         KeyCode::Down => app_state.reader_data.scroll_down(),
@@ -604,14 +913,28 @@ fn control_reader(ctx: &mut Context, app_state: &mut AppState, key: KeyCode) {
             // Again, do nothing if it fails
             let _ = goto_prev_ch(app_state, ctx);
         }
+        KeyCode::Char('b') => enter_typing(app_state),
+        KeyCode::Char('v') => enter_reader_selection(app_state),
         _ => (),
     }
 }
 
 fn control_history_menu(ctx: &Context, app_state: &mut AppState, key: KeyCode) {
+    if app_state.history_data.view == HistoryView::Timeline {
+        match key {
+            KeyCode::Up => app_state.history_data.select_prev_event(ctx),
+            KeyCode::Down => app_state.history_data.select_next_event(ctx),
+            KeyCode::Char('t') => app_state.history_data.toggle_view(),
+            KeyCode::Enter => enter_timeline_book_view(app_state, ctx),
+            _ => (),
+        }
+        return;
+    }
+
     match key {
         KeyCode::Up => app_state.history_data.select_prev_entry(ctx),
         KeyCode::Down => app_state.history_data.select_next_entry(ctx),
+        KeyCode::Char('t') => app_state.history_data.toggle_view(),
         KeyCode::Enter => {
             let b = app_state.history_data.get_selected_book(ctx);
             let Some(entry) = b else {
@@ -1,4 +1,4 @@
-use chrono::{TimeZone, Utc};
+use chrono::{Local, TimeZone};
 use ratatui::widgets::ListState;
 
 /// A structure containing both the vector of items, `items`, as well as the state, `state`
@@ -137,9 +137,9 @@ impl<T> StatefulList<T> {
     }
 }
 
-/// Converts a UNIX timestamp into a formatting date/time string.
-pub fn to_datetime(timestamp_secs: u64) -> String {
-    let dt = Utc.timestamp_opt(timestamp_secs as i64, 0).unwrap();
+/// Converts a UNIX timestamp into a formatted local time of day, e.g. for lists grouped by day.
+pub fn to_local_time(timestamp_secs: u64) -> String {
+    let dt = Local.timestamp_opt(timestamp_secs as i64, 0).unwrap();
 
-    dt.format("%d/%m/%y, %H:%M").to_string()
+    dt.format("%H:%M").to_string()
 }
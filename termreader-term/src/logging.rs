@@ -2,6 +2,7 @@ use anyhow::Result;
 use directories::ProjectDirs;
 use lazy_static::lazy_static;
 use std::path::PathBuf;
+use termreader_core::storage::StorageBackend;
 use tracing_error::ErrorLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
@@ -11,6 +12,8 @@ lazy_static! {
         std::env::var(format!("{}_DATA", PROJECT_NAME.clone()))
             .ok()
             .map(PathBuf::from);
+    pub static ref STORAGE_ENV: String = format!("{}_STORAGE", PROJECT_NAME.clone());
+    pub static ref PROFILE_ENV: String = format!("{}_PROFILE", PROJECT_NAME.clone());
     pub static ref LOG_ENV: String = format!("{}_LOGLEVEL", PROJECT_NAME.clone());
     pub static ref LOG_FILE: String = format!("{}.log", env!("CARGO_PKG_NAME"));
 }
@@ -30,6 +33,22 @@ pub fn get_data_dir() -> PathBuf {
     directory
 }
 
+/// Returns the storage backend requested by the user, if any.
+/// If none is requested, the backend should be detected from the existing data
+pub fn get_storage_backend() -> Result<Option<StorageBackend>> {
+    match std::env::var(STORAGE_ENV.clone()) {
+        Err(_) => Ok(None),
+        Ok(s) => match s.to_lowercase().as_str() {
+            "json" => Ok(Some(StorageBackend::Json)),
+            "sqlite" => Ok(Some(StorageBackend::Sqlite)),
+            _ => anyhow::bail!(
+                "unknown storage backend '{}', expected 'json' or 'sqlite'",
+                s
+            ),
+        },
+    }
+}
+
 pub fn initialize_logging() -> Result<()> {
     let directory = get_data_dir();
     std::fs::create_dir_all(directory.clone())?;
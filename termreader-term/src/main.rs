@@ -1,4 +1,5 @@
 // #![allow(dead_code, unused_imports, unused_variables)]
+pub mod args;
 pub mod controls;
 pub mod helpers;
 pub mod logging;
@@ -10,29 +11,74 @@ pub mod ui;
 use crate::controls::handle_controls;
 use crate::logging::initialize_logging;
 use crate::state::channels::RequestData;
+use crate::state::config::ConfigData;
 use crate::state::AppState;
 use crate::state::Screen;
 use crate::ui::ui_main;
 use anyhow::Result;
+use args::Args;
 use crossterm::{
     event::{self, Event},
     execute, terminal,
 };
 use helpers::StatefulList;
-use logging::get_data_dir;
+use logging::{get_data_dir, get_storage_backend};
 use ratatui::prelude::*;
 use setup::enter_book_view;
+use setup::finish_book_update;
+use setup::finish_library_import;
 use setup::BookViewType;
 use state::channels::BookInfo;
 use state::channels::BookInfoDetails;
 use state::SourceScreen;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use termreader_core::book::Book;
+use termreader_core::profile::{get_profile_path, list_profiles};
+use termreader_core::storage::StorageBackend;
 use termreader_core::Context;
+use termreader_core::TRError;
 use ui::reader::ui_reader;
 
+/// How long to wait for input before redrawing and checking for autosaves/signals
+const TICK_RATE: Duration = Duration::from_millis(250);
+
 fn main() -> Result<()> {
     // Start logging
     initialize_logging()?;
+
+    // Load data before touching the terminal, so that any errors are printed normally
+    let args = Args::parse()?;
+    let profile = select_profile(&get_data_dir(), args.profile)?;
+    let project_dir = get_profile_path(&get_data_dir(), &profile)?;
+    let mut ctx = load_context(project_dir, get_storage_backend()?, args.read_only)?;
+    // The retention settings must be in place before anything saves, or the defaults would be applied to the data
+    ConfigData::load(&ctx.get_save_dir())
+        .unwrap_or_default()
+        .apply(&mut ctx);
+    if args.fsck {
+        return check_data(&mut ctx);
+    }
+    let mut app_state = AppState::build(&ctx);
+    app_state.profile = profile;
+    if let Some(dir) = app_state.config.sync_dir.clone() {
+        // Failing to sync shouldn't stop the user from reading, they can sync again later
+        if let Err(e) = ctx.enable_sync(dir).and_then(|_| ctx.sync()) {
+            tracing::error!("failed to sync with other devices: {e}");
+        }
+        app_state.reload_data(&ctx);
+    }
+
+    // Set when the process is asked to terminate, so that we can save and exit cleanly
+    let shutdown = Arc::new(AtomicBool::new(false));
+    register_signals(&shutdown)?;
+    install_panic_hook();
+
     // Set up the terminal
     terminal::enable_raw_mode()?;
     let mut stdout = std::io::stdout();
@@ -44,29 +90,179 @@ fn main() -> Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Load data, run the app, and save data
-    let project_dir = get_data_dir();
-    let mut ctx = Context::build(project_dir)?;
-    let mut app_state = AppState::build(&ctx);
-    let res = run_app(&mut terminal, &mut ctx, &mut app_state);
+    // Run the app. A panic should never lose the user's data, so we catch it and save anyway.
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        run_app(&mut terminal, &mut ctx, &mut app_state, &shutdown)
+    }));
+
+    // The panic hook will have already restored the terminal if we panicked
+    if res.is_ok() {
+        restore_terminal()?;
+        terminal.show_cursor()?;
+    }
+
+    let saved = save_state(&mut ctx, &mut app_state);
+
+    match res {
+        // Errors must be printed after the terminal is fixed, or they won't show (properly).
+        Ok(Err(e)) => println!("An error occured: {:?}", e),
+        Ok(Ok(())) => (),
+        Err(panic) => {
+            // The crash is what needs reporting, so failing to save can't be allowed to hide it
+            if let Err(e) = saved {
+                tracing::error!("failed to save after a crash: {e:?}");
+                eprintln!("Failed to save after a crash: {:?}", e);
+            }
+            panic::resume_unwind(panic)
+        }
+    }
+
+    saved
+}
+
+/// Returns the profile to load. If one wasn't requested and there are several, the user is asked to pick one
+fn select_profile(data_dir: &Path, requested: Option<String>) -> Result<String> {
+    if let Some(profile) = requested {
+        return Ok(profile);
+    }
+    let mut profiles = list_profiles(data_dir)?;
+    if profiles.len() == 1 {
+        return Ok(profiles.remove(0));
+    }
 
-    app_state.config.save(&ctx.get_save_dir())?;
-    ctx.save()?;
+    println!("Pick a profile:");
+    for (i, profile) in profiles.iter().enumerate() {
+        println!("  {}: {}", i + 1, profile);
+    }
+    loop {
+        print!("> ");
+        std::io::stdout().flush()?;
+        let mut input = String::new();
+        if std::io::stdin().read_line(&mut input)? == 0 {
+            anyhow::bail!("no profile was picked");
+        }
+        let input = input.trim();
+        // Accept either the number or the name of a profile
+        let picked = input
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| i.checked_sub(1))
+            .and_then(|i| profiles.get(i))
+            .or_else(|| profiles.iter().find(|p| p.as_str() == input));
+        match picked {
+            Some(profile) => return Ok(profile.clone()),
+            None => println!("'{}' is not one of the profiles", input),
+        }
+    }
+}
+
+/// Loads the data. If another instance is using it, the user is asked whether to open it read-only
+fn load_context(
+    data_path: PathBuf,
+    backend: Option<StorageBackend>,
+    read_only: bool,
+) -> Result<Context> {
+    if read_only {
+        return Ok(Context::build_read_only(data_path, backend)?);
+    }
+    let res = match backend {
+        Some(backend) => Context::build_with_storage(data_path.clone(), backend),
+        None => Context::build(data_path.clone()),
+    };
+    let Err(TRError::Locked(holder)) = res else {
+        return Ok(res?);
+    };
+
+    println!(
+        "The data is in use by another instance of termreader ({}).",
+        holder
+    );
+    if confirm("Open it read-only? Nothing will be saved.")? {
+        Ok(Context::build_read_only(data_path, backend)?)
+    } else {
+        anyhow::bail!(
+            "the data is in use by another instance of termreader, close it or use --read-only"
+        )
+    }
+}
 
-    // Restore the terminal to its initial state
+/// Checks the data for problems and reports them, offering to repair them. Used instead of starting the TUI
+fn check_data(ctx: &mut Context) -> Result<()> {
+    let problems = ctx.verify();
+    if problems.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+    println!("Found {} problem(s):", problems.len());
+    for problem in problems.iter() {
+        println!("  {}", problem);
+    }
+
+    if ctx.is_read_only() {
+        println!("The data is read-only, so it can't be repaired.");
+    } else if confirm("Repair them?")? {
+        let repaired = ctx.repair();
+        ctx.save()?;
+        println!("Repaired {} problem(s).", repaired.len());
+    }
+    Ok(())
+}
+
+/// Asks the user a yes or no question on the command line, defaulting to no
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    Ok(input.trim().eq_ignore_ascii_case("y"))
+}
+
+/// Restores the terminal to its initial state
+fn restore_terminal() -> Result<()> {
     terminal::disable_raw_mode()?;
     execute!(
-        terminal.backend_mut(),
+        std::io::stdout(),
         terminal::LeaveAlternateScreen,
-        event::DisableMouseCapture
+        event::DisableMouseCapture,
+        crossterm::cursor::Show
     )?;
-    terminal.show_cursor()?;
+    Ok(())
+}
+
+/// Restore the terminal before the panic message is printed, otherwise it can't be read
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        default_hook(info);
+    }));
+}
 
-    // Errors must be printed after the terminal is fixed, or they won't show (properly).
-    if let Err(e) = res {
-        println!("An error occured: {:?}", e);
+/// Set `shutdown` upon receiving any signal asking the process to terminate
+fn register_signals(shutdown: &Arc<AtomicBool>) -> Result<()> {
+    #[cfg(unix)]
+    let signals = [
+        signal_hook::consts::SIGTERM,
+        signal_hook::consts::SIGHUP,
+        signal_hook::consts::SIGINT,
+    ];
+    #[cfg(not(unix))]
+    let signals = [signal_hook::consts::SIGTERM, signal_hook::consts::SIGINT];
+
+    for signal in signals {
+        signal_hook::flag::register(signal, Arc::clone(shutdown))?;
     }
+    Ok(())
+}
 
+/// Save both the core data and the config. Nothing is saved if the data was opened read-only
+fn save_state(ctx: &mut Context, app_state: &mut AppState) -> Result<()> {
+    if !ctx.is_read_only() {
+        app_state.config.save(&ctx.get_save_dir())?;
+        ctx.save()?;
+    }
+    app_state.save_requested = false;
+    app_state.last_save = Instant::now();
     Ok(())
 }
 
@@ -74,67 +270,55 @@ fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     ctx: &mut Context,
     app_state: &mut AppState,
+    shutdown: &AtomicBool,
 ) -> Result<()> {
     loop {
-        if app_state.quit {
+        if app_state.quit || shutdown.load(Ordering::Relaxed) {
+            // Make sure the latest reading progress is stored before exiting
+            if app_state.screen == Screen::Reader {
+                app_state.update_from_reader(ctx);
+            }
             break;
         }
+
+        if app_state.autosave_due() {
+            // A failed autosave shouldn't stop the user from reading, it'll be retried on the next one
+            if let Err(e) = save_state(ctx, app_state) {
+                tracing::error!("autosave failed: {e:?}");
+                app_state.save_requested = false;
+                app_state.last_save = Instant::now();
+            }
+        }
+
         match app_state.screen {
             Screen::Reader => terminal.draw(|f| ui_reader(f, app_state))?,
             _ => terminal.draw(|f| ui_main(f, ctx, app_state))?,
         };
 
         if app_state.channel.loading {
-            if let Ok(data) = app_state.channel.reciever.recv() {
-                match data {
-                    RequestData::SearchResults(res) => {
-                        app_state.buffer.novel_search_res = StatefulList::from(res?);
-                        app_state.update_screen(Screen::Sources(SourceScreen::SearchRes));
-                    }
-                    RequestData::BookInfo((res, info)) => {
-                        let novel = res?;
-                        let book = match ctx.get_book_url(novel.get_full_url().to_string()) {
-                            Some(b) => b,
-                            None => {
-                                let book = Book::from_novel(novel);
-                                let book_id = book.get_id();
-                                ctx.add_book(book);
-                                ctx.get_book(book_id).expect("we just added the book")
-                            }
-                        };
-
-                        match info {
-                            BookInfoDetails::SourceWithOptions => {
-                                enter_book_view(app_state, ctx, book, BookViewType::Source);
-                            }
-                            BookInfoDetails::HistoryWithOptions => {
-                                enter_book_view(app_state, ctx, book, BookViewType::History);
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    RequestData::Chapter((book_info, res, ch)) => match book_info {
-                        BookInfo::NewBook(_b) => {
-                            unreachable!()
-                            // b.global_set_ch(ch);
-                            // app_state.move_to_reader(b, Some(res?));
-                        }
-                        BookInfo::ID(id) => {
-                            let book = ctx.get_book(id);
-                            match book {
-                                    Some(mut b) => {
-                                        b.global_set_chapter(ch).unwrap();
-                                        app_state.move_to_reader(b.clone(), Some(res?) );
-                                    },
-                                    None => panic!("Book existed so we returned an ID, but we were unable to find it?"),
-                                }
-                        }
-                    },
-                }
+            let data = match app_state.channel.reciever.recv_timeout(TICK_RATE) {
+                Ok(data) => data,
+                // Keep redrawing while waiting so that signals and autosaves are still handled
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => unreachable!("we always hold a sender"),
+            };
+            // Books updated in the background aren't what's being waited for
+            let finished = !matches!(data, RequestData::Updated(_));
+            handle_request(ctx, app_state, data)?;
+            if finished {
                 app_state.channel.loading = false;
-                // `event::read()` is blocking so continue to redraw after
-                continue;
             }
+            // `event::read()` is blocking so continue to redraw after
+            continue;
+        }
+
+        // Apply the results of any work happening in the background
+        while let Ok(data) = app_state.channel.reciever.try_recv() {
+            handle_request(ctx, app_state, data)?;
+        }
+
+        if !event::poll(TICK_RATE)? {
+            continue;
         }
 
         if let Event::Key(key) = event::read()? {
@@ -142,8 +326,74 @@ fn run_app<B: Backend>(
                 // We only care about key presses
                 continue;
             }
-            handle_controls(ctx, app_state, key.code);
+            handle_controls(ctx, app_state, key);
+        }
+    }
+    Ok(())
+}
+
+/// Handles the result of a request made through the channel
+fn handle_request(ctx: &mut Context, app_state: &mut AppState, data: RequestData) -> Result<()> {
+    match data {
+        RequestData::SearchResults(res) => {
+            app_state.buffer.novel_search_res = StatefulList::from(res?);
+            app_state.update_screen(Screen::Sources(SourceScreen::SearchRes));
+        }
+        RequestData::BookInfo((res, info)) => {
+            let novel = res?;
+            let book = match ctx.get_book_url(novel.get_full_url().to_string()) {
+                Some(b) => b,
+                None => {
+                    let book = Book::from_novel(novel);
+                    let book_id = book.get_id();
+                    ctx.add_book(book);
+                    ctx.get_book(book_id).expect("we just added the book")
+                }
+            };
+
+            match info {
+                BookInfoDetails::SourceWithOptions => {
+                    enter_book_view(app_state, ctx, book, BookViewType::Source);
+                }
+                BookInfoDetails::HistoryWithOptions => {
+                    enter_book_view(app_state, ctx, book, BookViewType::History);
+                }
+                _ => unreachable!(),
+            }
+        }
+        RequestData::Chapter((book_info, res, ch)) => {
+            match book_info {
+                BookInfo::NewBook(_b) => {
+                    unreachable!()
+                    // b.global_set_ch(ch);
+                    // app_state.move_to_reader(b, Some(res?));
+                }
+                BookInfo::ID(id) => {
+                    let book = ctx.get_book(id);
+                    match book {
+                        Some(mut b) => {
+                            b.global_set_chapter(ch).unwrap();
+                            app_state.move_to_reader(ctx, b.clone(), Some(res?));
+                        }
+                        None => panic!(
+                            "Book existed so we returned an ID, but we were unable to find it?"
+                        ),
+                    }
+                }
+            }
+        }
+        RequestData::ChapterAt((id, res, ch, word)) => {
+            let Some(mut b) = ctx.get_book(id) else {
+                panic!("Book existed so we returned an ID, but we were unable to find it?")
+            };
+            b.global_set_chapter(ch).unwrap();
+            app_state.move_to_reader(ctx, b, Some(res?));
+            app_state.reader_data.jump_to_word(word);
+        }
+        RequestData::LibraryImport((books, failed)) => {
+            finish_library_import(app_state, ctx, books, failed);
         }
+        RequestData::Updated((id, res)) => finish_book_update(app_state, ctx, id, res),
     }
     Ok(())
 }
@@ -1,4 +1,7 @@
-use ratatui::{style::Style, widgets::StatefulWidget};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    widgets::StatefulWidget,
+};
 use termreader_core::book::ChapterProgress;
 use termreader_sources::chapter::Chapter;
 
@@ -21,6 +24,10 @@ pub struct GlobalReaderContents {
     pub words: Vec<String>,
     /// The character length of the entire text
     pub text_length: usize,
+    /// The first and last words of each highlight in the chapter
+    pub highlights: Vec<(usize, usize)>,
+    /// The first and last words being selected to highlight, if any
+    pub selection: Option<(usize, usize)>,
 }
 
 #[derive(Debug, Clone)]
@@ -36,6 +43,11 @@ pub struct GlobalReaderState {
 }
 
 impl GlobalReaderContents {
+    const HIGHLIGHT_STYLE: Style = Style::new()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::UNDERLINED);
+    const SELECTION_STYLE: Style = Style::new().add_modifier(Modifier::REVERSED);
+
     /// Returns the set of lines to be displayed for a given width and height.
     pub fn get_display_lines(
         &self,
@@ -43,23 +55,46 @@ impl GlobalReaderContents {
         term_width: u16,
         term_height: u16,
     ) -> Vec<String> {
+        self.get_display_words(state, term_width, term_height)
+            .into_iter()
+            .map(|line| {
+                let words: Vec<&str> = line.iter().map(|&i| self.words[i].as_str()).collect();
+                words.join(" ")
+            })
+            .collect()
+    }
+
+    /// Returns the indexes of the words in each line to be displayed for a given width and height.
+    pub fn get_display_words(
+        &self,
+        state: &mut GlobalReaderState,
+        term_width: u16,
+        term_height: u16,
+    ) -> Vec<Vec<usize>> {
         if (term_width, term_height) != (state.prev_term_width, state.prev_term_height) {
             state.prev_start_words = Vec::new();
             state.prev_term_width = term_width;
             state.prev_term_height = term_height
         }
-        let mut words = self.words.iter().skip(state.start_word_idx).peekable();
+        let mut words = self
+            .words
+            .iter()
+            .enumerate()
+            .skip(state.start_word_idx)
+            .peekable();
         let mut word_count = 0;
         let mut lines = Vec::new();
-        let mut current_line = String::new();
+        let mut current_line = Vec::new();
+        let mut current_len = 0;
         while lines.len() < term_height as usize {
-            let next_word = words.peek_mut();
+            let next_word = words.peek();
             match next_word {
-                Some(next) => {
+                Some(&(idx, next)) => {
                     // If we've come across a newline then special logic is required
-                    if next == &&String::from("\n") {
+                    if next == "\n" {
                         lines.push(current_line);
-                        current_line = String::new();
+                        current_line = Vec::new();
+                        current_len = 0;
                         // Move to the next word
                         words.next();
                         // Newlines count as words
@@ -71,18 +106,21 @@ impl GlobalReaderContents {
                         panic!("Encountered word longer than terminal width")
                     } else if current_line.is_empty() {
                         // No need for a space if a line is empty
-                        // The unwrap is safe as the next word was peeked
-                        current_line.push_str(&words.next().unwrap());
+                        current_line.push(idx);
+                        current_len = next.len();
+                        words.next();
                         word_count += 1;
-                    } else if current_line.len() + 1 + next.len() < term_width as usize {
+                    } else if current_len + 1 + next.len() < term_width as usize {
                         // Line isn't empty so check if a space will also fit.
-                        current_line.push(' ');
-                        current_line.push_str(&words.next().unwrap());
+                        current_line.push(idx);
+                        current_len += 1 + next.len();
+                        words.next();
                         word_count += 1;
                     } else {
                         // We have a word that doesn't fit, so move onto the next line.
                         lines.push(current_line);
-                        current_line = String::new();
+                        current_line = Vec::new();
+                        current_len = 0;
                         continue;
                     }
                 }
@@ -96,6 +134,18 @@ impl GlobalReaderContents {
         state.end_word_idx = state.start_word_idx + word_count;
         lines
     }
+
+    /// Returns the style of a word, which differs if it's selected or highlighted
+    fn get_word_style(&self, word_idx: usize) -> Style {
+        let within = |&(start, end): &(usize, usize)| (start..=end).contains(&word_idx);
+        if self.selection.as_ref().is_some_and(within) {
+            Self::SELECTION_STYLE
+        } else if self.highlights.iter().any(within) {
+            Self::HIGHLIGHT_STYLE
+        } else {
+            Style::new()
+        }
+    }
 }
 
 impl GlobalReader {
@@ -120,7 +170,12 @@ impl GlobalReader {
         }
 
         Self {
-            contents: GlobalReaderContents { words, text_length },
+            contents: GlobalReaderContents {
+                words,
+                text_length,
+                highlights: Vec::new(),
+                selection: None,
+            },
             state: GlobalReaderState {
                 start_word_idx: 0,
                 end_word_idx: 0,
@@ -241,6 +296,73 @@ impl GlobalReader {
         }
     }
 
+    /// Scroll so that the text starts at a word, e.g. to go to a bookmark
+    pub fn jump_to_word(&mut self, word_idx: usize) {
+        self.state.start_word_idx = word_idx.min(self.contents.words.len().saturating_sub(1));
+        self.state.prev_start_words = Vec::new();
+    }
+
+    /// Returns up to a number of the first words being displayed, ignoring newlines
+    pub fn get_snippet(&self, word_count: usize) -> String {
+        let words: Vec<&str> = self
+            .contents
+            .words
+            .iter()
+            .skip(self.state.start_word_idx)
+            .filter(|w| *w != "\n")
+            .take(word_count)
+            .map(|w| w.as_str())
+            .collect();
+        words.join(" ")
+    }
+
+    /// Returns the words from one index to another, ignoring newlines
+    pub fn get_text(&self, start: usize, end: usize) -> String {
+        let words: Vec<&str> = self
+            .contents
+            .words
+            .iter()
+            .take(end + 1)
+            .skip(start)
+            .filter(|w| *w != "\n")
+            .map(|w| w.as_str())
+            .collect();
+        words.join(" ")
+    }
+
+    /// Returns the index of the first word from an index that isn't a newline, searching backwards if `back` is set
+    pub fn find_word(&self, from: usize, back: bool) -> Option<usize> {
+        let is_word = |i: &usize| self.contents.words.get(*i).is_some_and(|w| w != "\n");
+        if back {
+            (0..=from).rev().find(is_word)
+        } else {
+            (from..self.contents.words.len()).find(is_word)
+        }
+    }
+
+    /// Scroll until a word is displayed, e.g. as a selection moves
+    pub fn scroll_to_word(&mut self, word_idx: usize, term_width: u16, term_height: u16) {
+        while word_idx < self.state.start_word_idx {
+            let start = self.state.start_word_idx;
+            self.scroll_up(term_width, term_height);
+            if self.state.start_word_idx == start {
+                break;
+            }
+        }
+        // Work out where the displayed text ends before checking against it
+        self.contents
+            .get_display_words(&mut self.state, term_width, term_height);
+        while word_idx >= self.state.end_word_idx {
+            let start = self.state.start_word_idx;
+            self.scroll_down(term_width, term_height);
+            if self.state.start_word_idx == start {
+                break;
+            }
+            self.contents
+                .get_display_words(&mut self.state, term_width, term_height);
+        }
+    }
+
     pub fn get_progress(&self) -> ChapterProgress {
         if self.state.end_word_idx >= self.contents.words.len() - 1 {
             return ChapterProgress::Finished;
@@ -262,9 +384,27 @@ impl StatefulWidget for GlobalReaderContents {
         buf: &mut ratatui::prelude::Buffer,
         state: &mut Self::State,
     ) {
-        let display = self.get_display_lines(state, area.width, area.height);
+        let display = self.get_display_words(state, area.width, area.height);
         for (i, line) in display.into_iter().enumerate() {
-            buf.set_string(area.x, area.y + i as u16, line, Style::new())
+            let y = area.y + i as u16;
+            let mut x = area.x;
+            let mut prev_style = None;
+            for word_idx in line {
+                let style = self.get_word_style(word_idx);
+                if let Some(prev_style) = prev_style {
+                    // The space between two words is only styled if both of them are
+                    let space_style = if prev_style == style {
+                        style
+                    } else {
+                        Style::new()
+                    };
+                    x = buf.set_stringn(x, y, " ", usize::MAX, space_style).0;
+                }
+                x = buf
+                    .set_stringn(x, y, &self.words[word_idx], usize::MAX, style)
+                    .0;
+                prev_style = Some(style);
+            }
         }
     }
 }
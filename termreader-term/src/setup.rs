@@ -1,18 +1,35 @@
-use std::thread;
+use std::{
+    path::{Path, PathBuf},
+    thread,
+};
 
 use crate::{
     helpers::StatefulList,
+    logging::get_data_dir,
     state::{
         channels::{BookInfo, BookInfoDetails, RequestData},
+        config::ConfigData,
         sources::SourceNovelPreviewSelection,
-        AppState, HistoryScreen, LibScreen, Screen, SourceScreen,
+        AppState, HistoryScreen, LibScreen, Screen, SettingsScreen, SourceScreen,
     },
     ui::sources::BookViewOption,
 };
-use termreader_core::{book::BookRef, history::HistoryEntry, id::ID, Context};
+use termreader_core::{
+    backup::ImportMode,
+    book::{Book, BookRef, ReadingStatus},
+    export::{read_library_export, FailedEntry},
+    history::HistoryEntry,
+    id::ID,
+    journal::Action,
+    profile::{get_profile_path, list_profiles},
+    smart::SmartCategory,
+    sort::BookSort,
+    updates::ChapterDiff,
+    Context,
+};
 use termreader_sources::{
     novel::NovelPreview,
-    sources::{Scrape, SortOrder, SourceID},
+    sources::{Scrape, SortOrder, Source, SourceID},
 };
 use thiserror::Error;
 
@@ -88,6 +105,31 @@ pub fn continue_reading_global_select(
     Ok(())
 }
 
+/// Enter the book view of the book of the selected event in the history timeline, from its history entry.
+///
+/// Does nothing if the book is no longer in the history
+pub fn enter_timeline_book_view(app_state: &mut AppState, ctx: &Context) {
+    let Some(event) = app_state.history_data.get_selected_event(ctx) else {
+        return;
+    };
+    if !app_state.history_data.select_book_entry(ctx, event) {
+        app_state.status_message = Some(format!(
+            "\"{}\" is no longer in the history",
+            event.get_book_name()
+        ));
+        return;
+    }
+    let Some(entry) = app_state.history_data.get_selected_book(ctx) else {
+        return;
+    };
+    let book = entry.get_book_ref();
+    if book.is_local() {
+        app_state.status_message = Some(String::from("Only books from sources can be opened"));
+        return;
+    }
+    enter_book_view(app_state, ctx, book, BookViewType::History);
+}
+
 /// Set up for and enter the typing screen
 pub fn enter_typing(app_state: &mut AppState) {
     app_state.buffer.text.clear();
@@ -215,11 +257,520 @@ pub fn enter_book_opts_categories(app_state: &mut AppState, ctx: &Context) {
     app_state.update_screen(Screen::Lib(LibScreen::BookViewCategory));
 }
 
+/// Set up for and enter the screen where a profile to copy the selected book to is picked.
+///
+/// Does nothing if there are no other profiles
+pub fn enter_book_opts_profiles(app_state: &mut AppState) {
+    let profiles = match list_profiles(&get_data_dir()) {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::error!("failed to list profiles: {e}");
+            return;
+        }
+    };
+    let profiles: Vec<String> = profiles
+        .into_iter()
+        .filter(|p| p != &app_state.profile)
+        .collect();
+    if profiles.is_empty() {
+        return;
+    }
+    app_state.buffer.temporary_list = StatefulList::from(profiles);
+    app_state.update_screen(Screen::Lib(LibScreen::BookViewProfile));
+}
+
+/// Copy the selected book, with its progress, to the selected profile
+pub fn copy_book_to_profile(app_state: &mut AppState, ctx: &Context) -> Result<(), EntryError> {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return Err(EntryError::UnselectedLibBook);
+    };
+    let Some(profile) = app_state.buffer.temporary_list.selected().cloned() else {
+        return Err(EntryError::UnsetValue);
+    };
+
+    let res = get_profile_path(&get_data_dir(), &profile)
+        .and_then(|path| ctx.copy_book_to_profile(book.get_id(), &path));
+    if let Err(e) = res {
+        tracing::error!("failed to copy book to profile {profile}: {e}");
+    }
+
+    // Go back to the book's options
+    app_state.screen = app_state
+        .prev_screens
+        .pop()
+        .unwrap_or(Screen::Lib(LibScreen::BookView));
+    Ok(())
+}
+
+/// Set up for and enter the screen where the tags of the selected book are edited
+pub fn enter_book_opts_tags(app_state: &mut AppState, ctx: &Context) {
+    refresh_book_tags(app_state, ctx);
+    app_state.update_screen(Screen::Lib(LibScreen::BookViewTags));
+}
+
+/// Set up for and enter the screen where the reading status of the selected book is picked
+pub fn enter_book_opts_status(app_state: &mut AppState, ctx: &Context) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    let current = book.get_reading_status();
+    let options: Vec<String> = ReadingStatus::ALL.iter().map(|s| s.to_string()).collect();
+    app_state.buffer.temporary_list = StatefulList::from(options);
+    let idx = ReadingStatus::ALL.iter().position(|s| *s == current);
+    app_state.buffer.temporary_list.state_mut().select(idx);
+    app_state.update_screen(Screen::Lib(LibScreen::BookViewStatus));
+}
+
+/// Set the reading status of the selected book to the selected status
+pub fn set_book_status(app_state: &mut AppState, ctx: &Context) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    let Some(idx) = app_state.buffer.temporary_list.selected_idx() else {
+        return;
+    };
+    book.set_reading_status(ReadingStatus::ALL[idx]);
+
+    // The book is no longer shown if the library is filtered by another status
+    if app_state.lib_data.filter.matches(&book) {
+        app_state.screen = app_state
+            .prev_screens
+            .pop()
+            .unwrap_or(Screen::Lib(LibScreen::BookView));
+    } else {
+        app_state.lib_data.reset_selection(ctx);
+        app_state.update_screen(Screen::Lib(LibScreen::Main));
+    }
+}
+
+/// Set up for and enter the screen where the bookmarks of the selected book are listed.
+///
+/// Does nothing if the book has no bookmarks
+pub fn enter_book_opts_bookmarks(app_state: &mut AppState, ctx: &Context) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    if book.get_bookmarks().is_empty() {
+        app_state.status_message = Some(String::from(
+            "This book has no bookmarks. Press b in the reader to add one",
+        ));
+        return;
+    }
+    app_state.buffer.temporary_list = StatefulList::new();
+    refresh_book_bookmarks(app_state, &book);
+    app_state.buffer.temporary_list.select_first();
+    app_state.update_screen(Screen::Lib(LibScreen::BookViewBookmarks));
+}
+
+/// Lists the bookmarks of a book, keeping the selection where it can
+fn refresh_book_bookmarks(app_state: &mut AppState, book: &BookRef) {
+    let selected = app_state.buffer.temporary_list.selected_idx();
+    let options: Vec<String> = book
+        .get_bookmarks()
+        .iter()
+        .map(|b| match b.get_note() {
+            Some(note) => format!("Ch {}: {} - {}", b.get_chapter(), b.get_name(), note),
+            None => format!("Ch {}: {}", b.get_chapter(), b.get_name()),
+        })
+        .collect();
+    let len = options.len();
+    app_state.buffer.temporary_list = StatefulList::from(options);
+    let selected = selected
+        .map(|i| i.min(len.saturating_sub(1)))
+        .filter(|_| len > 0);
+    app_state.buffer.temporary_list.state_mut().select(selected);
+}
+
+/// Add a bookmark where the reader is, from text written as a name, optionally followed by a `|` and a note.
+/// The bookmark is named after the words it's at if no name is given
+pub fn add_bookmark(app_state: &mut AppState, ctx: &mut Context, text: String) {
+    let Some(book) = app_state.reader_data.get_book() else {
+        return;
+    };
+    let (Some(chapter), Some((word, snippet))) =
+        (book.get_current_ch(), app_state.reader_data.get_anchor())
+    else {
+        return;
+    };
+    let (name, note) = match text.split_once('|') {
+        Some((name, note)) => (name.trim(), Some(note)),
+        None => (text.trim(), None),
+    };
+    let name = if name.is_empty() { &snippet } else { name };
+    app_state.status_message = match ctx.add_bookmark(book.get_id(), chapter, word, name, note) {
+        Ok(()) => Some(format!("Added bookmark \"{name}\"")),
+        Err(e) => Some(format!("Failed to add bookmark: {e}")),
+    };
+}
+
+/// Delete the selected bookmark of the selected book, going back to the book's options if it has none left
+pub fn delete_bookmark(app_state: &mut AppState, ctx: &mut Context) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    let Some(idx) = app_state.buffer.temporary_list.selected_idx() else {
+        return;
+    };
+    if let Err(e) = ctx.remove_bookmark(book.get_id(), idx) {
+        app_state.status_message = Some(format!("Failed to delete bookmark: {e}"));
+    }
+    if book.get_bookmarks().is_empty() {
+        app_state.screen = app_state
+            .prev_screens
+            .pop()
+            .unwrap_or(Screen::Lib(LibScreen::BookView));
+    } else {
+        refresh_book_bookmarks(app_state, &book);
+    }
+}
+
+/// Fetch the chapter of the selected bookmark, then open it in the reader at the bookmark
+pub fn jump_to_bookmark(app_state: &mut AppState, ctx: &Context) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    let Some(idx) = app_state.buffer.temporary_list.selected_idx() else {
+        return;
+    };
+    let Some(bookmark) = book.get_bookmarks().get(idx).cloned() else {
+        return;
+    };
+    open_chapter_at(
+        app_state,
+        ctx,
+        book,
+        bookmark.get_chapter(),
+        bookmark.get_word(),
+    );
+}
+
+/// Fetch a chapter of a book, then open it in the reader at a word
+fn open_chapter_at(app_state: &mut AppState, ctx: &Context, book: BookRef, ch: usize, word: usize) {
+    let id = book.get_id();
+    let (Some(novel_path), Some(chapter_path)) = (book.get_url(), book.get_chapter_url(ch)) else {
+        app_state.status_message = Some(format!("Chapter {ch} of this book no longer exists"));
+        return;
+    };
+    let source = ctx.get_book_source(id).unwrap().clone();
+    let tx = app_state.channel.get_sender();
+
+    app_state.channel.loading = true;
+    thread::spawn(move || {
+        let text = source.parse_chapter(novel_path, chapter_path);
+        let _ = tx.send(RequestData::ChapterAt((id, text, ch, word)));
+    });
+}
+
+/// Set up for and enter the screen where the highlights of the selected book are listed.
+///
+/// Does nothing if the book has no highlights
+pub fn enter_book_opts_highlights(app_state: &mut AppState, ctx: &Context) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    if book.get_highlights().is_empty() {
+        app_state.status_message = Some(String::from(
+            "This book has no highlights. Press v in the reader to select words to highlight",
+        ));
+        return;
+    }
+    app_state.buffer.temporary_list = StatefulList::new();
+    refresh_book_highlights(app_state, &book);
+    app_state.buffer.temporary_list.select_first();
+    app_state.update_screen(Screen::Lib(LibScreen::BookViewHighlights));
+}
+
+/// Lists the highlights of a book, keeping the selection where it can
+fn refresh_book_highlights(app_state: &mut AppState, book: &BookRef) {
+    let selected = app_state.buffer.temporary_list.selected_idx();
+    let options: Vec<String> = book
+        .get_highlights()
+        .iter()
+        .map(|h| match h.get_comment() {
+            Some(comment) => format!("Ch {}: \"{}\" - {}", h.get_chapter(), h.get_text(), comment),
+            None => format!("Ch {}: \"{}\"", h.get_chapter(), h.get_text()),
+        })
+        .collect();
+    let len = options.len();
+    app_state.buffer.temporary_list = StatefulList::from(options);
+    let selected = selected
+        .map(|i| i.min(len.saturating_sub(1)))
+        .filter(|_| len > 0);
+    app_state.buffer.temporary_list.state_mut().select(selected);
+}
+
+/// Start selecting words to highlight in the reader
+pub fn enter_reader_selection(app_state: &mut AppState) {
+    let Some(book) = app_state.reader_data.get_book() else {
+        return;
+    };
+    if book.is_local() {
+        app_state.status_message = Some(String::from("Only books from sources can be highlighted"));
+        return;
+    }
+    app_state.reader_data.start_selection();
+}
+
+/// Mark the start of the selection in the reader, or its end, after which a comment is typed for the highlight
+pub fn mark_reader_selection(app_state: &mut AppState) {
+    if app_state.reader_data.mark_selection() {
+        enter_typing(app_state);
+    }
+}
+
+/// Highlight the words selected in the reader, with the text as a comment
+pub fn add_highlight(app_state: &mut AppState, ctx: &mut Context, text: String) {
+    let Some(book) = app_state.reader_data.get_book() else {
+        return;
+    };
+    let (Some(chapter), Some((span, words))) = (
+        book.get_current_ch(),
+        app_state.reader_data.get_selected_text(),
+    ) else {
+        return;
+    };
+    app_state.reader_data.clear_selection();
+    app_state.status_message =
+        match ctx.add_highlight(book.get_id(), chapter, span, &words, Some(&text)) {
+            Ok(()) => Some(String::from("Added highlight")),
+            Err(e) => Some(format!("Failed to add highlight: {e}")),
+        };
+}
+
+/// Delete the selected highlight of the selected book, going back to the book's options if it has none left
+pub fn delete_highlight(app_state: &mut AppState, ctx: &mut Context) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    let Some(idx) = app_state.buffer.temporary_list.selected_idx() else {
+        return;
+    };
+    if let Err(e) = ctx.remove_highlight(book.get_id(), idx) {
+        app_state.status_message = Some(format!("Failed to delete highlight: {e}"));
+    }
+    if book.get_highlights().is_empty() {
+        app_state.screen = app_state
+            .prev_screens
+            .pop()
+            .unwrap_or(Screen::Lib(LibScreen::BookView));
+    } else {
+        refresh_book_highlights(app_state, &book);
+    }
+}
+
+/// Fetch the chapter of the selected highlight, then open it in the reader at the highlight
+pub fn jump_to_highlight(app_state: &mut AppState, ctx: &Context) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    let Some(idx) = app_state.buffer.temporary_list.selected_idx() else {
+        return;
+    };
+    let Some(highlight) = book.get_highlights().get(idx).cloned() else {
+        return;
+    };
+    open_chapter_at(
+        app_state,
+        ctx,
+        book,
+        highlight.get_chapter(),
+        highlight.get_span().0,
+    );
+}
+
+/// Start typing the path to export the selected book's annotations to, suggesting one named after the book
+pub fn enter_annotations_export(app_state: &mut AppState, ctx: &Context) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    enter_typing(app_state);
+    app_state.buffer.text = format!("{}.md", book.get_name());
+}
+
+/// Export the highlights and bookmarks of the selected book to the given path as Markdown
+pub fn export_annotations(app_state: &mut AppState, ctx: &Context, path: String) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    let path = PathBuf::from(path.trim());
+    app_state.status_message = Some(match ctx.export_annotations(book.get_id(), &path) {
+        Ok(count) => format!("Exported {count} annotations to {}", path.display()),
+        Err(e) => format!("Failed to export annotations: {e}"),
+    });
+}
+
+/// Lists the tags of the selected book, followed by an option to add one
+fn refresh_book_tags(app_state: &mut AppState, ctx: &Context) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    let selected = app_state.buffer.temporary_list.selected_idx();
+    let mut options = book.get_tags();
+    options.push(String::from("Add a tag"));
+    app_state.buffer.temporary_list = StatefulList::from(options);
+    if let Some(idx) = selected {
+        let idx = idx.min(app_state.buffer.temporary_list.items.len() - 1);
+        app_state
+            .buffer
+            .temporary_list
+            .state_mut()
+            .select(Some(idx));
+    }
+}
+
+/// Remove the selected tag from the selected book, or start typing a new tag if adding one was selected
+pub fn edit_book_tag(app_state: &mut AppState, ctx: &mut Context) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    let list = &app_state.buffer.temporary_list;
+    let Some(idx) = list.selected_idx() else {
+        return;
+    };
+    if idx == list.items.len() - 1 {
+        enter_typing(app_state);
+        return;
+    }
+    let tag = list.items[idx].clone();
+    if let Err(e) = ctx.remove_book_tag(book.get_id(), &tag) {
+        app_state.status_message = Some(format!("Failed to remove tag: {e}"));
+    }
+
+    // The book is no longer shown if the library is filtered by the tag
+    let still_shown = app_state
+        .lib_data
+        .get_selected_book(ctx)
+        .is_some_and(|b| b.get_id() == book.get_id());
+    if still_shown {
+        refresh_book_tags(app_state, ctx);
+    } else {
+        app_state.lib_data.reset_selection(ctx);
+        app_state.update_screen(Screen::Lib(LibScreen::Main));
+    }
+}
+
+/// Add a tag to the selected book
+pub fn add_book_tag(app_state: &mut AppState, ctx: &mut Context, tag: String) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    if let Err(e) = ctx.add_book_tag(book.get_id(), &tag) {
+        app_state.status_message = Some(format!("Failed to add tag: {e}"));
+    }
+    refresh_book_tags(app_state, ctx);
+}
+
+/// Set up for and enter the screen where the reading status and tags to filter the library by are picked
+pub fn enter_library_filter(app_state: &mut AppState, ctx: &Context) {
+    app_state.buffer.temporary_list = StatefulList::new();
+    refresh_library_filter(app_state, ctx);
+    app_state.buffer.temporary_list.select_first();
+    app_state.update_screen(Screen::Lib(LibScreen::Filter));
+}
+
+/// Start typing the text filter for the library, starting from the current filter
+pub fn enter_text_filter(app_state: &mut AppState) {
+    app_state.buffer.text = app_state.lib_data.filter.text.clone();
+    app_state.typing = true;
+}
+
+/// Enter a screen where the order of the books in the selected category is picked
+pub fn enter_sort_select(app_state: &mut AppState, ctx: &Context) {
+    let name = app_state.lib_data.get_selected_category_name(ctx);
+    let current = ctx.get_category_sort(&name);
+    let options: Vec<String> = BookSort::ALL
+        .iter()
+        .map(|o| {
+            if *o == current {
+                format!("{o} (current)")
+            } else {
+                o.to_string()
+            }
+        })
+        .collect();
+    app_state.buffer.temporary_list = StatefulList::from(options);
+    let idx = BookSort::ALL.iter().position(|o| *o == current);
+    app_state.buffer.temporary_list.state_mut().select(idx);
+    app_state.update_screen(Screen::Lib(LibScreen::Sort));
+}
+
+/// Sort the selected category by the selected order
+pub fn set_category_sort(app_state: &mut AppState, ctx: &mut Context) {
+    let Some(idx) = app_state.buffer.temporary_list.selected_idx() else {
+        return;
+    };
+    let name = app_state.lib_data.get_selected_category_name(ctx);
+    if let Err(e) = ctx.set_category_sort(&name, BookSort::ALL[idx]) {
+        app_state.status_message = Some(format!("Failed to sort category: {e}"));
+    }
+    app_state.lib_data.reset_selection(ctx);
+}
+
+/// Lists every reading status, followed by every tag in the library, marking those in the filter
+fn refresh_library_filter(app_state: &mut AppState, ctx: &Context) {
+    let selected = app_state.buffer.temporary_list.selected_idx();
+    let filter = &app_state.lib_data.filter;
+    let statuses = ReadingStatus::ALL.iter().map(|s| {
+        let mark = if filter.status == Some(*s) { "*" } else { " " };
+        format!("({mark}) {s}")
+    });
+    let tags = ctx.get_library_tags().into_iter().map(|t| {
+        let mark = if filter.tags.contains(&t) { "x" } else { " " };
+        format!("[{mark}] {t}")
+    });
+    let options: Vec<String> = statuses.chain(tags).collect();
+    app_state.buffer.temporary_list = StatefulList::from(options);
+    app_state.buffer.temporary_list.state_mut().select(selected);
+}
+
+/// Add the selected reading status or tag to the library filter, or remove it if it's already in it
+pub fn toggle_library_filter(app_state: &mut AppState, ctx: &Context) {
+    let Some(idx) = app_state.buffer.temporary_list.selected_idx() else {
+        return;
+    };
+    if let Some(status) = ReadingStatus::ALL.get(idx) {
+        app_state.lib_data.toggle_status_filter(ctx, *status);
+    } else {
+        let idx = idx - ReadingStatus::ALL.len();
+        let Some(tag) = ctx.get_library_tags().into_iter().nth(idx) else {
+            return;
+        };
+        app_state.lib_data.toggle_tag_filter(ctx, tag);
+    }
+    refresh_library_filter(app_state, ctx);
+}
+
 pub fn enter_category_options(app_state: &mut AppState) {
     app_state.lib_data.category_options.select_first();
     app_state.update_screen(Screen::Lib(LibScreen::CategoryOptions));
 }
 
+pub fn enter_trash(app_state: &mut AppState, ctx: &Context) {
+    app_state.lib_data.reset_trash_selection(ctx);
+    app_state.update_screen(Screen::Lib(LibScreen::Trash));
+}
+
+/// Move the selected book in the trash back into the library
+pub fn restore_from_trash(app_state: &mut AppState, ctx: &mut Context) {
+    let Some(id) = app_state.lib_data.get_selected_trash_entry(ctx) else {
+        return;
+    };
+    if let Err(e) = ctx.restore_from_trash(id) {
+        tracing::error!("failed to restore book {:?} from the trash: {}", id, e);
+    }
+    app_state.lib_data.reset_trash_selection(ctx);
+    app_state.lib_data.fix_book_selection_state(ctx);
+}
+
+/// Delete the selected book in the trash for good
+pub fn delete_from_trash(app_state: &mut AppState, ctx: &mut Context) {
+    let Some(id) = app_state.lib_data.get_selected_trash_entry(ctx) else {
+        return;
+    };
+    ctx.delete_from_trash(id);
+    app_state.lib_data.reset_trash_selection(ctx);
+}
+
 pub fn move_book_category(app_state: &mut AppState, ctx: &mut Context) -> Result<(), EntryError> {
     let Some(b) = app_state.lib_data.get_selected_book(ctx) else {
         return Err(EntryError::UnselectedLibBook);
@@ -245,13 +796,9 @@ pub fn delete_category(
     ctx: &mut Context,
     category_name: String,
 ) -> Result<(), ()> {
-    if ctx.delete_library_category(category_name).is_ok() {
-        // If we deleted the last category, and it's currently selected,
-        // We subtract one
-        if ctx.get_library_categories().len() == app_state.lib_data.get_selected_category() {
-            app_state.lib_data.current_category_idx =
-                app_state.lib_data.current_category_idx.saturating_sub(1);
-        }
+    if ctx.perform(Action::DeleteCategory(category_name)).is_ok() {
+        // If we deleted the last category, and it's currently selected, select the one before it
+        app_state.lib_data.fix_category_selection(ctx);
 
         let cats = ctx.get_library_categories().clone();
         app_state.buffer.temporary_list = StatefulList::from(cats);
@@ -275,6 +822,114 @@ pub fn create_category(
     }
 }
 
+/// Start typing a smart category, showing how one is written
+pub fn enter_smart_category_typing(app_state: &mut AppState) {
+    app_state.status_message = Some(String::from(
+        "Write a name, then conditions: unread, updated:DAYS, unopened:DAYS, source:NAME, status:ongoing/completed",
+    ));
+    enter_typing(app_state);
+}
+
+/// Create a smart category from what was typed, returning whether it was created
+pub fn create_smart_category(app_state: &mut AppState, ctx: &mut Context, text: String) -> bool {
+    let res = SmartCategory::parse(&text).and_then(|c| ctx.create_smart_category(c));
+    match res {
+        Ok(()) => {
+            app_state.status_message = None;
+            true
+        }
+        Err(e) => {
+            app_state.status_message = Some(format!("Failed to create smart category: {e}"));
+            false
+        }
+    }
+}
+
+pub fn enter_smart_category_select(app_state: &mut AppState, ctx: &Context) {
+    if ctx.get_smart_categories().is_empty() {
+        app_state.status_message = Some(String::from("There are no smart categories"));
+        return;
+    }
+    let cats = ctx
+        .get_smart_categories()
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    app_state.buffer.temporary_list = StatefulList::from(cats);
+    app_state.update_screen(Screen::Lib(LibScreen::CategorySelect));
+}
+
+/// Delete the selected smart category
+pub fn delete_smart_category(app_state: &mut AppState, ctx: &mut Context) {
+    let Some(idx) = app_state.buffer.temporary_list.selected_idx() else {
+        return;
+    };
+    let Some(name) = ctx
+        .get_smart_categories()
+        .get(idx)
+        .map(|c| c.get_name().to_string())
+    else {
+        return;
+    };
+    if let Err(e) = ctx.delete_smart_category(&name) {
+        app_state.status_message = Some(format!("Failed to delete smart category: {e}"));
+    }
+    app_state.lib_data.fix_category_selection(ctx);
+    app_state.lib_data.reset_selection(ctx);
+}
+
+/// Returns true if books in the selected category are shown in the order they're kept in, so can be moved.
+/// Otherwise, tells the user why they can't be
+fn can_reorder_books(app_state: &mut AppState, ctx: &Context) -> bool {
+    let lib_data = &app_state.lib_data;
+    let reason = if lib_data.in_smart_category(ctx) {
+        "Books can't be moved in smart categories"
+    } else if ctx.get_category_sort(&lib_data.get_selected_category_name(ctx)) != BookSort::Unsorted
+    {
+        "Books can only be moved in unsorted categories"
+    } else if !lib_data.filter.is_empty() {
+        "Books can't be moved while the library is filtered"
+    } else {
+        return true;
+    };
+    app_state.status_message = Some(String::from(reason));
+    false
+}
+
+/// Enter a screen where the selected book is moved within its category
+pub fn enter_book_reorder(app_state: &mut AppState, ctx: &Context) {
+    if app_state.lib_data.get_selected_book(ctx).is_none() || !can_reorder_books(app_state, ctx) {
+        return;
+    }
+    app_state.config.prompt_style = Some(app_state.config.selected_style_2);
+    app_state.update_screen(Screen::Lib(LibScreen::Reorder));
+}
+
+/// The ways a book can be moved within its category
+pub enum BookMove {
+    Up,
+    Down,
+    ToTop,
+}
+
+/// Move the selected book within its category, keeping it selected
+pub fn move_book(app_state: &mut AppState, ctx: &mut Context, direction: BookMove) {
+    let Some(book) = app_state.lib_data.get_selected_book(ctx) else {
+        return;
+    };
+    if !can_reorder_books(app_state, ctx) {
+        return;
+    }
+    let new_pos = match direction {
+        BookMove::Up => ctx.reorder_book_forwards(book.get_id()),
+        BookMove::Down => ctx.reorder_book_backwards(book.get_id()),
+        BookMove::ToTop => ctx.reorder_book_to_front(book.get_id()),
+    };
+    if let Some(new_pos) = new_pos {
+        app_state.lib_data.select_book(new_pos);
+    }
+}
+
 pub fn move_category_up(app_state: &mut AppState, ctx: &mut Context) {
     let cat = app_state
         .buffer
@@ -491,7 +1146,7 @@ pub fn goto_prev_ch(app_state: &mut AppState, ctx: &mut Context) -> Result<(), B
 
 pub fn remove_history_entry(app_state: &mut AppState, ctx: &mut Context, book: ID) {
     // Remove book
-    ctx.remove_history_entry(book);
+    perform_action(app_state, ctx, Action::RemoveHistoryEntry(book));
 
     // Select the previous entry if one exists, otherwise select none
     let sel = app_state.history_data.get_selected_entry_mut().selected();
@@ -570,3 +1225,326 @@ pub fn add_book_to_lib(app_state: &mut AppState, ctx: &mut Context, book: BookRe
     // Select this book if there were previously no books selected
     app_state.lib_data.fix_book_selection_state(ctx);
 }
+
+/// Save the config, then create a backup of all data in the default backup directory
+pub fn create_backup(app_state: &mut AppState, ctx: &mut Context) {
+    // The config is part of the backup, so make sure it's up to date
+    let res = app_state
+        .config
+        .save(&ctx.get_save_dir())
+        .map_err(|e| e.to_string())
+        .and_then(|_| {
+            ctx.create_backup(&ctx.get_backup_dir())
+                .map_err(|e| e.to_string())
+        });
+
+    app_state.settings_data.message = Some(match res {
+        Ok(path) => format!("Created backup {}", path.display()),
+        Err(e) => format!("Failed to create backup: {e}"),
+    });
+}
+
+/// Set up for and enter the screen where a backup to restore is picked
+pub fn enter_backup_select(app_state: &mut AppState, ctx: &Context, mode: ImportMode) {
+    let backups = match ctx.list_backups() {
+        Ok(b) => b,
+        Err(e) => {
+            app_state.settings_data.message = Some(format!("Failed to find backups: {e}"));
+            return;
+        }
+    };
+    if backups.is_empty() {
+        app_state.settings_data.message = Some(String::from("There are no backups to restore"));
+        return;
+    }
+
+    app_state.buffer.temporary_list = StatefulList::from(
+        backups
+            .iter()
+            .map(|p| {
+                p.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Vec<_>>(),
+    );
+    app_state.settings_data.backups = backups;
+    app_state.settings_data.import_mode = mode;
+    app_state.update_screen(Screen::Settings(SettingsScreen::BackupSelect));
+}
+
+/// Restore the selected backup, then return to the main settings screen
+pub fn restore_backup(app_state: &mut AppState, ctx: &mut Context) -> Result<(), EntryError> {
+    let Some(idx) = app_state.buffer.temporary_list.selected_idx() else {
+        return Err(EntryError::UnsetValue);
+    };
+    let path = app_state.settings_data.backups[idx].clone();
+    let mode = app_state.settings_data.import_mode;
+
+    let res = ctx.restore_backup(&path, mode);
+    // The data has changed (possibly partially, if restoring failed), so nothing that refers to it can be kept
+    app_state.reload_data(ctx);
+    if mode == ImportMode::Replace {
+        app_state.config = ConfigData::load(&ctx.get_save_dir()).unwrap_or_default();
+        app_state.config.apply(ctx);
+    }
+
+    app_state.settings_data.message = Some(match res {
+        Ok(()) => format!("Restored backup {}", path.display()),
+        Err(e) => format!("Failed to restore backup: {e}"),
+    });
+    app_state.update_screen(Screen::Settings(SettingsScreen::Main));
+    Ok(())
+}
+
+/// Import an LNReader backup at the given path, showing which novels couldn't be imported
+pub fn import_lnreader_backup(app_state: &mut AppState, ctx: &mut Context, path: String) {
+    let res = ctx.import_lnreader_backup(Path::new(path.trim()));
+    app_state.reload_data(ctx);
+
+    app_state.settings_data.message = Some(match res {
+        Ok(import) if import.unsupported.is_empty() => {
+            format!("Imported {} novels", import.imported)
+        }
+        Ok(import) => format!(
+            "Imported {} novels, skipped {} from unsupported sources: {}",
+            import.imported,
+            import.unsupported.len(),
+            import
+                .unsupported
+                .iter()
+                .map(|n| format!("{} ({})", n.name, n.plugin_id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Err(e) => format!("Failed to import LNReader backup: {e}"),
+    });
+}
+
+/// Export the library to the given path, in the format given by its extension
+pub fn export_library(app_state: &mut AppState, ctx: &Context, path: String) {
+    let path = PathBuf::from(path.trim());
+    app_state.settings_data.message = Some(match ctx.export_library(&path) {
+        Ok(count) => format!("Exported {count} books to {}", path.display()),
+        Err(e) => format!("Failed to export library: {e}"),
+    });
+}
+
+/// Work out the reading statistics, and enter the screen where they're shown
+///
+/// Does nothing if no reading has been recorded
+pub fn enter_reading_stats(app_state: &mut AppState, ctx: &Context) {
+    if ctx.get_reading_sessions().is_empty() {
+        app_state.settings_data.message = Some(String::from(
+            "No reading has been recorded yet. Time spent in the reader is recorded from now on",
+        ));
+        return;
+    }
+    app_state.settings_data.stats = Some(ctx.get_reading_stats());
+    app_state.update_screen(Screen::Settings(SettingsScreen::Stats));
+}
+
+/// Export every reading session to the given path as CSV
+pub fn export_reading_sessions(app_state: &mut AppState, ctx: &Context, path: String) {
+    let path = PathBuf::from(path.trim());
+    app_state.settings_data.message = Some(match ctx.export_reading_sessions(&path) {
+        Ok(count) => format!("Exported {count} reading sessions to {}", path.display()),
+        Err(e) => format!("Failed to export reading sessions: {e}"),
+    });
+}
+
+/// Start importing an exported library from the given path, fetching each book from its source
+pub fn import_library(app_state: &mut AppState, ctx: &Context, path: String) {
+    let entries = match read_library_export(Path::new(path.trim())) {
+        Ok(e) => e,
+        Err(e) => {
+            app_state.settings_data.message = Some(format!("Failed to import library: {e}"));
+            return;
+        }
+    };
+
+    let mut failed = Vec::new();
+    let mut to_resolve = Vec::new();
+    for entry in entries {
+        match ctx.find_source_by_name(&entry.source) {
+            Some(source) => to_resolve.push((source.clone(), entry)),
+            None => failed.push(entry.fail(format!("unknown source {}", entry.source))),
+        }
+    }
+
+    let tx = app_state.channel.get_sender();
+    app_state.channel.loading = true;
+    app_state.settings_data.message = Some(format!("Importing {} books...", to_resolve.len()));
+
+    thread::spawn(move || {
+        let mut books = Vec::new();
+        for (source, entry) in to_resolve {
+            match entry.resolve(&source) {
+                Ok(book) => books.push((book, entry.category)),
+                Err(e) => failed.push(entry.fail(e.to_string())),
+            }
+        }
+        let _ = tx.send(RequestData::LibraryImport((books, failed)));
+    });
+}
+
+/// Add the books from an imported library, showing which books couldn't be imported
+pub fn finish_library_import(
+    app_state: &mut AppState,
+    ctx: &mut Context,
+    books: Vec<(Book, String)>,
+    failed: Vec<FailedEntry>,
+) {
+    let imported = books.len();
+    ctx.import_library_books(books);
+    app_state.reload_data(ctx);
+
+    app_state.settings_data.message = Some(if failed.is_empty() {
+        format!("Imported {imported} books")
+    } else {
+        format!(
+            "Imported {imported} books, failed to import {}: {}",
+            failed.len(),
+            failed
+                .iter()
+                .map(|f| format!("{} ({})", f.title, f.reason))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    });
+}
+
+/// Apply the changes other devices have made, and share the changes made on this one
+pub fn sync_now(app_state: &mut AppState, ctx: &mut Context) {
+    if ctx.get_sync_dir().is_none() {
+        app_state.settings_data.message = Some(String::from("Set a sync folder first"));
+        return;
+    }
+    let res = ctx.sync();
+    app_state.reload_data(ctx);
+    app_state.settings_data.message = Some(match res {
+        Ok(count) => format!("Synced {count} changes from other devices"),
+        Err(e) => format!("Failed to sync: {e}"),
+    });
+}
+
+/// Set the folder data is synced through, disabling syncing if no folder is given
+pub fn set_sync_dir(app_state: &mut AppState, ctx: &mut Context, path: String) {
+    let path = path.trim();
+    if path.is_empty() {
+        ctx.disable_sync();
+        app_state.config.sync_dir = None;
+        app_state.settings_data.message = Some(String::from("Syncing disabled"));
+        return;
+    }
+
+    let dir = PathBuf::from(path);
+    if let Err(e) = ctx.enable_sync(dir.clone()) {
+        app_state.settings_data.message = Some(format!("Failed to enable syncing: {e}"));
+        return;
+    }
+    app_state.config.sync_dir = Some(dir);
+    sync_now(app_state, ctx);
+}
+
+/// Discard the data in memory and load it from disk again, e.g. after another instance has changed it
+pub fn reload_from_disk(app_state: &mut AppState, ctx: &mut Context) {
+    let was_read_only = ctx.is_read_only();
+    let res = ctx.reload();
+    app_state.reload_data(ctx);
+    app_state.config = ConfigData::load(&ctx.get_save_dir()).unwrap_or_default();
+    app_state.config.apply(ctx);
+
+    app_state.settings_data.message = Some(match res {
+        Err(e) => format!("Failed to reload data: {e}"),
+        Ok(()) if ctx.is_read_only() => {
+            String::from("Reloaded data, still read-only as another instance is using it")
+        }
+        Ok(()) if was_read_only => String::from(
+            "Reloaded data, changes will now be saved as no other instance is using it",
+        ),
+        Ok(()) => String::from("Reloaded data"),
+    });
+}
+
+/// Update a book from its source in the background
+pub fn update_book(app_state: &mut AppState, ctx: &Context, book: BookRef) {
+    let Some(source) = ctx.get_book_source(book.get_id()) else {
+        return;
+    };
+    spawn_updates(app_state, vec![(book, source.clone())]);
+}
+
+/// Update every book in the library from its source in the background
+pub fn update_library(app_state: &mut AppState, ctx: &Context) {
+    spawn_updates(app_state, ctx.get_library_books_with_sources());
+}
+
+/// Updates books one after another in a background thread. The books are changed directly by the thread,
+/// only how their chapters changed is sent back so that the history and updates can follow
+fn spawn_updates(app_state: &mut AppState, books: Vec<(BookRef, Source)>) {
+    if books.is_empty() {
+        return;
+    }
+    app_state.updates_data.updating += books.len();
+    let tx = app_state.channel.get_sender();
+    thread::spawn(move || {
+        for (book, source) in books {
+            let res = book.update(&source).map_err(anyhow::Error::from);
+            let _ = tx.send(RequestData::Updated((book.get_id(), res)));
+        }
+    });
+}
+
+/// Record how the chapters of a book that was updated in the background changed
+pub fn finish_book_update(
+    app_state: &mut AppState,
+    ctx: &mut Context,
+    id: ID,
+    res: anyhow::Result<ChapterDiff>,
+) {
+    app_state.updates_data.updating = app_state.updates_data.updating.saturating_sub(1);
+    match res {
+        Err(e) => tracing::error!("failed to update book {:?}: {}", id, e),
+        Ok(diff) => {
+            ctx.record_update(id, diff);
+            app_state.updates_data.fix_entry_selection(ctx);
+            app_state.save_requested = true;
+        }
+    }
+
+    // Show the new chapters if the book is being viewed
+    if let Some(book) = app_state.buffer.novel.as_ref().filter(|b| b.get_id() == id) {
+        if let Ok(chapters) = book.get_chapters() {
+            app_state.buffer.chapter_previews = StatefulList::from(chapters);
+        }
+    }
+}
+
+/// Perform an action that can be undone, showing why if it fails
+pub fn perform_action(app_state: &mut AppState, ctx: &mut Context, action: Action) {
+    if let Err(e) = ctx.perform(action) {
+        app_state.status_message = Some(format!("Failed: {e}"));
+    }
+}
+
+/// Undo the latest change, showing what was undone
+pub fn undo_change(app_state: &mut AppState, ctx: &mut Context) {
+    app_state.status_message = Some(match ctx.undo() {
+        Ok(Some(change)) => format!("Undid {change}"),
+        Ok(None) => String::from("Nothing to undo"),
+        Err(e) => format!("Failed to undo: {e}"),
+    });
+    app_state.fix_selections(ctx);
+}
+
+/// Redo the latest undone change, showing what was redone
+pub fn redo_change(app_state: &mut AppState, ctx: &mut Context) {
+    app_state.status_message = Some(match ctx.redo() {
+        Ok(Some(change)) => format!("Redid {change}"),
+        Ok(None) => String::from("Nothing to redo"),
+        Err(e) => format!("Failed to redo: {e}"),
+    });
+    app_state.fix_selections(ctx);
+}
@@ -3,7 +3,7 @@
 // This is required as async is not used.
 use anyhow::Result;
 use std::sync::mpsc::{Receiver, Sender};
-use termreader_core::{book::Book, id::ID};
+use termreader_core::{book::Book, export::FailedEntry, id::ID, updates::ChapterDiff};
 use termreader_sources::{
     chapter::Chapter,
     novel::{Novel, NovelPreview},
@@ -18,8 +18,13 @@ pub enum RequestData {
     BookInfo((Result<Novel>, BookInfoDetails)),
     /// A chapter and it's number
     Chapter((BookInfo, Result<Chapter>, usize)),
-    // /// Update info for a book
-    // Updated(Book),
+    /// A chapter, along with its number and the word in it to jump to, e.g. for a bookmark
+    ChapterAt((ID, Result<Chapter>, usize, usize)),
+    /// Books resolved from an exported library, along with their categories, and the entries that couldn't be
+    LibraryImport((Vec<(Book, String)>, Vec<FailedEntry>)),
+    /// How the chapters of a book that was updated in the background changed.
+    /// Unlike other requests, these arrive without `ChannelData::loading` being set
+    Updated((ID, Result<ChapterDiff>)),
 }
 
 pub enum BookInfo {
@@ -3,6 +3,10 @@ use std::{fs, path::PathBuf};
 
 use ratatui::style::{Color, Style};
 use serde::{Deserialize, Serialize};
+use termreader_core::backup::DEFAULT_AUTO_BACKUPS;
+use termreader_core::timeline::{DEFAULT_HISTORY_MAX_AGE_DAYS, DEFAULT_HISTORY_MAX_EVENTS};
+use termreader_core::trash::DEFAULT_TRASH_RETENTION_DAYS;
+use termreader_core::Context;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigData {
@@ -12,6 +16,27 @@ pub struct ConfigData {
     pub greyed_style: Style,
     #[serde(skip)]
     pub prompt_style: Option<Style>,
+    /// How often to autosave, in seconds. Autosaving on a timer is disabled when this is 0
+    #[serde(default = "ConfigData::default_autosave_interval")]
+    pub autosave_interval_secs: u64,
+    /// Whether to save whenever the chapter being read changes
+    #[serde(default = "ConfigData::default_autosave_on_chapter_change")]
+    pub autosave_on_chapter_change: bool,
+    /// How many automatic backups to keep. Automatic backups are disabled when this is 0
+    #[serde(default = "ConfigData::default_auto_backup_count")]
+    pub auto_backup_count: usize,
+    /// How many days books removed from the library are kept in the trash. They are kept forever when this is 0
+    #[serde(default = "ConfigData::default_trash_retention_days")]
+    pub trash_retention_days: u64,
+    /// How many chapters opened and finished are kept in the history timeline. All are kept when this is 0
+    #[serde(default = "ConfigData::default_history_max_events")]
+    pub history_max_events: usize,
+    /// How many days chapters opened and finished are kept in the history timeline. They are kept forever when this is 0
+    #[serde(default = "ConfigData::default_history_max_age_days")]
+    pub history_max_age_days: u64,
+    /// A folder shared with other devices to sync data through, if syncing is enabled
+    #[serde(default)]
+    pub sync_dir: Option<PathBuf>,
 }
 
 impl Default for ConfigData {
@@ -22,6 +47,13 @@ impl Default for ConfigData {
             unselected_style: Self::DEFAULT_UNSELECTED_STYLE,
             greyed_style: Self::DEFAULT_GREYED_STYLE,
             prompt_style: None,
+            autosave_interval_secs: Self::default_autosave_interval(),
+            autosave_on_chapter_change: Self::default_autosave_on_chapter_change(),
+            auto_backup_count: Self::default_auto_backup_count(),
+            trash_retention_days: Self::default_trash_retention_days(),
+            history_max_events: Self::default_history_max_events(),
+            history_max_age_days: Self::default_history_max_age_days(),
+            sync_dir: None,
         }
     }
 }
@@ -32,7 +64,31 @@ impl ConfigData {
     pub const DEFAULT_SELECTED_STYLE_2: ratatui::style::Style = Style::new().fg(Color::Yellow);
     pub const DEFAULT_GREYED_STYLE: ratatui::style::Style = Style::new().fg(Color::DarkGray);
 
-    pub fn save(self, path: &PathBuf) -> Result<()> {
+    fn default_autosave_interval() -> u64 {
+        300
+    }
+
+    fn default_autosave_on_chapter_change() -> bool {
+        true
+    }
+
+    fn default_auto_backup_count() -> usize {
+        DEFAULT_AUTO_BACKUPS
+    }
+
+    fn default_trash_retention_days() -> u64 {
+        DEFAULT_TRASH_RETENTION_DAYS
+    }
+
+    fn default_history_max_events() -> usize {
+        DEFAULT_HISTORY_MAX_EVENTS
+    }
+
+    fn default_history_max_age_days() -> u64 {
+        DEFAULT_HISTORY_MAX_AGE_DAYS
+    }
+
+    pub fn save(&self, path: &PathBuf) -> Result<()> {
         let json = serde_json::to_string(&self)?;
         std::fs::write(path.join("config.json"), json)?;
         Ok(())
@@ -46,6 +102,13 @@ impl ConfigData {
         }
     }
 
+    /// Passes the settings that the core library enforces on to the context
+    pub fn apply(&self, ctx: &mut Context) {
+        ctx.set_auto_backup_count(self.auto_backup_count);
+        ctx.set_trash_retention_days(self.trash_retention_days);
+        ctx.set_history_retention(self.history_max_events, self.history_max_age_days);
+    }
+
     pub fn get_prompt_style(&self) -> Style {
         self.prompt_style.unwrap_or_else(|| self.selected_style)
     }
@@ -1,13 +1,26 @@
 // This module contains data related to the history tab of the TUI.
 
 use ratatui::widgets::ListState;
-use termreader_core::{history::HistoryEntry, Context};
+use termreader_core::{history::HistoryEntry, timeline::HistoryEvent, Context};
 
 use crate::helpers::StatefulList;
 
+/// What the history tab lists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HistoryView {
+    /// The latest chapter read of each book
+    #[default]
+    Latest,
+    /// Every chapter opened and finished
+    Timeline,
+}
+
 pub struct HistoryData {
     /// The currently selected history entry
     selected_entry: ListState,
+    pub view: HistoryView,
+    /// The currently selected event of the timeline, counting from the newest
+    selected_event: ListState,
     pub local_book_options: StatefulList<String>,
     pub global_book_options: StatefulList<String>,
 }
@@ -20,8 +33,15 @@ impl HistoryData {
         } else {
             ListState::default().with_selected(Some(0))
         };
+        let selected_event = if ctx.get_timeline().is_empty() {
+            ListState::default()
+        } else {
+            ListState::default().with_selected(Some(0))
+        };
         Self {
             selected_entry,
+            view: HistoryView::default(),
+            selected_event,
             local_book_options: StatefulList::from(vec![
                 String::from("Continue reading"),
                 String::from("Remove from history"),
@@ -66,6 +86,11 @@ impl HistoryData {
         &mut self.selected_entry
     }
 
+    /// Returns the index of the selected history entry
+    pub fn get_selected_book_idx(&self) -> Option<usize> {
+        self.selected_entry.selected()
+    }
+
     /// Returns a reference to the entry that's selected
     pub fn get_selected_book<'a>(&self, ctx: &'a Context) -> Option<&'a HistoryEntry> {
         let hist = ctx.get_history();
@@ -110,4 +135,54 @@ impl HistoryData {
             }
         }
     }
+
+    /// Swap between listing the latest chapter of each book and the timeline
+    pub fn toggle_view(&mut self) {
+        self.view = match self.view {
+            HistoryView::Latest => HistoryView::Timeline,
+            HistoryView::Timeline => HistoryView::Latest,
+        };
+    }
+
+    /// Returns the index of the selected event of the timeline, counting from the newest
+    pub fn get_selected_event_idx(&self) -> Option<usize> {
+        self.selected_event.selected()
+    }
+
+    /// Returns a reference to the event of the timeline that's selected
+    pub fn get_selected_event<'a>(&self, ctx: &'a Context) -> Option<&'a HistoryEvent> {
+        let timeline = ctx.get_timeline();
+        let idx = self.selected_event.selected()?;
+        timeline.iter().rev().nth(idx)
+    }
+
+    /// Selects the history entry of a book, returning false if it isn't in the history
+    pub fn select_book_entry(&mut self, ctx: &Context, event: &HistoryEvent) -> bool {
+        let id = event.get_book_id();
+        let Some(pos) = ctx.get_history().iter().position(|e| e.get_book_id() == id) else {
+            return false;
+        };
+        self.selected_entry.select(Some(pos));
+        true
+    }
+
+    pub fn select_next_event(&mut self, ctx: &Context) {
+        let len = ctx.get_timeline().len();
+        let next = match self.selected_event.selected() {
+            _ if len == 0 => None,
+            Some(s) => Some((s + 1) % len),
+            None => Some(0),
+        };
+        self.selected_event.select(next);
+    }
+
+    pub fn select_prev_event(&mut self, ctx: &Context) {
+        let len = ctx.get_timeline().len();
+        let prev = match self.selected_event.selected() {
+            _ if len == 0 => None,
+            Some(0) | None => Some(len - 1),
+            Some(s) => Some(s - 1),
+        };
+        self.selected_event.select(prev);
+    }
 }
@@ -1,7 +1,12 @@
 // This module contains data related to the library tab of the TUI.
 
 use ratatui::widgets::ListState;
-use termreader_core::{book::BookRef, Context};
+use termreader_core::{
+    book::{BookRef, ReadingStatus},
+    id::ID,
+    sort::BookFilter,
+    Context,
+};
 
 use crate::helpers::StatefulList;
 
@@ -15,6 +20,10 @@ pub struct LibData {
     pub global_selected_book_opts: StatefulList<String>,
     /// Options for categories
     pub category_options: StatefulList<String>,
+    /// The currently selected book in the trash
+    trash_selection: ListState,
+    /// Only books passing this filter are shown
+    pub filter: BookFilter,
 }
 
 impl LibData {
@@ -43,13 +52,23 @@ impl LibData {
                 String::from("Reset Progress"),
                 String::from("Remove book from library"),
                 String::from("Open in browser"),
+                String::from("Copy to profile"),
+                String::from("Edit tags"),
+                String::from("Set reading status"),
+                String::from("Bookmarks"),
+                String::from("Highlights"),
+                String::from("Export annotations"),
             ]),
             category_options: StatefulList::from(vec![
                 String::from("Create categories"),
                 String::from("Re-order categories"),
                 String::from("Rename categories"),
                 String::from("Delete categories"),
+                String::from("Create smart category"),
+                String::from("Delete smart categories"),
             ]),
+            trash_selection: ListState::default(),
+            filter: BookFilter::default(),
         }
     }
 
@@ -58,9 +77,33 @@ impl LibData {
         return self.current_category_idx;
     }
 
+    /// Returns the names of the categories shown, which are the library's categories followed by its smart categories
+    pub fn get_category_names(ctx: &Context) -> Vec<String> {
+        let smart = ctx.get_smart_categories().iter();
+        let mut names = ctx.get_library_categories().clone();
+        names.extend(smart.map(|c| c.get_name().to_string()));
+        names
+    }
+
+    /// Returns the name of the selected category
+    pub fn get_selected_category_name(&self, ctx: &Context) -> String {
+        Self::get_category_names(ctx).swap_remove(self.current_category_idx)
+    }
+
+    /// Returns true if the selected category is a smart category
+    pub fn in_smart_category(&self, ctx: &Context) -> bool {
+        self.current_category_idx >= ctx.get_library_categories().len()
+    }
+
+    /// Selects the last category if the selected category no longer exists
+    pub fn fix_category_selection(&mut self, ctx: &Context) {
+        let max_idx = Self::get_category_names(ctx).len() - 1;
+        self.current_category_idx = self.current_category_idx.min(max_idx);
+    }
+
     /// Selects the next category, wrapping around as required
     pub fn select_next_category(&mut self, ctx: &Context) {
-        let list_len = ctx.get_library_categories().len();
+        let list_len = Self::get_category_names(ctx).len();
         self.current_category_idx = (self.current_category_idx + 1) % list_len;
 
         self.selected_book.select(None);
@@ -69,7 +112,7 @@ impl LibData {
 
     /// Selects the previous category, wrapping around as required
     pub fn select_previous_category(&mut self, ctx: &Context) {
-        let max_idx = ctx.get_library_categories().len() - 1;
+        let max_idx = Self::get_category_names(ctx).len() - 1;
         if self.current_category_idx == 0 {
             self.current_category_idx = max_idx;
         } else {
@@ -92,10 +135,43 @@ impl LibData {
         self.fix_book_selection_state(ctx);
     }
 
-    /// Returns the size of the currently selected category
+    /// Returns the books shown in the currently selected category, which are those passing the filter, in the
+    /// category's sort order
+    pub fn get_current_books(&self, ctx: &Context) -> Vec<BookRef> {
+        let name = self.get_selected_category_name(ctx);
+        ctx.get_category_books(&name, &self.filter)
+            .unwrap_or_default()
+    }
+
+    /// Returns the amount of books shown in the currently selected category
     pub fn get_current_category_size(&mut self, ctx: &Context) -> usize {
-        let cat_name = &ctx.get_library_categories()[self.get_selected_category()];
-        ctx.get_library_books().get(cat_name).unwrap().len()
+        self.get_current_books(ctx).len()
+    }
+
+    /// Adds the tag to the filter if it isn't in it, otherwise removes it
+    pub fn toggle_tag_filter(&mut self, ctx: &Context, tag: String) {
+        if let Some(pos) = self.filter.tags.iter().position(|t| t == &tag) {
+            self.filter.tags.remove(pos);
+        } else {
+            self.filter.tags.push(tag);
+        }
+        self.reset_selection(ctx);
+    }
+
+    /// Only shows books with the status, or stops filtering by status if it's already being filtered by
+    pub fn toggle_status_filter(&mut self, ctx: &Context, status: ReadingStatus) {
+        if self.filter.status == Some(status) {
+            self.filter.status = None;
+        } else {
+            self.filter.status = Some(status);
+        }
+        self.reset_selection(ctx);
+    }
+
+    /// Sets the text filter, selecting the first book shown
+    pub fn set_text_filter(&mut self, ctx: &Context, filter: String) {
+        self.filter.text = filter;
+        self.reset_selection(ctx);
     }
 
     /// Returns a mutable reference to the state representing the selected book. This will always succeed
@@ -106,11 +182,12 @@ impl LibData {
     /// Gets the currently selected book
     pub fn get_selected_book(&self, ctx: &Context) -> Option<BookRef> {
         let idx = self.selected_book.selected()?;
-        let category_name = ctx
-            .get_library_categories()
-            .get(self.get_selected_category())?;
+        self.get_current_books(ctx).get(idx).cloned()
+    }
 
-        Some(ctx.get_library_books().get(category_name)?[idx].clone())
+    /// Selects the book at the index in the currently selected category
+    pub fn select_book(&mut self, idx: usize) {
+        self.selected_book.select(Some(idx))
     }
 
     /// Selects the next book in the currently selected category. If no book is selected, the first book is selected
@@ -151,4 +228,49 @@ impl LibData {
             }
         }
     }
+
+    /// Returns a mutable reference to the state representing the selected book in the trash
+    pub fn get_trash_selection_mut(&mut self) -> &mut ListState {
+        &mut self.trash_selection
+    }
+
+    /// Gets the ID of the currently selected book in the trash
+    pub fn get_selected_trash_entry(&self, ctx: &Context) -> Option<ID> {
+        let idx = self.trash_selection.selected()?;
+        Some(ctx.get_trash().get(idx)?.get_book_id())
+    }
+
+    /// Selects the first book in the trash if there is one, or nothing if the trash is empty.
+    /// Called whenever the trash changes, as the selection may no longer be valid
+    pub fn reset_trash_selection(&mut self, ctx: &Context) {
+        let len = ctx.get_trash().len();
+        match self.trash_selection.selected() {
+            _ if len == 0 => self.trash_selection.select(None),
+            Some(n) if n < len => (),
+            _ => self.trash_selection.select(Some(0)),
+        }
+    }
+
+    /// Selects the next book in the trash, wrapping around as required
+    pub fn select_next_trash_entry(&mut self, ctx: &Context) {
+        let len = ctx.get_trash().len();
+        if len == 0 {
+            return;
+        }
+        let next = self.trash_selection.selected().map_or(0, |n| (n + 1) % len);
+        self.trash_selection.select(Some(next));
+    }
+
+    /// Selects the previous book in the trash, wrapping around as required
+    pub fn select_prev_trash_entry(&mut self, ctx: &Context) {
+        let len = ctx.get_trash().len();
+        if len == 0 {
+            return;
+        }
+        let prev = match self.trash_selection.selected() {
+            Some(n) if n > 0 => n - 1,
+            _ => len - 1,
+        };
+        self.trash_selection.select(Some(prev));
+    }
 }
@@ -1,7 +1,10 @@
 use crate::helpers::StatefulList;
-use crate::state::reader::ReaderData;
+use crate::state::reader::{FinishedSession, ReaderData};
 use crate::state::updates::UpdatesData;
+use std::time::Instant;
 use termreader_core::book::BookRef;
+use termreader_core::profile::DEFAULT_PROFILE;
+use termreader_core::timeline::HistoryEventKind;
 use termreader_core::Context;
 use termreader_sources::chapter::Chapter;
 
@@ -10,6 +13,7 @@ use self::channels::ChannelData;
 use self::config::ConfigData;
 use self::history::HistoryData;
 use self::library::LibData;
+use self::settings::SettingsData;
 use self::sources::SourceData;
 
 pub mod buffer;
@@ -18,6 +22,7 @@ pub mod config;
 pub mod history;
 pub mod library;
 pub mod reader;
+pub mod settings;
 pub mod sources;
 pub mod updates;
 
@@ -43,6 +48,8 @@ pub struct AppState {
     pub history_data: HistoryData,
     /// Data related to the updates tab
     pub updates_data: UpdatesData,
+    /// Data related to the settings tab
+    pub settings_data: SettingsData,
     /// Data from the reader
     pub reader_data: ReaderData,
     /// Any config data
@@ -53,6 +60,14 @@ pub struct AppState {
     pub command_bar: bool,
     /// A boolean representing whether the user is typing or not
     pub typing: bool,
+    /// Set when something has happened that should be saved as soon as possible
+    pub save_requested: bool,
+    /// The last time that data was saved
+    pub last_save: Instant,
+    /// The name of the profile whose data is loaded
+    pub profile: String,
+    /// A message about the last thing that happened, shown until the next key press
+    pub status_message: Option<String>,
 }
 
 impl AppState {
@@ -74,11 +89,16 @@ impl AppState {
             source_data: SourceData::build(),
             history_data: HistoryData::build(ctx),
             updates_data: UpdatesData::build(ctx),
+            settings_data: SettingsData::build(),
             reader_data: ReaderData::build(),
             config: ConfigData::load(&ctx.get_save_dir()).unwrap_or_default(),
             buffer: Buffer::build(),
             command_bar: false,
             typing: false,
+            save_requested: false,
+            last_save: Instant::now(),
+            profile: String::from(DEFAULT_PROFILE),
+            status_message: None,
         }
     }
 
@@ -117,8 +137,31 @@ impl AppState {
         .contains(&self.screen)
     }
 
-    /// Moves the user into the reader
-    pub fn move_to_reader(&mut self, book: BookRef, chapter: Option<Chapter>) {
+    /// Rebuilds all data that refers to the contents of `ctx`, for when it has been replaced (e.g. by restoring a backup)
+    pub fn reload_data(&mut self, ctx: &Context) {
+        self.lib_data = LibData::build(ctx);
+        self.history_data = HistoryData::build(ctx);
+        self.updates_data = UpdatesData::build(ctx);
+        self.buffer.clear();
+    }
+
+    /// Fixes the library and history selections, for when books or categories may have been added or removed anywhere
+    pub fn fix_selections(&mut self, ctx: &Context) {
+        let category = self.lib_data.get_selected_category();
+        let filter = std::mem::take(&mut self.lib_data.filter);
+        self.lib_data = LibData::build(ctx);
+        self.lib_data.current_category_idx = category;
+        self.lib_data.fix_category_selection(ctx);
+        self.lib_data.filter = filter;
+        self.lib_data.reset_selection(ctx);
+        self.history_data = HistoryData::build(ctx);
+    }
+
+    /// Moves the user into the reader, adding the chapter opened to the timeline
+    pub fn move_to_reader(&mut self, ctx: &mut Context, book: BookRef, chapter: Option<Chapter>) {
+        if let Err(e) = ctx.log_history_event(book.get_id(), HistoryEventKind::Opened, 0) {
+            tracing::error!("failed to add the chapter opened to the timeline: {e}");
+        }
         self.reader_data.set_data(book, chapter);
         self.prev_screens = Vec::new();
         self.screen = Screen::Reader;
@@ -132,6 +175,10 @@ impl AppState {
         // let mut b = self.reader_data.get_book_mut().as_mut().unwrap().clone();
         let mut b = self.reader_data.get_book().unwrap();
 
+        if let Some(session) = self.reader_data.finish_session() {
+            self.record_reading_session(ctx, session);
+        }
+
         // Set the chapter progress
         if !b.is_local() {
             let current_ch = b.get_current_ch().unwrap();
@@ -146,6 +193,13 @@ impl AppState {
             todo!()
         }
 
+        // The chapter is only added to the timeline as finished the first time its end is reached
+        if let Some(secs) = self.reader_data.take_chapter_finished() {
+            if let Err(e) = ctx.log_history_event(b.get_id(), HistoryEventKind::Finished, secs) {
+                tracing::error!("failed to add the chapter finished to the timeline: {e}");
+            }
+        }
+
         // NOTE: In theory this isn't needed with BookRef
         // // If the book is in library then update the copy in the lib
         // // FIXME: Check that this isn't just an empty copy
@@ -158,16 +212,40 @@ impl AppState {
 
         // NOTE: In theory this also isn't needed with BookRef
 
-        // Remove any entries with the same ID
-        // FIXME: This should probably be done on another field as temporary
-        // books are assigned an ID that will end up being unique.
-        // Maybe do it on the full novel link (hash works for local books)
+        // Remove any entries with the same ID. Every copy of a book has the same ID,
+        // so this also catches the book having been opened from somewhere else
         ctx.remove_history_entry(b.get_id());
 
         ctx.add_history_entry(b.get_id());
         // We added to history so we can select the first entry
         // (it doesn't matter if there's already one selected or not)
         self.history_data.get_selected_entry_mut().select(Some(0));
+
+        if self.config.autosave_on_chapter_change {
+            self.save_requested = true;
+        }
+    }
+
+    /// Records a session of reading the book in the reader
+    pub fn record_reading_session(&self, ctx: &mut Context, session: FinishedSession) {
+        let Some(book) = self.reader_data.get_book() else {
+            return;
+        };
+        if let Err(e) = ctx.record_reading_session(
+            book.get_id(),
+            session.chapter,
+            session.start,
+            session.end,
+            session.words,
+        ) {
+            tracing::error!("failed to record reading session: {e}");
+        }
+    }
+
+    /// Returns true if an autosave is due, either because one was requested, or because the autosave interval has passed
+    pub fn autosave_due(&self) -> bool {
+        let interval = self.config.autosave_interval_secs;
+        self.save_requested || (interval != 0 && self.last_save.elapsed().as_secs() >= interval)
     }
 }
 
@@ -187,10 +265,28 @@ pub enum LibScreen {
     Main,
     BookView,
     BookViewCategory,
+    /// A screen where a profile to copy the selected book to is picked
+    BookViewProfile,
     /// A screen where we are seeing a list of categories
     CategorySelect,
     /// A screen where we are seeing options for categories (creation, deletion, etc.)
     CategoryOptions,
+    /// A screen where we are seeing the books removed from the library
+    Trash,
+    /// A screen where the tags of the selected book are edited
+    BookViewTags,
+    /// A screen where the tags and reading status to filter the library by are picked
+    Filter,
+    /// A screen where the reading status of the selected book is picked
+    BookViewStatus,
+    /// A screen where the bookmarks of the selected book are listed
+    BookViewBookmarks,
+    /// A screen where the highlights of the selected book are listed
+    BookViewHighlights,
+    /// A screen where the order of the books in the selected category is picked
+    Sort,
+    /// A screen where the selected book is moved within its category
+    Reorder,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -216,4 +312,8 @@ pub enum HistoryScreen {
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum SettingsScreen {
     Main,
+    /// A screen where we are picking a backup to restore
+    BackupSelect,
+    /// A screen where statistics about the user's reading are shown
+    Stats,
 }
@@ -1,14 +1,68 @@
 // This module contains data relating to when the user is reading a book
+use std::time::{SystemTime, UNIX_EPOCH};
 use termreader_core::book::{BookRef, ChapterProgress};
 use termreader_sources::chapter::Chapter;
 
 use crate::reader::{GlobalReader, GlobalReaderContents, GlobalReaderState};
 
+/// A span of words being selected in the reader, to be highlighted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Selection {
+    /// The word the selection was started from, once it's been marked
+    pub start: Option<usize>,
+    /// The word the cursor is on
+    pub cursor: usize,
+}
+
+impl Selection {
+    /// Returns the first and last words selected, in order
+    pub fn get_span(&self) -> (usize, usize) {
+        let start = self.start.unwrap_or(self.cursor);
+        (start.min(self.cursor), start.max(self.cursor))
+    }
+}
+
+/// How long the reader can go without a key press before the time is no longer counted as reading, in seconds
+const IDLE_SECS: u64 = 5 * 60;
+
+/// Time spent reading the current chapter, which is recorded as a session once it's over
+#[derive(Clone, Copy, Debug)]
+struct SessionTracker {
+    start: u64,
+    last_active: u64,
+    /// The first word displayed when the session started
+    start_word: usize,
+    /// The furthest first word displayed during the session
+    furthest_word: usize,
+}
+
+/// A session of reading a chapter that's over, to be recorded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FinishedSession {
+    pub chapter: usize,
+    pub start: u64,
+    pub end: u64,
+    pub words: usize,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time has gone VERY backwards")
+        .as_secs()
+}
+
 /// Data related to what's being read
 pub struct ReaderData {
     book: Option<BookRef>,
     chapter: Option<Chapter>,
     data: Option<GlobalReader>,
+    selection: Option<Selection>,
+    session: Option<SessionTracker>,
+    /// How long the chapter has been read for since it was opened, in seconds
+    chapter_secs: u64,
+    /// Whether the end of the chapter has been reached since it was opened
+    chapter_finished: bool,
     // Set by the renderer as required
     term_height: u16,
     term_width: u16,
@@ -20,6 +74,10 @@ impl ReaderData {
             book: None,
             chapter: None,
             data: None,
+            selection: None,
+            session: None,
+            chapter_secs: 0,
+            chapter_finished: false,
             term_height: 0,
             term_width: 0,
         }
@@ -32,6 +90,76 @@ impl ReaderData {
         self.book = Some(book);
         self.data = Some(GlobalReader::from_chapter(chapter.as_ref().unwrap()));
         self.chapter = chapter;
+        self.selection = None;
+        self.chapter_secs = 0;
+        self.chapter_finished = false;
+        self.start_session();
+    }
+
+    /// Returns how long the chapter was read for if its end has been reached for the first time since it was opened
+    pub fn take_chapter_finished(&mut self) -> Option<u64> {
+        if self.chapter_finished || self.get_ch_progress()? != ChapterProgress::Finished {
+            return None;
+        }
+        self.chapter_finished = true;
+        Some(self.chapter_secs)
+    }
+
+    /// Start timing a session of reading, from the first word being displayed
+    fn start_session(&mut self) {
+        let Some(reader) = &self.data else {
+            return;
+        };
+        let word = reader.state.start_word_idx;
+        let now = now();
+        self.session = Some(SessionTracker {
+            start: now,
+            last_active: now,
+            start_word: word,
+            furthest_word: word,
+        });
+    }
+
+    /// Notes that the user is still reading. If they've been away for long enough, the session they were in is
+    /// returned, and a new one is started
+    pub fn note_activity(&mut self) -> Option<FinishedSession> {
+        let now = now();
+        let idle = self
+            .session
+            .is_some_and(|s| now.saturating_sub(s.last_active) > IDLE_SECS);
+        if idle {
+            return self.finish_session();
+        }
+        let word = self.data.as_ref()?.state.start_word_idx;
+        let session = self.session.as_mut()?;
+        session.last_active = now;
+        session.furthest_word = session.furthest_word.max(word);
+        None
+    }
+
+    /// Ends the current session, returning it, and starts a new one from where the reader is.
+    /// Time after the last key press isn't counted if the user has been away for long enough
+    pub fn finish_session(&mut self) -> Option<FinishedSession> {
+        let (reader, session, book) = (self.data.as_ref()?, self.session?, self.book.as_ref()?);
+        let now = now();
+        let end = if now.saturating_sub(session.last_active) > IDLE_SECS {
+            session.last_active
+        } else {
+            now
+        };
+        let word = match reader.get_progress() {
+            ChapterProgress::Finished => reader.get_total_words(),
+            _ => reader.state.start_word_idx,
+        };
+        let finished = FinishedSession {
+            chapter: book.get_current_ch()?,
+            start: session.start,
+            end,
+            words: session.furthest_word.max(word) - session.start_word,
+        };
+        self.chapter_secs += finished.end - finished.start;
+        self.start_session();
+        Some(finished)
     }
 
     pub fn get_reader_state_mut(&mut self) -> Option<&mut GlobalReaderState> {
@@ -50,8 +178,118 @@ impl ReaderData {
         }
     }
 
+    /// Scroll so that the text starts at a word
+    pub fn jump_to_word(&mut self, word_idx: usize) {
+        if let Some(ref mut reader) = self.data {
+            reader.jump_to_word(word_idx);
+        }
+        // Jumping isn't reading, so the session starts from the word jumped to
+        if let Some(session) = &mut self.session {
+            session.start_word = word_idx;
+            session.furthest_word = word_idx;
+        }
+    }
+
+    /// Returns the index of the first word being displayed, and a few of the words from it
+    pub fn get_anchor(&self) -> Option<(usize, String)> {
+        let reader = self.data.as_ref()?;
+        Some((reader.state.start_word_idx, reader.get_snippet(6)))
+    }
+
+    /// Start selecting words to highlight, from the first word being displayed
+    pub fn start_selection(&mut self) {
+        let Some(reader) = &self.data else {
+            return;
+        };
+        if let Some(cursor) = reader.find_word(reader.state.start_word_idx, false) {
+            self.selection = Some(Selection {
+                start: None,
+                cursor,
+            });
+        }
+    }
+
+    pub fn get_selection(&self) -> Option<Selection> {
+        self.selection
+    }
+
+    /// Marks the start of the selection at the cursor, returning true if it was already marked
+    pub fn mark_selection(&mut self) -> bool {
+        let Some(selection) = &mut self.selection else {
+            return false;
+        };
+        if selection.start.is_some() {
+            return true;
+        }
+        selection.start = Some(selection.cursor);
+        false
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection = None;
+    }
+
+    /// Moves the selection's cursor to the next or previous word, scrolling to keep it displayed
+    pub fn move_selection(&mut self, back: bool) {
+        let (Some(reader), Some(selection)) = (&mut self.data, &mut self.selection) else {
+            return;
+        };
+        let from = if back {
+            selection.cursor.checked_sub(1)
+        } else {
+            Some(selection.cursor + 1)
+        };
+        if let Some(cursor) = from.and_then(|i| reader.find_word(i, back)) {
+            selection.cursor = cursor;
+            reader.scroll_to_word(cursor, self.term_width, self.term_height);
+        }
+    }
+
+    /// Scrolls the text, keeping the selection's cursor on a word being displayed
+    pub fn scroll_selection(&mut self, up: bool) {
+        if up {
+            self.scroll_up();
+        } else {
+            self.scroll_down();
+        }
+        let (Some(reader), Some(selection)) = (&mut self.data, &mut self.selection) else {
+            return;
+        };
+        reader
+            .contents
+            .get_display_words(&mut reader.state, self.term_width, self.term_height);
+        let (start, end) = (reader.state.start_word_idx, reader.state.end_word_idx);
+        let cursor = if selection.cursor < start {
+            reader.find_word(start, false)
+        } else if selection.cursor >= end {
+            reader.find_word(end.saturating_sub(1), true)
+        } else {
+            None
+        };
+        if let Some(cursor) = cursor {
+            selection.cursor = cursor;
+        }
+    }
+
+    /// Returns the first and last words selected, and the words between them
+    pub fn get_selected_text(&self) -> Option<((usize, usize), String)> {
+        let span = self.selection?.get_span();
+        Some((span, self.data.as_ref()?.get_text(span.0, span.1)))
+    }
+
+    /// Returns the contents of the reader, along with the highlights in the chapter and the selection
     pub fn get_reader_contents(&self) -> Option<GlobalReaderContents> {
-        let d = self.data.as_ref()?.contents.clone();
+        let mut d = self.data.as_ref()?.contents.clone();
+        if let Some(book) = &self.book {
+            let chapter = book.get_current_ch();
+            d.highlights = book
+                .get_highlights()
+                .iter()
+                .filter(|h| Some(h.get_chapter()) == chapter)
+                .map(|h| h.get_span())
+                .collect();
+        }
+        d.selection = self.selection.map(|s| s.get_span());
         Some(d)
     }
 
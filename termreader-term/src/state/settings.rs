@@ -0,0 +1,46 @@
+// This module contains data related to the settings tab of the TUI.
+
+use std::path::PathBuf;
+
+use termreader_core::{backup::ImportMode, stats::ReadingStats};
+
+use crate::helpers::StatefulList;
+
+/// Data related to the settings tab
+pub struct SettingsData {
+    /// The options on the main settings screen
+    pub options: StatefulList<String>,
+    /// The backups that can be restored, newest first
+    pub backups: Vec<PathBuf>,
+    /// How the selected backup will be restored
+    pub import_mode: ImportMode,
+    /// The result of the last action taken, to be shown to the user
+    pub message: Option<String>,
+    /// The reading statistics being shown, worked out when the stats screen is entered
+    pub stats: Option<ReadingStats>,
+}
+
+impl SettingsData {
+    /// Creates an instance of SettingsData
+    pub(super) fn build() -> Self {
+        Self {
+            options: StatefulList::from(vec![
+                String::from("Create backup"),
+                String::from("Restore backup (replace current data)"),
+                String::from("Restore backup (merge with current data)"),
+                String::from("Import LNReader backup"),
+                String::from("Export library (.csv, .json or .opml)"),
+                String::from("Import library (.csv, .json or .opml)"),
+                String::from("Sync with other devices now"),
+                String::from("Set sync folder (leave blank to disable syncing)"),
+                String::from("Reload data from disk"),
+                String::from("Reading statistics"),
+                String::from("Export reading sessions (.csv)"),
+            ]),
+            backups: Vec::new(),
+            import_mode: ImportMode::Replace,
+            message: None,
+            stats: None,
+        }
+    }
+}
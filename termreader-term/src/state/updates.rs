@@ -4,6 +4,8 @@ use termreader_core::Context;
 pub struct UpdatesData {
     /// The currently selected updates entry
     selected_entry: ListState,
+    /// The amount of books still being updated in the background
+    pub updating: usize,
 }
 
 impl UpdatesData {
@@ -14,7 +16,10 @@ impl UpdatesData {
             ListState::default().with_selected(Some(0))
         };
 
-        Self { selected_entry }
+        Self {
+            selected_entry,
+            updating: 0,
+        }
     }
 
     /// Returns a mutable reference to the state representing the selected history entry. This will always succeed. This function should **not** be used directly
@@ -22,6 +27,13 @@ impl UpdatesData {
         &mut self.selected_entry
     }
 
+    /// Selects the first entry if none is selected, e.g. after updates have been added
+    pub fn fix_entry_selection(&mut self, ctx: &Context) {
+        if self.selected_entry.selected().is_none() && ctx.get_updates_entry_count() != 0 {
+            self.selected_entry.select(Some(0));
+        }
+    }
+
     pub fn select_next_entry(&mut self, ctx: &Context) {
         if ctx.get_updates_entry_count() == 0 {
             self.selected_entry.select(None);
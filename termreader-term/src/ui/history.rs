@@ -1,9 +1,11 @@
-use crate::helpers::to_datetime;
+use crate::helpers::to_local_time;
+use crate::state::history::HistoryView;
 use crate::state::HistoryScreen;
 use crate::state::Screen;
 use crate::AppState;
 use crate::Context;
 use ratatui::{prelude::*, widgets::*};
+use termreader_core::timeline::{group_by_day, HistoryEventKind};
 
 use super::sources::render_book_view;
 use super::sources::BookViewOption;
@@ -40,45 +42,122 @@ pub(super) fn render_history(rect: Rect, ctx: &Context, app_state: &mut AppState
     }
 
     if render_books {
-        let mut display_data: Vec<ListItem> = {
-            ctx.get_history()
-                .clone()
-                .into_iter()
-                .map(|e| {
+        let style = app_state.config.unselected_style;
+        let history_data = &app_state.history_data;
+        let (rows, selected, title, empty) = match history_data.view {
+            HistoryView::Latest => {
+                let entries = ctx.get_history().iter().map(|e| {
                     let t = if e.get_chapter() == 0 {
-                        format!("{} | {}", e.get_book_name(), to_datetime(e.get_timestamp()))
+                        format!(
+                            "{} | {}",
+                            to_local_time(e.get_timestamp()),
+                            e.get_book_name()
+                        )
+                    } else {
+                        format!(
+                            "{} | {} | Chapter {}",
+                            to_local_time(e.get_timestamp()),
+                            e.get_book_name(),
+                            e.get_chapter()
+                        )
+                    };
+                    (e.get_timestamp(), t)
+                });
+                let (rows, selected) = rows_by_day(
+                    entries,
+                    history_data.get_selected_book_idx(),
+                    (app_state.config.greyed_style, style),
+                );
+                (
+                    rows,
+                    selected,
+                    "History (t: show timeline)",
+                    "You currently have no history.",
+                )
+            }
+            HistoryView::Timeline => {
+                let events = ctx.get_timeline().iter().rev().map(|e| {
+                    let action = match e.get_kind() {
+                        HistoryEventKind::Opened => String::from("Opened"),
+                        HistoryEventKind::Finished if e.get_duration() >= 60 => {
+                            format!("Finished in {}m", e.get_duration() / 60)
+                        }
+                        HistoryEventKind::Finished => String::from("Finished"),
+                    };
+                    let t = if e.get_chapter() == 0 {
+                        format!(
+                            "{} | {} | {}",
+                            to_local_time(e.get_timestamp()),
+                            e.get_book_name(),
+                            action
+                        )
                     } else {
                         format!(
-                            "{} | Chapter {} | {}",
+                            "{} | {} | {} chapter {}",
+                            to_local_time(e.get_timestamp()),
                             e.get_book_name(),
-                            e.get_chapter(),
-                            to_datetime(e.get_timestamp())
+                            action,
+                            e.get_chapter()
                         )
                     };
-                    ListItem::new(t).style(app_state.config.unselected_style)
-                })
-                .collect()
+                    (e.get_timestamp(), t)
+                });
+                let (rows, selected) = rows_by_day(
+                    events,
+                    history_data.get_selected_event_idx(),
+                    (app_state.config.greyed_style, style),
+                );
+                (
+                    rows,
+                    selected,
+                    "Timeline (t: show latest per book)",
+                    "Nothing has been read yet.",
+                )
+            }
         };
 
-        let entries_len = display_data.len();
-        if entries_len == 0 {
-            display_data.push(ListItem::new("You currently have no history."))
+        let mut display_data = rows;
+        if display_data.is_empty() {
+            display_data.push(ListItem::new(empty))
         }
 
         let history = List::new(display_data)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title("History")
+                    .title(title)
                     .border_type(BorderType::Rounded),
             )
             .highlight_style(app_state.config.selected_style)
             .highlight_symbol("> ");
 
-        f.render_stateful_widget(
-            history,
-            rect,
-            app_state.history_data.get_selected_entry_mut(),
-        );
+        // The rows include a heading for each day, so the selection is mapped to the row of the selected item
+        let mut state = ListState::default().with_selected(selected);
+        f.render_stateful_widget(history, rect, &mut state);
+    }
+}
+
+/// Lists items under a heading for each day they happened on, returning the rows and the row of the selected item.
+/// Items should be newest first
+fn rows_by_day<'a>(
+    items: impl Iterator<Item = (u64, String)>,
+    selected: Option<usize>,
+    styles: (Style, Style),
+) -> (Vec<ListItem<'a>>, Option<usize>) {
+    let (day_style, item_style) = styles;
+    let mut rows = Vec::new();
+    let mut selected_row = None;
+    let mut idx = 0;
+    for (day, items) in group_by_day(items, |(timestamp, _)| *timestamp) {
+        let heading = day.format("%A %-d %B %Y").to_string();
+        rows.push(ListItem::new(heading).style(day_style));
+        for (_, text) in items {
+            if selected == Some(idx) {
+                selected_row = Some(rows.len());
+            }
+            rows.push(ListItem::new(text).style(item_style));
+            idx += 1;
+        }
     }
+    (rows, selected_row)
 }
@@ -1,8 +1,10 @@
+use crate::state::library::LibData;
 use crate::state::LibScreen;
 use crate::state::Screen;
 use crate::AppState;
 use crate::Context;
 use ratatui::{prelude::*, widgets::*};
+use termreader_core::sort::BookSort;
 
 use super::render_selection_box;
 use super::render_selection_screen;
@@ -22,13 +24,22 @@ pub(super) fn render_lib(rect: Rect, ctx: &Context, app_state: &mut AppState, f:
     let mut render_books = false;
     let mut render_category_list = false;
     let mut render_category_options = false;
+    let mut render_trash = false;
+    let mut render_filter = false;
+    let mut render_sort = false;
 
     match libscreen {
         LibScreen::Main => {
             render_books = true;
             render_categories = true;
         }
-        LibScreen::BookView | LibScreen::BookViewCategory => {
+        LibScreen::BookView
+        | LibScreen::BookViewCategory
+        | LibScreen::BookViewProfile
+        | LibScreen::BookViewTags
+        | LibScreen::BookViewStatus
+        | LibScreen::BookViewBookmarks
+        | LibScreen::BookViewHighlights => {
             render_book_v = true;
         }
         LibScreen::CategorySelect => {
@@ -39,6 +50,24 @@ pub(super) fn render_lib(rect: Rect, ctx: &Context, app_state: &mut AppState, f:
             render_categories = true;
             render_category_options = true;
         }
+        LibScreen::Trash => {
+            render_categories = true;
+            render_trash = true;
+        }
+        LibScreen::Filter => {
+            render_books = true;
+            render_categories = true;
+            render_filter = true;
+        }
+        LibScreen::Reorder => {
+            render_books = true;
+            render_categories = true;
+        }
+        LibScreen::Sort => {
+            render_books = true;
+            render_categories = true;
+            render_sort = true;
+        }
     }
 
     // Split into two chunks, one for the categories, and one for the book lists
@@ -72,11 +101,19 @@ pub(super) fn render_lib(rect: Rect, ctx: &Context, app_state: &mut AppState, f:
 
     if render_categories {
         // Render categories
-        let categories: Vec<Line> = ctx
-            .get_library_categories()
-            .clone()
+        // Smart categories are in italics, as books can't be moved into them
+        let manual = ctx.get_library_categories().len();
+        let categories: Vec<Line> = LibData::get_category_names(ctx)
             .into_iter()
-            .map(|t| Line::from(t).alignment(Alignment::Center))
+            .enumerate()
+            .map(|(i, t)| {
+                let line = Line::from(t).alignment(Alignment::Center);
+                if i < manual {
+                    line
+                } else {
+                    line.italic()
+                }
+            })
             .collect();
 
         let tabs = Tabs::new(categories)
@@ -94,31 +131,53 @@ pub(super) fn render_lib(rect: Rect, ctx: &Context, app_state: &mut AppState, f:
     }
 
     if render_books {
-        let current_category =
-            &ctx.get_library_categories()[app_state.lib_data.get_selected_category()];
-
-        let mut display_data: Vec<ListItem> = ctx
-            .get_library_books()
-            .get(current_category)
-            .unwrap()
+        let mut display_data: Vec<ListItem> = app_state
+            .lib_data
+            .get_current_books(ctx)
             .iter()
             .map(|b| ListItem::new(b.get_display_info()).style(app_state.config.unselected_style))
             .collect();
 
         let book_len = display_data.len();
 
-        if book_len == 0 {
+        let lib_data = &app_state.lib_data;
+        if book_len == 0 && lib_data.filter.is_empty() {
             display_data.push(ListItem::new("There are no books in this category."))
+        } else if book_len == 0 {
+            display_data.push(ListItem::new(
+                "There are no books matching the filters in this category.",
+            ))
         }
 
+        let mut filters = Vec::new();
+        if let Some(status) = lib_data.filter.status {
+            filters.push(status.to_string().to_lowercase());
+        }
+        if !lib_data.filter.tags.is_empty() {
+            filters.push(format!("tagged {}", lib_data.filter.tags.join(", ")));
+        }
+        if !lib_data.filter.text.is_empty() {
+            filters.push(format!("matching \"{}\"", lib_data.filter.text));
+        }
+        let sort = ctx.get_category_sort(&lib_data.get_selected_category_name(ctx));
+        if sort != BookSort::Unsorted {
+            filters.push(format!("by {}", sort.to_string().to_lowercase()));
+        }
+        let title = if libscreen == LibScreen::Reorder {
+            String::from("Books (moving: Up/Down, T: to top, Enter: done)")
+        } else if filters.is_empty() {
+            String::from("Books")
+        } else {
+            format!("Books ({})", filters.join(", "))
+        };
         let books = List::new(display_data)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title("Books")
+                    .title(title)
                     .border_type(BorderType::Rounded),
             )
-            .highlight_style(app_state.config.selected_style)
+            .highlight_style(app_state.config.get_prompt_style())
             .highlight_symbol("> ");
 
         f.render_stateful_widget(
@@ -138,7 +197,73 @@ pub(super) fn render_lib(rect: Rect, ctx: &Context, app_state: &mut AppState, f:
         );
 
         if app_state.typing {
-            render_type_box(chunks[1], app_state, f, "Enter name:".into())
+            let title = match app_state.lib_data.category_options.selected_idx() {
+                Some(4) => "Name: conditions",
+                _ => "Enter name:",
+            };
+            render_type_box(chunks[1], app_state, f, title.into())
+        }
+    }
+
+    if render_filter {
+        render_selection_box(
+            &app_state.config,
+            chunks[1],
+            String::from("Filter by status and tags:"),
+            &mut app_state.buffer.temporary_list,
+            f,
+        );
+    }
+
+    if render_sort {
+        render_selection_box(
+            &app_state.config,
+            chunks[1],
+            String::from("Sort by:"),
+            &mut app_state.buffer.temporary_list,
+            f,
+        );
+    }
+
+    if render_trash {
+        let default_category = ctx.get_default_category_name();
+        let mut display_data: Vec<ListItem> = ctx
+            .get_trash()
+            .iter()
+            .map(|e| {
+                let expiry = match ctx.get_trash_days_left(e) {
+                    Some(days) => format!("deleted in {} days", days),
+                    None => String::from("kept until deleted"),
+                };
+                let category = e.get_category().unwrap_or(default_category);
+                ListItem::new(format!(
+                    "{} | {} | {}",
+                    e.get_book_ref().get_name(),
+                    category,
+                    expiry
+                ))
+                .style(app_state.config.unselected_style)
+            })
+            .collect();
+
+        if display_data.is_empty() {
+            display_data.push(ListItem::new("The trash is empty."))
         }
+
+        let trash = List::new(display_data)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Trash (Enter: restore, d: delete, D: delete all)")
+                    .border_type(BorderType::Rounded),
+            )
+            .highlight_style(app_state.config.selected_style)
+            .highlight_symbol("> ");
+
+        f.render_stateful_widget(
+            trash,
+            chunks[1],
+            app_state.lib_data.get_trash_selection_mut(),
+        );
     }
 }
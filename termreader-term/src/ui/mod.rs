@@ -2,6 +2,7 @@ pub mod helpers;
 pub mod history;
 pub mod library;
 pub mod reader;
+pub mod settings;
 pub mod sources;
 pub mod updates;
 
@@ -14,6 +15,7 @@ use crate::state::SourceScreen;
 use crate::ui::helpers::centered_sized_rect;
 use crate::ui::history::render_history;
 use crate::ui::library::render_lib;
+use crate::ui::settings::render_settings;
 use crate::ui::sources::render_sources;
 use crate::AppState;
 use crate::Context;
@@ -35,11 +37,16 @@ pub fn ui_main(f: &mut Frame, ctx: &mut Context, app_state: &mut AppState) {
     let in_book_view = app_state.screen == Screen::Sources(SourceScreen::BookView)
         || app_state.screen == Screen::Lib(LibScreen::BookView)
         || app_state.screen == Screen::Lib(LibScreen::BookViewCategory)
+        || app_state.screen == Screen::Lib(LibScreen::BookViewProfile)
+        || app_state.screen == Screen::Lib(LibScreen::BookViewTags)
+        || app_state.screen == Screen::Lib(LibScreen::BookViewStatus)
+        || app_state.screen == Screen::Lib(LibScreen::BookViewBookmarks)
+        || app_state.screen == Screen::Lib(LibScreen::BookViewHighlights)
         || app_state.screen == Screen::History(HistoryScreen::BookView);
 
     // Render the tabs
     if !in_book_view {
-        render_tabs(chunks[0], app_state, f);
+        render_tabs(chunks[0], ctx, app_state, f);
     }
 
     // Render the body of the content, depending on the selected tab
@@ -48,17 +55,21 @@ pub fn ui_main(f: &mut Frame, ctx: &mut Context, app_state: &mut AppState) {
         "Sources" => render_sources(chunks[1], ctx, app_state, f),
         "History" => render_history(chunks[1], ctx, app_state, f),
         "Updates" => render_updates(chunks[1], ctx, app_state, f),
-        // TODO: Render other tabs
-        // _ => unreachable!(),
-        _ => (),
+        "Settings" => render_settings(chunks[1], app_state, f),
+        _ => unreachable!(),
     }
 
     // Render command bar / controls
     let text = if app_state.command_bar {
         format!(":{}_", app_state.buffer.text)
+    } else if app_state.typing && app_state.screen == Screen::Lib(LibScreen::Main) {
+        // The library's text filter is typed in the command bar, so the books it matches can be seen
+        format!("Filter: {}_", app_state.buffer.text)
+    } else if let Some(message) = &app_state.status_message {
+        message.clone()
     } else {
         String::from(
-            "Quit: Esc/q | Scroll tabs: [/] | Scroll categories: {/} | Scroll entries: Up/Down",
+            "Quit: Esc/q | Scroll tabs: [/] | Scroll categories: {/} | Scroll entries: Up/Down | Undo/redo: u/Ctrl-r",
         )
     };
 
@@ -71,17 +82,20 @@ pub fn ui_main(f: &mut Frame, ctx: &mut Context, app_state: &mut AppState) {
 }
 
 /// Renders the different tabs
-fn render_tabs(rect: Rect, app_state: &AppState, f: &mut Frame) {
+fn render_tabs(rect: Rect, ctx: &Context, app_state: &AppState, f: &mut Frame) {
     let titles: Vec<Line> = Vec::from(app_state.menu_tabs.clone())
         .into_iter()
         .map(|t| Line::from(t).alignment(Alignment::Center))
         .collect();
 
+    // Make it clear when changes won't be saved
+    let title = if ctx.is_read_only() { "Read-only" } else { "" };
     let tabs = Tabs::new(titles)
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .border_type(BorderType::Rounded),
+                .border_type(BorderType::Rounded)
+                .title(title),
         )
         .style(app_state.config.unselected_style)
         .highlight_style(app_state.config.selected_style)
@@ -64,10 +64,22 @@ fn render_bottom(rect: Rect, app_state: &AppState, f: &mut Frame) {
         )
     };
 
-    let display = if !app_state.command_bar {
-        display
-    } else {
+    let display = if app_state.command_bar {
         format!(":{}_", app_state.buffer.text)
+    } else if app_state.typing && app_state.reader_data.get_selection().is_some() {
+        format!("Highlight comment: {}_", app_state.buffer.text)
+    } else if app_state.typing {
+        format!("Bookmark name | note: {}_", app_state.buffer.text)
+    } else if let Some(message) = &app_state.status_message {
+        message.clone()
+    } else if let Some(selection) = app_state.reader_data.get_selection() {
+        if selection.start.is_some() {
+            String::from("Move to the last word to highlight, then press Enter (Esc: cancel)")
+        } else {
+            String::from("Move to the first word to highlight, then press Enter (Esc: cancel)")
+        }
+    } else {
+        display
     };
 
     let text = Paragraph::new(display).block(
@@ -0,0 +1,154 @@
+use crate::state::Screen;
+use crate::state::SettingsScreen;
+use crate::AppState;
+use ratatui::{prelude::*, widgets::*};
+use termreader_core::stats::ReadingStats;
+
+use super::render_selection_box;
+use super::render_selection_screen;
+use super::render_type_box;
+
+/// Renders the settings tab
+pub(super) fn render_settings(rect: Rect, app_state: &mut AppState, f: &mut Frame) {
+    let Screen::Settings(settings_screen) = app_state.screen else {
+        // We'll never be rendering the settings screen if we aren't on it
+        unreachable!()
+    };
+
+    // Split into two chunks, one for the options, and one for the result of the last action
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(3)])
+        .split(rect);
+
+    render_selection_screen(
+        &app_state.config,
+        chunks[0],
+        String::from("Settings"),
+        &mut app_state.settings_data.options,
+        f,
+    );
+
+    let message = app_state.settings_data.message.clone().unwrap_or_default();
+    let message = Paragraph::new(message)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded),
+        )
+        .style(app_state.config.unselected_style);
+    f.render_widget(message, chunks[1]);
+
+    if settings_screen == SettingsScreen::Stats {
+        if let Some(stats) = &app_state.settings_data.stats {
+            render_stats(chunks[0], app_state, stats, f);
+        }
+    }
+
+    if settings_screen == SettingsScreen::BackupSelect {
+        render_selection_box(
+            &app_state.config,
+            chunks[0],
+            String::from("Pick backup:"),
+            &mut app_state.buffer.temporary_list,
+            f,
+        );
+    }
+
+    if app_state.typing {
+        let title = match app_state.settings_data.options.selected_idx() {
+            Some(7) => "Sync folder:",
+            _ => "Path:",
+        };
+        render_type_box(chunks[0], app_state, f, title.into());
+    }
+}
+
+/// How many of the latest days of reading are shown
+const STATS_DAYS: usize = 14;
+
+/// Formats a number of seconds as hours and minutes
+fn format_duration(secs: u64) -> String {
+    let mins = secs / 60;
+    if mins >= 60 {
+        format!("{}h {:02}m", mins / 60, mins % 60)
+    } else {
+        format!("{mins}m")
+    }
+}
+
+/// Renders reading statistics over the settings, with totals above the latest days and the books read
+fn render_stats(rect: Rect, app_state: &AppState, stats: &ReadingStats, f: &mut Frame) {
+    let style = app_state.config.unselected_style;
+    let block = |title: &str| {
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title.to_string())
+            .border_type(BorderType::Rounded)
+    };
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(6), Constraint::Min(1)])
+        .split(rect);
+    let lists = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[1]);
+
+    let speed = match stats.words_per_minute() {
+        Some(wpm) => format!("{wpm:.0} words per minute"),
+        None => String::from("unknown"),
+    };
+    let summary = vec![
+        Line::from(format!(
+            "Read {} words in {}",
+            stats.total_words,
+            format_duration(stats.total_secs)
+        )),
+        Line::from(format!("Average speed: {speed}")),
+        Line::from(format!(
+            "Current streak: {} days | Longest streak: {} days",
+            stats.current_streak, stats.longest_streak
+        )),
+    ];
+    let summary = Paragraph::new(summary)
+        .block(block("Reading statistics (Esc: back)"))
+        .style(style);
+
+    let days: Vec<ListItem> = stats
+        .days
+        .iter()
+        .rev()
+        .take(STATS_DAYS)
+        .map(|d| {
+            ListItem::new(format!(
+                "{} | {} words | {} chapters | {}",
+                d.day.format("%Y-%m-%d"),
+                d.words,
+                d.chapters,
+                format_duration(d.secs)
+            ))
+        })
+        .collect();
+    let days = List::new(days).block(block("Latest days")).style(style);
+
+    let books: Vec<ListItem> = stats
+        .books
+        .iter()
+        .map(|b| {
+            ListItem::new(format!(
+                "{} | {} | {} words",
+                b.name,
+                format_duration(b.secs),
+                b.words
+            ))
+        })
+        .collect();
+    let books = List::new(books).block(block("Time per book")).style(style);
+
+    f.render_widget(Clear, rect);
+    f.render_widget(summary, chunks[0]);
+    f.render_widget(days, lists[0]);
+    f.render_widget(books, lists[1]);
+}
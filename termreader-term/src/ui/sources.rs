@@ -180,9 +180,16 @@ pub fn render_book_view(
 
     if option_type != BookViewOption::None {
         if app_state.typing {
+            let title = if app_state.screen == Screen::Lib(LibScreen::BookViewTags) {
+                "New tag:"
+            } else if app_state.lib_data.global_selected_book_opts.selected_idx() == Some(12) {
+                "Export annotations to:"
+            } else {
+                "New Name (leave blank to reset):"
+            };
             let block = Block::default()
                 .borders(Borders::ALL)
-                .title("New Name (leave blank to reset):")
+                .title(title)
                 .border_type(BorderType::Rounded)
                 .style(app_state.config.selected_style); // Always the selected box if typing
 
@@ -200,6 +207,46 @@ pub fn render_book_view(
                 &mut app_state.buffer.temporary_list,
                 f,
             );
+        } else if matches!(app_state.screen, Screen::Lib(LibScreen::BookViewProfile)) {
+            render_selection_screen(
+                &app_state.config,
+                chunks_vert_2[0],
+                String::from("Copy to profile:"),
+                &mut app_state.buffer.temporary_list,
+                f,
+            );
+        } else if matches!(app_state.screen, Screen::Lib(LibScreen::BookViewBookmarks)) {
+            render_selection_screen(
+                &app_state.config,
+                chunks_vert_2[0],
+                String::from("Bookmarks (Enter: go to, d: delete):"),
+                &mut app_state.buffer.temporary_list,
+                f,
+            );
+        } else if matches!(app_state.screen, Screen::Lib(LibScreen::BookViewHighlights)) {
+            render_selection_screen(
+                &app_state.config,
+                chunks_vert_2[0],
+                String::from("Highlights (Enter: go to, d: delete):"),
+                &mut app_state.buffer.temporary_list,
+                f,
+            );
+        } else if matches!(app_state.screen, Screen::Lib(LibScreen::BookViewStatus)) {
+            render_selection_screen(
+                &app_state.config,
+                chunks_vert_2[0],
+                String::from("Reading status:"),
+                &mut app_state.buffer.temporary_list,
+                f,
+            );
+        } else if matches!(app_state.screen, Screen::Lib(LibScreen::BookViewTags)) {
+            render_selection_screen(
+                &app_state.config,
+                chunks_vert_2[0],
+                String::from("Tags (Enter removes a tag):"),
+                &mut app_state.buffer.temporary_list,
+                f,
+            );
         } else {
             let block = Block::default()
                 .borders(Borders::ALL)
@@ -22,11 +22,15 @@ pub(super) fn render_updates(rect: Rect, ctx: &Context, app_state: &mut AppState
         display_data.push(ListItem::new("There are currently no updates"))
     }
 
+    let title = match app_state.updates_data.updating {
+        0 => String::from("Updates"),
+        n => format!("Updates (updating {} books...)", n),
+    };
     let updates = List::new(display_data)
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title("Updates")
+                .title(title)
                 .border_type(BorderType::Rounded),
         )
         .highlight_style(app_state.config.selected_style)